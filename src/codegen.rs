@@ -1,19 +1,54 @@
 use inkwell::{
     context::Context,
     module::Module,
-    builder::Builder,
-    values::{BasicValueEnum, PointerValue},
-    types::{BasicType, BasicTypeEnum, BasicMetadataTypeEnum},
+    builder::{Builder, BuilderError},
+    basic_block::BasicBlock,
+    module::Linkage,
+    intrinsics::Intrinsic,
+    values::{BasicMetadataValueEnum, InstructionOpcode, BasicValue, BasicValueEnum, FunctionValue, IntValue, PointerValue, UnnamedAddress},
+    types::{BasicType, BasicTypeEnum, BasicMetadataTypeEnum, FunctionType, PointerType, StructType},
     targets::{TargetMachine, Target, InitializationConfig, RelocMode, CodeModel, FileType},
+    AddressSpace,
+    FloatPredicate,
+    IntPredicate,
 };
-use std::collections::HashMap;
-use crate::parser::AstNode;
+use std::collections::{HashMap, HashSet};
+use crate::consteval::{self, ConstValue};
+use crate::hir::runtime;
+use crate::lexer::Span;
+use crate::parser::{walk_node, AstNode, BinaryOperator, Spanned, UnaryOperator, Visitor};
+use crate::typecheck::BUILTINS;
+use crate::types::Type;
 
 pub struct CodeGen<'ctx> {
     context: &'ctx Context,
     module: Module<'ctx>,
     builder: Builder<'ctx>,
-    variables: HashMap<String, PointerValue<'ctx>>,
+    variables: HashMap<String, (PointerValue<'ctx>, BasicTypeEnum<'ctx>)>,
+    /// The signature of each variable that holds a function, for calls
+    /// through it.
+    signatures: HashMap<String, FunctionType<'ctx>>,
+    /// Module-level constants, visible from every function.
+    globals: HashMap<String, (PointerValue<'ctx>, BasicTypeEnum<'ctx>)>,
+    /// Folded values of the constants, for use in later constants.
+    constants: HashMap<String, ConstValue>,
+    /// Named LLVM struct types and their field names in declaration order.
+    structs: HashMap<String, (StructType<'ctx>, Vec<String>)>,
+    /// Enum variant names; a variant is lowered to its index as an i32 tag.
+    enums: HashMap<String, Vec<String>>,
+    /// `type` aliases, lowered as their target type.
+    aliases: HashMap<String, Type>,
+    /// Generic functions, which are not generated until codegen can
+    /// monomorphize them.
+    generics: HashSet<String>,
+    /// The global copy of each string generated so far, shared by every
+    /// use of the same text.
+    strings: HashMap<String, PointerValue<'ctx>>,
+    /// For each loop being generated, innermost last, the blocks that
+    /// `continue` and `break` jump to.
+    loops: Vec<(BasicBlock<'ctx>, BasicBlock<'ctx>)>,
+    /// Whether the error being propagated already names a source position.
+    error_located: bool,
 }
 
 impl<'ctx> CodeGen<'ctx> {
@@ -26,14 +61,51 @@ impl<'ctx> CodeGen<'ctx> {
             module,
             builder,
             variables: HashMap::new(),
+            signatures: HashMap::new(),
+            globals: HashMap::new(),
+            constants: HashMap::new(),
+            structs: HashMap::new(),
+            enums: HashMap::new(),
+            aliases: HashMap::new(),
+            generics: HashSet::new(),
+            strings: HashMap::new(),
+            loops: Vec::new(),
+            error_located: false,
         }
     }
 
     pub fn generate(&mut self, ast: &AstNode) -> Result<(), String> {
         match ast {
             AstNode::Program(nodes) => {
+                // types come first, so that any signature or field can name
+                // them, and every function is declared up front, so that one
+                // can call a function defined after it
                 for node in nodes {
-                    self.generate_expression(node)?;
+                    if let AstNode::StructDef { name, fields, .. } = &node.node {
+                        self.declare_struct(name, fields);
+                    }
+                }
+                let (types, items): (Vec<_>, Vec<_>) = nodes.iter().partition(|node| matches!(
+                    node.node,
+                    AstNode::StructDef { .. } | AstNode::EnumDef { .. } | AstNode::TypeAlias { .. }
+                ));
+                for node in &types {
+                    self.error_located = false;
+                    self.generate_expression(node).map_err(|e| self.locate(e, node.span))?;
+                }
+                for node in &items {
+                    if let AstNode::Function { name, type_params, params, return_type, .. } = &node.node {
+                        if type_params.is_empty() {
+                            self.error_located = false;
+                            self.declare_function(name, params, return_type).map_err(|e| self.locate(e, node.span))?;
+                        } else {
+                            self.generics.insert(name.clone());
+                        }
+                    }
+                }
+                for node in &items {
+                    self.error_located = false;
+                    self.generate_expression(node).map_err(|e| self.locate(e, node.span))?;
                 }
                 Ok(())
             },
@@ -43,11 +115,9 @@ impl<'ctx> CodeGen<'ctx> {
 
     fn generate_expression(&mut self, expr: &AstNode) -> Result<(), String> {
         match expr {
-            AstNode::Program(nodes) => {
-                // code for all nodes in the program/block
-                for node in nodes {
-                    self.generate_expression(node)?;
-                }
+            AstNode::Program(_) => {
+                // nested block
+                self.generate_block(expr)?;
                 Ok(())
             },
             AstNode::Number(n) => {
@@ -56,35 +126,53 @@ impl<'ctx> CodeGen<'ctx> {
                 Ok(())
             },
             AstNode::Let { name, value, .. } => {
-                let val = self.generate_value(value)?;
-                let alloca = self.builder.build_alloca(val.get_type(), name)
-                    .map_err(|e| format!("Failed to allocate: {:?}", e))?;
+                let val = self.generate_typed_value(value)?;
+                let alloca = self.entry_alloca(val.get_type(), name)?;
                 self.builder.build_store(alloca, val)
                     .map_err(|e| format!("Failed to store: {:?}", e))?;
-                self.variables.insert(name.clone(), alloca);
+                self.variables.insert(name.clone(), (alloca, val.get_type()));
+                self.bind_signature(name, value.ty().as_ref())?;
+                Ok(())
+            },
+            AstNode::Assign { name, value } => {
+                let val = self.generate_typed_value(value)?;
+                let (ptr, _) = self.lookup(name)
+                    .ok_or_else(|| format!("Undefined variable: {}", name))?;
+                self.builder.build_store(ptr, val)
+                    .map_err(|e| format!("Failed to store: {:?}", e))?;
                 Ok(())
             },
-            AstNode::Function { name, params: _, body } => {
-                let fn_type = self.context.i32_type().fn_type(&[], false);
-                let function = self.module.add_function(name, fn_type, None);
+            AstNode::Function { type_params, .. } if !type_params.is_empty() => Ok(()),
+            AstNode::Function { name, params, return_type, body, .. } => {
+                let function = self.declare_function(name, params, return_type)?;
                 
                 let basic_block = self.context.append_basic_block(function, "entry");
                 self.builder.position_at_end(basic_block);
-                
+
+                // a function only sees its own parameters and locals
+                let outer_variables = std::mem::take(&mut self.variables);
+                let outer_signatures = std::mem::take(&mut self.signatures);
+                let body_value = self.bind_params(function, params).and_then(|_| self.generate_block(body));
+                self.variables = outer_variables;
+                self.signatures = outer_signatures;
+
                 // fn body; program node returned by parse_block
-                match &**body {
-                    AstNode::Program(statements) => {
-                        for stmt in statements {
-                            self.generate_expression(stmt)?;
-                        }
-                    },
-                    _ => self.generate_expression(body)?,
+                if let Some(value) = body_value? {
+                    if !self.is_terminated() {
+                        self.builder.build_return(Some(&value))
+                            .map_err(|e| format!("Failed to build return: {:?}", e))?;
+                    }
                 }
 
-                // Only add default return if no explicit return was given
-                if !self.builder.get_insert_block().unwrap().get_terminator().is_some() {
-                    let default_return = self.context.i32_type().const_int(0, false);
-                    self.builder.build_return(Some(&default_return))
+                // Only add default return if no explicit return was given:
+                // the zero value of the declared return type. The checker
+                // leaves this reachable only after a `while true` loop.
+                if !self.is_terminated() {
+                    let default_return = match return_type {
+                        Type::Void => None,
+                        _ => Some(self.llvm_type(return_type)?.const_zero()),
+                    };
+                    self.builder.build_return(default_return.as_ref().map(|value| value as &dyn BasicValue))
                         .map_err(|e| format!("Failed to build default return: {:?}", e))?;
                 }
 
@@ -95,32 +183,1130 @@ impl<'ctx> CodeGen<'ctx> {
                 }
             },
             AstNode::Return(expr) => {
-                let return_value = self.generate_value(expr)?;
-                self.builder.build_return(Some(&return_value))
+                let return_value = match expr {
+                    Some(expr) => Some(self.generate_typed_value(expr)?),
+                    None => None,
+                };
+                self.builder.build_return(return_value.as_ref().map(|value| value as &dyn BasicValue))
                     .map_err(|e| format!("Failed to build return: {:?}", e))?;
                 Ok(())
             },
+            AstNode::If { cond, then_block, else_block } => {
+                self.generate_if(cond, then_block, else_block.as_deref())?;
+                Ok(())
+            },
+            AstNode::ExpressionStatement(expr) => {
+                match &expr.node {
+                    // a call to a void function has no value to generate
+                    AstNode::Call { callee, args } => self.generate_call(callee, args).map(|_| ()),
+                    _ => self.generate_value(expr).map(|_| ()),
+                }
+            },
+            AstNode::StructDef { name, fields, .. } => {
+                let struct_type = self.declare_struct(name, fields);
+                let field_types = fields.iter()
+                    .map(|(_, t)| self.llvm_type(t))
+                    .collect::<Result<Vec<_>, _>>()?;
+                struct_type.set_body(&field_types, false);
+                Ok(())
+            },
+            AstNode::Const { name, type_annotation, value, .. } => {
+                let folded = consteval::evaluate(value, &self.constants)?;
+                let initializer = self.const_initializer(&folded, type_annotation)?;
+                self.constants.insert(name.clone(), folded);
+                let global = self.module.add_global(initializer.get_type(), None, name);
+                global.set_initializer(&initializer);
+                global.set_constant(true);
+                self.globals.insert(name.clone(), (global.as_pointer_value(), initializer.get_type()));
+                Ok(())
+            },
+            AstNode::EnumDef { name, variants, .. } => {
+                if variants.iter().any(|(_, payload)| !payload.is_empty()) {
+                    return Err(format!("Enum {} has variant payloads, which codegen does not support yet", name));
+                }
+                self.enums.insert(name.clone(), variants.iter().map(|(variant, _)| variant.clone()).collect());
+                Ok(())
+            },
+            AstNode::TypeAlias { name, target, .. } => {
+                if matches!(target, Type::Struct(inner) if inner == name) {
+                    return Err(format!("Type alias {} refers to itself", name));
+                }
+                self.aliases.insert(name.clone(), target.clone());
+                Ok(())
+            },
+            AstNode::While { cond, body } => self.generate_while(cond, body),
+            AstNode::Break | AstNode::Continue => {
+                let (header, exit) = *self.loops.last()
+                    .ok_or("`break` and `continue` must be inside a loop")?;
+                let target = if matches!(expr, AstNode::Break) { exit } else { header };
+                self.builder.build_unconditional_branch(target)
+                    .map_err(|e| format!("Failed to build branch: {:?}", e))?;
+                Ok(())
+            },
+            AstNode::Match { .. } => Err("Match is not supported by codegen yet".to_string()),
+            AstNode::Impl { .. } => Err("Impl blocks are not supported by codegen yet".to_string()),
             _ => Ok(()),
         }
     }
 
-    fn generate_value(&self, expr: &AstNode) -> Result<BasicValueEnum<'ctx>, String> {
+    /// Generates the statements of a block and returns the value of its
+    /// trailing expression, if it has one. Statements after a terminator such
+    /// as `return` are unreachable and skipped.
+    fn generate_block(&mut self, block: &AstNode) -> Result<Option<BasicValueEnum<'ctx>>, String> {
+        let statements = match block {
+            AstNode::Program(statements) => statements,
+            _ => return self.generate_value(block).map(Some),
+        };
+
+        let mut value = None;
+        for (i, stmt) in statements.iter().enumerate() {
+            if self.is_terminated() {
+                break;
+            }
+            let is_tail = i + 1 == statements.len() && stmt.is_expression();
+            self.error_located = false;
+            let result = match &stmt.node {
+                AstNode::If { cond, then_block, else_block } => {
+                    self.generate_if(cond, then_block, else_block.as_deref())
+                },
+                // a nested block may or may not end in a value of its own
+                AstNode::Program(_) => self.generate_block(stmt),
+                AstNode::Call { callee, args } if is_tail => self.generate_call(callee, args),
+                _ if is_tail => self.generate_typed_value(stmt).map(Some),
+                _ => self.generate_expression(stmt).map(|_| None),
+            };
+            value = result.map_err(|e| self.locate(e, stmt.span))?;
+        }
+        Ok(value)
+    }
+
+    /// Lowers an if/else into then/else/merge blocks. When both branches fall
+    /// through with a value, the values are joined with a phi node.
+    fn generate_if(
+        &mut self,
+        cond: &AstNode,
+        then_block: &Spanned<AstNode>,
+        else_block: Option<&Spanned<AstNode>>,
+    ) -> Result<Option<BasicValueEnum<'ctx>>, String> {
+        let cond_value = self.generate_value(cond)?.into_int_value();
+        let function = self.current_function()?;
+
+        let then_bb = self.context.append_basic_block(function, "then");
+        let else_bb = self.context.append_basic_block(function, "else");
+        let merge_bb = self.context.append_basic_block(function, "ifcont");
+
+        self.builder.build_conditional_branch(cond_value, then_bb, else_bb)
+            .map_err(|e| format!("Failed to build branch: {:?}", e))?;
+
+        let mut incoming = Vec::new();
+        let mut fallthrough = 0;
+        for (block, branch) in [(then_bb, Some(then_block)), (else_bb, else_block)] {
+            self.builder.position_at_end(block);
+            let value = match branch {
+                Some(branch) => self.generate_block(branch)?,
+                None => None,
+            };
+            if !self.is_terminated() {
+                fallthrough += 1;
+                if let Some(value) = value {
+                    incoming.push((value, self.builder.get_insert_block().unwrap()));
+                }
+                self.builder.build_unconditional_branch(merge_bb)
+                    .map_err(|e| format!("Failed to build branch: {:?}", e))?;
+            }
+        }
+
+        self.builder.position_at_end(merge_bb);
+        if incoming.is_empty() || incoming.len() != fallthrough {
+            return Ok(None);
+        }
+
+        let phi = self.builder.build_phi(incoming[0].0.get_type(), "iftmp")
+            .map_err(|e| format!("Failed to build phi: {:?}", e))?;
+        for (value, block) in &incoming {
+            phi.add_incoming(&[(value, *block)]);
+        }
+        Ok(Some(phi.as_basic_value()))
+    }
+
+    /// Lowers a while loop into a header block that evaluates the condition
+    /// on every iteration, the body, and the block after the loop.
+    fn generate_while(&mut self, cond: &Spanned<AstNode>, body: &Spanned<AstNode>) -> Result<(), String> {
+        let function = self.current_function()?;
+        let header_bb = self.context.append_basic_block(function, "whilecond");
+        let body_bb = self.context.append_basic_block(function, "whilebody");
+        let exit_bb = self.context.append_basic_block(function, "whilecont");
+
+        self.builder.build_unconditional_branch(header_bb)
+            .map_err(|e| format!("Failed to build branch: {:?}", e))?;
+        self.builder.position_at_end(header_bb);
+        let cond_value = self.generate_typed_value(cond)?.into_int_value();
+        self.builder.build_conditional_branch(cond_value, body_bb, exit_bb)
+            .map_err(|e| format!("Failed to build branch: {:?}", e))?;
+
+        self.builder.position_at_end(body_bb);
+        self.loops.push((header_bb, exit_bb));
+        let result = self.generate_block(body);
+        self.loops.pop();
+        result?;
+        if !self.is_terminated() {
+            self.builder.build_unconditional_branch(header_bb)
+                .map_err(|e| format!("Failed to build branch: {:?}", e))?;
+        }
+
+        self.builder.position_at_end(exit_bb);
+        Ok(())
+    }
+
+    /// Like `generate_value`, but a numeric literal takes the width the type
+    /// checker resolved for it instead of defaulting to i32 or f64, and an
+    /// expression the checker folded is emitted as its value.
+    fn generate_typed_value(&mut self, expr: &Spanned<AstNode>) -> Result<BasicValueEnum<'ctx>, String> {
+        let Some(ty) = expr.ty() else {
+            return self.generate_value(expr);
+        };
+        if let Some(folded) = expr.folded() {
+            return self.const_initializer(&folded, &ty);
+        }
+        let (literal, negated) = match &expr.node {
+            AstNode::UnaryOp { op: UnaryOperator::Negate, operand } => (&operand.node, true),
+            literal => (literal, false),
+        };
+        match literal {
+            AstNode::Number(n) if ty.is_integer() => {
+                let n = if negated { n.wrapping_neg() } else { *n };
+                Ok(self.llvm_type(&ty)?.into_int_type().const_int(n as u64, true).into())
+            },
+            AstNode::Float(n) if ty.is_float() => {
+                let n = if negated { -n } else { *n };
+                Ok(self.llvm_type(&ty)?.into_float_type().const_float(n).into())
+            },
+            // only the checker knows what an empty array holds
+            AstNode::ArrayLiteral(elements) if elements.is_empty() => {
+                Ok(self.llvm_type(&ty)?.into_array_type().const_zero().into())
+            },
+            // the checker infers what a closure returns when it doesn't say
+            AstNode::Closure { params, body, .. } => match &ty {
+                Type::Function { return_type, .. } => self.generate_closure(params, return_type, body),
+                _ => self.generate_value(expr),
+            },
+            _ => self.generate_value(expr),
+        }
+    }
+
+    fn generate_value(&mut self, expr: &AstNode) -> Result<BasicValueEnum<'ctx>, String> {
         match expr {
             AstNode::Number(n) => {
                 let int_type = self.context.i32_type(); // Changed from i64 to i32
-                Ok(int_type.const_int(*n as u64, false).into())
+                Ok(int_type.const_int(*n as u64, true).into())
+            },
+            AstNode::Float(n) => {
+                Ok(self.context.f64_type().const_float(*n).into())
+            },
+            AstNode::Char(c) => {
+                // chars are Unicode scalar values, so they need the full 32 bits
+                Ok(self.context.i32_type().const_int(*c as u64, false).into())
+            },
+            AstNode::Boolean(b) => {
+                Ok(self.context.bool_type().const_int(*b as u64, false).into())
+            },
+            AstNode::StringLiteral(text) => {
+                if let Some(ptr) = self.strings.get(text) {
+                    return Ok((*ptr).into());
+                }
+                let ptr = self.builder.build_global_string_ptr(text, "str")
+                    .map_err(|e| format!("Failed to build string: {:?}", e))?
+                    .as_pointer_value();
+                self.strings.insert(text.clone(), ptr);
+                Ok(ptr.into())
             },
             AstNode::Identifier(name) => {
                 self.load_variable(name)
             },
+            AstNode::If { cond, then_block, else_block } => {
+                self.generate_if(cond, then_block, else_block.as_deref())?
+                    .ok_or_else(|| "If expression must produce a value in both branches".to_string())
+            },
+            AstNode::Program(_) => {
+                self.generate_block(expr)?
+                    .ok_or_else(|| "Block used as a value must end in an expression".to_string())
+            },
+            AstNode::Conditional { cond, then_value, else_value } => {
+                self.generate_if(cond, then_value, Some(else_value))?
+                    .ok_or_else(|| "Conditional expression must produce a value in both branches".to_string())
+            },
+            AstNode::ArrayLiteral(elements) => {
+                let values = elements.iter()
+                    .map(|element| self.generate_typed_value(element))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let element_type = values.first()
+                    .ok_or("Cannot generate an empty array literal")?
+                    .get_type();
+                let array_type = element_type.array_type(values.len() as u32);
+
+                // build the array in memory, then load it as a single aggregate value
+                let slot = self.entry_alloca(array_type, "array")?;
+                for (i, value) in values.iter().enumerate() {
+                    let index = self.context.i32_type().const_int(i as u64, false);
+                    let element_ptr = self.element_pointer(slot, array_type.into(), index)?;
+                    self.builder.build_store(element_ptr, *value)
+                        .map_err(|e| format!("Failed to store: {:?}", e))?;
+                }
+                self.builder.build_load(array_type, slot, "array")
+                    .map_err(|e| format!("Failed to load array: {:?}", e))
+            },
+            AstNode::Index { .. } | AstNode::FieldAccess { .. } => {
+                let (ptr, ty) = self.place(expr)?;
+                self.builder.build_load(ty, ptr, "load")
+                    .map_err(|e| format!("Failed to load: {:?}", e))
+            },
+            AstNode::EnumVariant { enum_name, variant, .. } => {
+                let tag = self.enums.get(enum_name)
+                    .and_then(|variants| variants.iter().position(|v| v == variant))
+                    .ok_or_else(|| format!("Unknown variant {}::{}", enum_name, variant))?;
+                Ok(self.context.i32_type().const_int(tag as u64, false).into())
+            },
+            AstNode::StructLiteral { name, fields } => {
+                let (struct_type, field_names) = self.structs.get(name)
+                    .cloned()
+                    .ok_or_else(|| format!("Unknown struct: {}", name))?;
+
+                let slot = self.entry_alloca(struct_type, name)?;
+                for (field, value) in fields {
+                    let index = field_names.iter().position(|f| f == field)
+                        .ok_or_else(|| format!("Struct {} has no field '{}'", name, field))?;
+                    let value = self.generate_typed_value(value)?;
+                    let field_ptr = self.builder.build_struct_gep(struct_type, slot, index as u32, field)
+                        .map_err(|e| format!("Failed to compute field address: {:?}", e))?;
+                    self.builder.build_store(field_ptr, value)
+                        .map_err(|e| format!("Failed to store: {:?}", e))?;
+                }
+                self.builder.build_load(struct_type, slot, name)
+                    .map_err(|e| format!("Failed to load struct: {:?}", e))
+            },
+            // unchecked, a closure that doesn't say returns i32, as functions do
+            AstNode::Closure { params, return_type, body } => {
+                self.generate_closure(params, return_type.as_ref().unwrap_or(&Type::I32), body)
+            },
+            AstNode::UnaryOp { op, operand } => {
+                let value = self.generate_value(operand)?;
+                let result = match (op, value) {
+                    (UnaryOperator::Negate, BasicValueEnum::IntValue(v)) => {
+                        self.builder.build_int_neg(v, "neg").map(Into::into)
+                    },
+                    (UnaryOperator::Negate, BasicValueEnum::FloatValue(v)) => {
+                        self.builder.build_float_neg(v, "fneg").map(Into::into)
+                    },
+                    // booleans are i1, so `not` is an xor with true
+                    (UnaryOperator::Not, BasicValueEnum::IntValue(v)) => {
+                        self.builder.build_not(v, "not").map(Into::into)
+                    },
+                    _ => return Err(format!("Unsupported operand for unary {:?}", op)),
+                };
+                result.map_err(|e| format!("Failed to build unary operation: {:?}", e))
+            },
+            AstNode::Cast { value, target } => {
+                let operand = self.generate_typed_value(value)?;
+                self.generate_cast(operand, value.ty().as_ref(), target)
+            },
+            AstNode::BinaryOp { op: BinaryOperator::Power, left, right } => {
+                let base = self.generate_typed_value(left)?;
+                let exponent = self.generate_typed_value(right)?;
+                let unsigned = right.ty().is_some_and(|ty| ty.is_unsigned());
+                self.generate_power(base, exponent, unsigned)
+            },
+            AstNode::Call { callee, args } => {
+                self.generate_call(callee, args)?
+                    .ok_or_else(|| format!("Function {} returns no value", callee))
+            },
+            AstNode::BinaryOp { op, left, right } if op.is_comparison() => {
+                let lhs = self.generate_typed_value(left)?;
+                let rhs = self.generate_typed_value(right)?;
+                if left.ty() == Some(Type::String) {
+                    return self.generate_string_operation(op, lhs, rhs);
+                }
+                // bools and chars order as unsigned integers, as true > false
+                let unsigned = left.ty().is_some_and(|ty| ty.is_unsigned() || matches!(ty, Type::Bool | Type::Char));
+                self.generate_comparison(op, lhs, rhs, unsigned)
+            },
+            AstNode::BinaryOp { op, left, right } if op.is_logical() => self.generate_logical(op, left, right),
+            AstNode::BinaryOp { op, left, right } => {
+                let lhs = self.generate_typed_value(left)?;
+                let rhs = self.generate_typed_value(right)?;
+                if left.ty() == Some(Type::String) {
+                    return self.generate_string_operation(op, lhs, rhs);
+                }
+                let unsigned = left.ty().is_some_and(|ty| ty.is_unsigned());
+                self.generate_arithmetic(op, lhs, rhs, unsigned)
+            },
+            AstNode::Match { .. } => Err("Match is not supported by codegen yet".to_string()),
             _ => Err("Unsupported expression for value generation".to_string()),
         }
     }
 
+    /// Lowers `+`, `==` and `!=` on two strings to calls of the runtime
+    /// helpers, the same calls `hir::Lowering` produces for them.
+    fn generate_string_operation(
+        &mut self,
+        op: &BinaryOperator,
+        lhs: BasicValueEnum<'ctx>,
+        rhs: BasicValueEnum<'ctx>,
+    ) -> Result<BasicValueEnum<'ctx>, String> {
+        let ptr_type = self.context.ptr_type(AddressSpace::default());
+        let error = |e| format!("Failed to build string operation: {:?}", e);
+        let (name, fn_type) = match op {
+            BinaryOperator::Add => (runtime::STRING_CONCAT, ptr_type.fn_type(&[ptr_type.into(), ptr_type.into()], false)),
+            BinaryOperator::Eq | BinaryOperator::Ne => {
+                (runtime::STRING_EQUALS, self.context.bool_type().fn_type(&[ptr_type.into(), ptr_type.into()], false))
+            },
+            _ => return Err(format!("Unsupported string operator {:?}", op)),
+        };
+        let function = self.declare_external(name, fn_type);
+        let result = self.builder.build_call(function, &[lhs.into(), rhs.into()], name).map_err(error)?
+            .try_as_basic_value()
+            .left()
+            .ok_or_else(|| format!("Runtime helper {} returned no value", name))?;
+        match op {
+            BinaryOperator::Ne => self.builder.build_not(result.into_int_value(), "ne").map(Into::into).map_err(error),
+            _ => Ok(result),
+        }
+    }
+
+    /// Lowers `+`, `-`, `*` and `/` on two integers or two floats of the
+    /// same type. Integer division rounds towards zero, and is unsigned for
+    /// operands the checker typed as unsigned.
+    fn generate_arithmetic(
+        &mut self,
+        op: &BinaryOperator,
+        lhs: BasicValueEnum<'ctx>,
+        rhs: BasicValueEnum<'ctx>,
+        unsigned: bool,
+    ) -> Result<BasicValueEnum<'ctx>, String> {
+        let result = match (lhs, rhs) {
+            (BasicValueEnum::IntValue(l), BasicValueEnum::IntValue(r)) => match op {
+                BinaryOperator::Add => self.builder.build_int_add(l, r, "add"),
+                BinaryOperator::Subtract => self.builder.build_int_sub(l, r, "sub"),
+                BinaryOperator::Multiply => self.builder.build_int_mul(l, r, "mul"),
+                BinaryOperator::Divide if unsigned => self.builder.build_int_unsigned_div(l, r, "udiv"),
+                BinaryOperator::Divide => self.builder.build_int_signed_div(l, r, "sdiv"),
+                _ => return Err(format!("Unsupported integer operator {:?}", op)),
+            }.map(Into::into),
+            (BasicValueEnum::FloatValue(l), BasicValueEnum::FloatValue(r)) => match op {
+                BinaryOperator::Add => self.builder.build_float_add(l, r, "fadd"),
+                BinaryOperator::Subtract => self.builder.build_float_sub(l, r, "fsub"),
+                BinaryOperator::Multiply => self.builder.build_float_mul(l, r, "fmul"),
+                BinaryOperator::Divide => self.builder.build_float_div(l, r, "fdiv"),
+                _ => return Err(format!("Unsupported float operator {:?}", op)),
+            }.map(Into::into),
+            _ => return Err(format!("Unsupported operands for {:?}", op)),
+        };
+        result.map_err(|e| format!("Failed to build {:?}: {:?}", op, e))
+    }
+
+    /// Lowers `&&` or `||`, which only evaluates its right operand when the
+    /// left one leaves the result open, joining the two ways out with a phi
+    /// node.
+    fn generate_logical(
+        &mut self,
+        op: &BinaryOperator,
+        left: &Spanned<AstNode>,
+        right: &Spanned<AstNode>,
+    ) -> Result<BasicValueEnum<'ctx>, String> {
+        let error = |e| format!("Failed to build logical operation: {:?}", e);
+        let function = self.current_function()?;
+        let lhs = self.generate_typed_value(left)?.into_int_value();
+        let left_bb = self.builder.get_insert_block().ok_or("No current block")?;
+        let rhs_bb = self.context.append_basic_block(function, "logicrhs");
+        let merge_bb = self.context.append_basic_block(function, "logiccont");
+
+        // a false left operand decides `&&`, and a true one `||`
+        let is_and = *op == BinaryOperator::And;
+        let (then_bb, else_bb) = if is_and { (rhs_bb, merge_bb) } else { (merge_bb, rhs_bb) };
+        self.builder.build_conditional_branch(lhs, then_bb, else_bb).map_err(error)?;
+
+        self.builder.position_at_end(rhs_bb);
+        let rhs = self.generate_typed_value(right)?.into_int_value();
+        // the right operand may have branched itself
+        let right_bb = self.builder.get_insert_block().ok_or("No current block")?;
+        self.builder.build_unconditional_branch(merge_bb).map_err(error)?;
+
+        self.builder.position_at_end(merge_bb);
+        let bool_type = self.context.bool_type();
+        let phi = self.builder.build_phi(bool_type, "logictmp").map_err(error)?;
+        phi.add_incoming(&[(&bool_type.const_int(!is_and as u64, false), left_bb), (&rhs, right_bb)]);
+        Ok(phi.as_basic_value())
+    }
+
+    /// Lowers a comparison of two integers or two floats of the same type
+    /// to an `i1`. Float comparisons are ordered, so they are false when an
+    /// operand is NaN, except `!=`, which NaN is to everything.
+    fn generate_comparison(
+        &mut self,
+        op: &BinaryOperator,
+        lhs: BasicValueEnum<'ctx>,
+        rhs: BasicValueEnum<'ctx>,
+        unsigned: bool,
+    ) -> Result<BasicValueEnum<'ctx>, String> {
+        let name = format!("{:?}", op).to_lowercase();
+        let result = match (lhs, rhs) {
+            (BasicValueEnum::IntValue(l), BasicValueEnum::IntValue(r)) => {
+                let predicate = match (op, unsigned) {
+                    (BinaryOperator::Eq, _) => IntPredicate::EQ,
+                    (BinaryOperator::Ne, _) => IntPredicate::NE,
+                    (BinaryOperator::Lt, false) => IntPredicate::SLT,
+                    (BinaryOperator::Le, false) => IntPredicate::SLE,
+                    (BinaryOperator::Gt, false) => IntPredicate::SGT,
+                    (BinaryOperator::Ge, false) => IntPredicate::SGE,
+                    (BinaryOperator::Lt, true) => IntPredicate::ULT,
+                    (BinaryOperator::Le, true) => IntPredicate::ULE,
+                    (BinaryOperator::Gt, true) => IntPredicate::UGT,
+                    (BinaryOperator::Ge, true) => IntPredicate::UGE,
+                    _ => return Err(format!("Unsupported comparison {:?}", op)),
+                };
+                self.builder.build_int_compare(predicate, l, r, &name)
+            },
+            (BasicValueEnum::FloatValue(l), BasicValueEnum::FloatValue(r)) => {
+                let predicate = match op {
+                    BinaryOperator::Eq => FloatPredicate::OEQ,
+                    BinaryOperator::Ne => FloatPredicate::UNE,
+                    BinaryOperator::Lt => FloatPredicate::OLT,
+                    BinaryOperator::Le => FloatPredicate::OLE,
+                    BinaryOperator::Gt => FloatPredicate::OGT,
+                    BinaryOperator::Ge => FloatPredicate::OGE,
+                    _ => return Err(format!("Unsupported comparison {:?}", op)),
+                };
+                self.builder.build_float_compare(predicate, l, r, &name)
+            },
+            _ => return Err(format!("Unsupported operands for {:?}", op)),
+        };
+        result.map(Into::into).map_err(|e| format!("Failed to build {:?}: {:?}", op, e))
+    }
+
+    /// Raises `base` to `exponent`, whose signedness is given by
+    /// `unsigned_exponent`. A float base goes through `llvm.pow` or
+    /// `llvm.powi`; an integer base is multiplied out by squaring, wrapping
+    /// on overflow as the other integer operators do, and traps on a negative
+    /// exponent, which the constant folder rejects too.
+    fn generate_power(
+        &mut self,
+        base: BasicValueEnum<'ctx>,
+        exponent: BasicValueEnum<'ctx>,
+        unsigned_exponent: bool,
+    ) -> Result<BasicValueEnum<'ctx>, String> {
+        let i32_type = self.context.i32_type();
+        let error = |e| format!("Failed to build power: {:?}", e);
+
+        let float_base = match (base, exponent) {
+            (BasicValueEnum::FloatValue(v), _) => v,
+            (BasicValueEnum::IntValue(base), BasicValueEnum::IntValue(exponent)) => {
+                return self.generate_integer_power(base, exponent, unsigned_exponent);
+            },
+            (BasicValueEnum::IntValue(_), _) => return Err("Unsupported exponent for '**'".to_string()),
+            _ => return Err("Unsupported base for '**'".to_string()),
+        };
+
+        // intrinsics are overloaded on their float type (and powi on its exponent)
+        let (intrinsic, args, overloads): (_, [BasicMetadataValueEnum; 2], Vec<BasicTypeEnum>) = match exponent {
+            BasicValueEnum::IntValue(v) => {
+                let v = self.builder.build_int_cast_sign_flag(v, i32_type, !unsigned_exponent, "powexp").map_err(error)?;
+                ("llvm.powi", [float_base.into(), v.into()], vec![float_base.get_type().into(), i32_type.into()])
+            },
+            BasicValueEnum::FloatValue(v) => {
+                ("llvm.pow", [float_base.into(), v.into()], vec![float_base.get_type().into()])
+            },
+            _ => return Err("Unsupported exponent for '**'".to_string()),
+        };
+
+        let function = Intrinsic::find(intrinsic)
+            .and_then(|intrinsic| intrinsic.get_declaration(&self.module, &overloads))
+            .ok_or_else(|| format!("Intrinsic {} is not available", intrinsic))?;
+        self.builder.build_call(function, &args, "pow").map_err(error)?
+            .try_as_basic_value()
+            .left()
+            .ok_or_else(|| format!("Intrinsic {} returned no value", intrinsic))
+    }
+
+    fn generate_integer_power(
+        &mut self,
+        base: IntValue<'ctx>,
+        exponent: IntValue<'ctx>,
+        unsigned_exponent: bool,
+    ) -> Result<BasicValueEnum<'ctx>, String> {
+        let error = |e| format!("Failed to build power: {:?}", e);
+        let function = self.current_function()?;
+        let int_type = base.get_type();
+        let exponent_type = exponent.get_type();
+
+        if !unsigned_exponent {
+            let trap_bb = self.context.append_basic_block(function, "powtrap");
+            let start_bb = self.context.append_basic_block(function, "powstart");
+            let negative = self.builder
+                .build_int_compare(IntPredicate::SLT, exponent, exponent_type.const_zero(), "powneg")
+                .map_err(error)?;
+            self.builder.build_conditional_branch(negative, trap_bb, start_bb).map_err(error)?;
+
+            self.builder.position_at_end(trap_bb);
+            let trap = Intrinsic::find("llvm.trap")
+                .and_then(|intrinsic| intrinsic.get_declaration(&self.module, &[]))
+                .ok_or("Intrinsic llvm.trap is not available")?;
+            self.builder.build_call(trap, &[], "").map_err(error)?;
+            self.builder.build_unreachable().map_err(error)?;
+            self.builder.position_at_end(start_bb);
+        }
+
+        let preheader_bb = self.builder.get_insert_block().ok_or("No current block")?;
+        let header_bb = self.context.append_basic_block(function, "powcond");
+        let body_bb = self.context.append_basic_block(function, "powbody");
+        let exit_bb = self.context.append_basic_block(function, "powcont");
+        self.builder.build_unconditional_branch(header_bb).map_err(error)?;
+
+        // result *= factor for every set bit of the exponent, squaring the
+        // factor as the exponent is shifted down
+        self.builder.position_at_end(header_bb);
+        let result = self.builder.build_phi(int_type, "powresult").map_err(error)?;
+        let factor = self.builder.build_phi(int_type, "powfactor").map_err(error)?;
+        let remaining = self.builder.build_phi(exponent_type, "powexp").map_err(error)?;
+        let done = self.builder
+            .build_int_compare(IntPredicate::EQ, remaining.as_basic_value().into_int_value(), exponent_type.const_zero(), "powdone")
+            .map_err(error)?;
+        self.builder.build_conditional_branch(done, exit_bb, body_bb).map_err(error)?;
+
+        self.builder.position_at_end(body_bb);
+        let (current, square, bits) = (
+            result.as_basic_value().into_int_value(),
+            factor.as_basic_value().into_int_value(),
+            remaining.as_basic_value().into_int_value(),
+        );
+        let low_bit = self.builder.build_and(bits, exponent_type.const_int(1, false), "powbit").map_err(error)?;
+        let odd = self.builder
+            .build_int_compare(IntPredicate::NE, low_bit, exponent_type.const_zero(), "powodd")
+            .map_err(error)?;
+        let product = self.builder.build_int_mul(current, square, "powmul").map_err(error)?;
+        let next_result = self.builder.build_select(odd, product, current, "pownext").map_err(error)?;
+        let next_factor = self.builder.build_int_mul(square, square, "powsquare").map_err(error)?;
+        let next_bits = self.builder
+            .build_right_shift(bits, exponent_type.const_int(1, false), false, "powshift")
+            .map_err(error)?;
+        self.builder.build_unconditional_branch(header_bb).map_err(error)?;
+
+        result.add_incoming(&[(&int_type.const_int(1, false), preheader_bb), (&next_result, body_bb)]);
+        factor.add_incoming(&[(&base, preheader_bb), (&next_factor, body_bb)]);
+        remaining.add_incoming(&[(&exponent, preheader_bb), (&next_bits, body_bb)]);
+
+        self.builder.position_at_end(exit_bb);
+        Ok(result.as_basic_value())
+    }
+
+    /// Lowers `value as target`. LLVM integers carry no sign, so whether to
+    /// zero- or sign-extend, and which int/float conversion to use, follows
+    /// the checked type of the value and the target type; a value without a
+    /// checked type counts as signed.
+    fn generate_cast(
+        &mut self,
+        value: BasicValueEnum<'ctx>,
+        source: Option<&Type>,
+        target: &Type,
+    ) -> Result<BasicValueEnum<'ctx>, String> {
+        let target = match target {
+            Type::Struct(name) if self.aliases.contains_key(name) => self.aliases[name].clone(),
+            other => other.clone(),
+        };
+        let from_unsigned = source.is_some_and(Type::is_unsigned);
+        let error = |e| format!("Failed to build cast: {:?}", e);
+
+        match (value, self.llvm_type(&target)?) {
+            (BasicValueEnum::IntValue(v), BasicTypeEnum::IntType(to)) => {
+                let from_width = v.get_type().get_bit_width();
+                let result = if from_width > to.get_bit_width() {
+                    self.builder.build_int_truncate(v, to, "trunc")
+                } else if from_width == to.get_bit_width() {
+                    return Ok(v.into());
+                } else if from_unsigned {
+                    self.builder.build_int_z_extend(v, to, "zext")
+                } else {
+                    self.builder.build_int_s_extend(v, to, "sext")
+                };
+                result.map(Into::into).map_err(error)
+            },
+            (BasicValueEnum::IntValue(v), BasicTypeEnum::FloatType(to)) if from_unsigned => {
+                self.builder.build_unsigned_int_to_float(v, to, "uitofp").map(Into::into).map_err(error)
+            },
+            (BasicValueEnum::IntValue(v), BasicTypeEnum::FloatType(to)) => {
+                self.builder.build_signed_int_to_float(v, to, "sitofp").map(Into::into).map_err(error)
+            },
+            (BasicValueEnum::FloatValue(v), BasicTypeEnum::IntType(to)) if target.is_unsigned() => {
+                self.builder.build_float_to_unsigned_int(v, to, "fptoui").map(Into::into).map_err(error)
+            },
+            (BasicValueEnum::FloatValue(v), BasicTypeEnum::IntType(to)) => {
+                self.builder.build_float_to_signed_int(v, to, "fptosi").map(Into::into).map_err(error)
+            },
+            // fpext or fptrunc, whichever the widths call for
+            (BasicValueEnum::FloatValue(v), BasicTypeEnum::FloatType(to)) => {
+                self.builder.build_float_cast(v, to, "fpcast").map(Into::into).map_err(error)
+            },
+            _ => Err(format!("Cannot cast to {}", target)),
+        }
+    }
+
+    /// Lowers a closure to a private function and yields a pointer to it.
+    /// Closures cannot capture yet, so the body only sees its parameters.
+    fn generate_closure(
+        &mut self,
+        params: &[(String, Type)],
+        return_type: &Type,
+        body: &Spanned<AstNode>,
+    ) -> Result<BasicValueEnum<'ctx>, String> {
+        let mut captures = Captures { locals: &self.variables, params, found: None };
+        captures.visit_node(body);
+        if let Some(name) = captures.found {
+            return Err(format!("Closures cannot capture variables yet: {}", name));
+        }
+
+        let param_types: Vec<Type> = params.iter().map(|(_, t)| t.clone()).collect();
+        let fn_type = self.function_type(&param_types, return_type)?;
+        let function = self.module.add_function("closure", fn_type, Some(Linkage::Private));
+
+        let outer_block = self.builder.get_insert_block();
+        let outer_variables = std::mem::take(&mut self.variables);
+        let outer_signatures = std::mem::take(&mut self.signatures);
+        let outer_loops = std::mem::take(&mut self.loops);
+
+        let entry = self.context.append_basic_block(function, "entry");
+        self.builder.position_at_end(entry);
+        let result = self.generate_closure_body(function, params, return_type, body);
+
+        self.variables = outer_variables;
+        self.signatures = outer_signatures;
+        self.loops = outer_loops;
+        if let Some(block) = outer_block {
+            self.builder.position_at_end(block);
+        }
+
+        result?;
+        Ok(function.as_global_value().as_pointer_value().into())
+    }
+
+    fn generate_closure_body(
+        &mut self,
+        function: FunctionValue<'ctx>,
+        params: &[(String, Type)],
+        return_type: &Type,
+        body: &AstNode,
+    ) -> Result<(), String> {
+        self.bind_params(function, params)?;
+        let value = self.generate_block(body)?;
+        if !self.is_terminated() {
+            match (return_type, value) {
+                (Type::Void, _) => self.builder.build_return(None),
+                (_, Some(value)) => self.builder.build_return(Some(&value)),
+                (_, None) => return Err("Closure body must produce a value".to_string()),
+            }
+            .map_err(|e| format!("Failed to build return: {:?}", e))?;
+        }
+
+        if function.verify(true) {
+            Ok(())
+        } else {
+            Err("Invalid closure generated".to_string())
+        }
+    }
+
+    /// The named LLVM type of a struct, registered without a body the first
+    /// time, so that fields and signatures can refer to it before its
+    /// fields are known.
+    fn declare_struct(&mut self, name: &str, fields: &[(String, Type)]) -> StructType<'ctx> {
+        if let Some((struct_type, _)) = self.structs.get(name) {
+            return *struct_type;
+        }
+        let struct_type = self.context.opaque_struct_type(name);
+        self.structs.insert(name.to_string(), (struct_type, fields.iter().map(|(f, _)| f.clone()).collect()));
+        struct_type
+    }
+
+    /// The LLVM function for a Nova function, added to the module the first
+    /// time it is declared.
+    fn declare_function(
+        &mut self,
+        name: &str,
+        params: &[(String, Type)],
+        return_type: &Type,
+    ) -> Result<FunctionValue<'ctx>, String> {
+        if let Some(function) = self.module.get_function(name) {
+            return Ok(function);
+        }
+        let param_types: Vec<Type> = params.iter().map(|(_, t)| t.clone()).collect();
+        let fn_type = self.function_type(&param_types, return_type)?;
+        Ok(self.module.add_function(name, fn_type, None))
+    }
+
+    /// Encodes the code point of a `char` as UTF-8 in a buffer of its own,
+    /// to be printed with `%s`; `%c` would print only its lowest byte.
+    fn generate_utf8(&mut self, code_point: IntValue<'ctx>) -> Result<PointerValue<'ctx>, String> {
+        let i32_type = self.context.i32_type();
+        let i8_type = self.context.i8_type();
+        let builder = &self.builder;
+        let constant = |n: u64| i32_type.const_int(n, false);
+        let encode = || -> Result<[IntValue<'ctx>; 4], BuilderError> {
+            let below = |limit, name| builder.build_int_compare(IntPredicate::ULT, code_point, constant(limit), name);
+            let (one, two, three) = (below(0x80, "onebyte")?, below(0x800, "twobytes")?, below(0x10000, "threebytes")?);
+            // the leading byte holds the length in its top bits, and every
+            // following byte 0b10 and six bits of the code point
+            let bits = |shift, mask, prefix| -> Result<IntValue<'ctx>, BuilderError> {
+                let shifted = builder.build_right_shift(code_point, constant(shift), false, "utf8shift")?;
+                let masked = builder.build_and(shifted, constant(mask), "utf8bits")?;
+                builder.build_or(masked, constant(prefix), "utf8byte")
+            };
+            let lead = |shift, prefix| bits(shift, 0x3F >> (shift / 6), prefix);
+            let follow = |shift| bits(shift, 0x3F, 0x80);
+            let select = |cond, then: IntValue<'ctx>, otherwise: IntValue<'ctx>| {
+                builder.build_select(cond, then, otherwise, "utf8").map(|value| value.into_int_value())
+            };
+            let zero = constant(0);
+            Ok([
+                select(one, code_point, select(two, lead(6, 0xC0)?, select(three, lead(12, 0xE0)?, lead(18, 0xF0)?)?)?)?,
+                select(one, zero, select(two, follow(0)?, select(three, follow(6)?, follow(12)?)?)?)?,
+                select(two, zero, select(three, follow(0)?, follow(6)?)?)?,
+                select(three, zero, follow(0)?)?,
+            ])
+        };
+        let error = |e| format!("Failed to encode char: {:?}", e);
+        let bytes = encode().map_err(error)?;
+
+        // the fifth byte stays zero and ends the string
+        let buffer_type = i8_type.array_type(5);
+        let mut buffer = buffer_type.const_zero();
+        for (i, byte) in bytes.into_iter().enumerate() {
+            let byte = self.builder.build_int_truncate(byte, i8_type, "utf8byte").map_err(error)?;
+            buffer = self.builder.build_insert_value(buffer, byte, i as u32, "utf8")
+                .map_err(error)?
+                .into_array_value();
+        }
+        let slot = self.entry_alloca(buffer_type, "utf8")?;
+        self.builder.build_store(slot, buffer).map_err(error)?;
+        Ok(slot)
+    }
+
+    /// A function defined outside the module, such as one of the C library
+    /// or of the runtime, declared the first time it is called.
+    fn declare_external(&self, name: &str, fn_type: FunctionType<'ctx>) -> FunctionValue<'ctx> {
+        self.module.get_function(name)
+            .unwrap_or_else(|| self.module.add_function(name, fn_type, Some(Linkage::External)))
+    }
+
+    /// Calls the function named `callee` with `args`, yielding its result, or
+    /// `None` for a void function.
+    fn generate_call(
+        &mut self,
+        callee: &str,
+        args: &[Spanned<AstNode>],
+    ) -> Result<Option<BasicValueEnum<'ctx>>, String> {
+        // a local holding a function shadows any function of the same name
+        if let Some(&signature) = self.signatures.get(callee) {
+            let pointer = self.load_variable(callee)?.into_pointer_value();
+            let args = self.generate_args(args)?;
+            let call = self.builder.build_indirect_call(signature, pointer, &args, callee)
+                .map_err(|e| format!("Failed to build call: {:?}", e))?;
+            return Ok(call.try_as_basic_value().left());
+        }
+        let function = match self.module.get_function(callee) {
+            Some(function) => function,
+            None if BUILTINS.contains(&callee) => return self.generate_print(callee == "println", args).map(|_| None),
+            None if self.generics.contains(callee) => {
+                return Err(format!("Generic function {} cannot be called by codegen yet", callee));
+            },
+            None => return Err(format!("Undefined function: {}", callee)),
+        };
+        let args = self.generate_args(args)?;
+        let call = self.builder.build_call(function, &args, callee)
+            .map_err(|e| format!("Failed to build call: {:?}", e))?;
+        Ok(call.try_as_basic_value().left())
+    }
+
+    fn generate_args(&mut self, args: &[Spanned<AstNode>]) -> Result<Vec<BasicMetadataValueEnum<'ctx>>, String> {
+        args.iter()
+            .map(|arg| self.generate_typed_value(arg).map(Into::into))
+            .collect()
+    }
+
+    /// Lowers `print` or `println` to one call of the C library's `printf`,
+    /// with a conversion for each argument chosen by its checked type.
+    fn generate_print(&mut self, newline: bool, args: &[Spanned<AstNode>]) -> Result<(), String> {
+        let ptr_type = self.context.ptr_type(AddressSpace::default());
+        let mut format = String::new();
+        let mut values: Vec<BasicMetadataValueEnum> = Vec::new();
+        for arg in args {
+            let ty = arg.ty().ok_or("Cannot print a value the type checker has not seen")?;
+            let value = self.generate_typed_value(arg)?;
+            // variadic arguments narrower than an int or a double are widened
+            let (conversion, value): (&str, BasicValueEnum) = match ty {
+                Type::I8 | Type::I16 => {
+                    let wide = self.builder.build_int_s_extend(value.into_int_value(), self.context.i32_type(), "widen")
+                        .map_err(|e| format!("Failed to widen: {:?}", e))?;
+                    ("%d", wide.into())
+                },
+                Type::U8 | Type::U16 => {
+                    let wide = self.builder.build_int_z_extend(value.into_int_value(), self.context.i32_type(), "widen")
+                        .map_err(|e| format!("Failed to widen: {:?}", e))?;
+                    ("%u", wide.into())
+                },
+                Type::I32 => ("%d", value),
+                Type::U32 => ("%u", value),
+                Type::I64 => ("%lld", value),
+                Type::U64 => ("%llu", value),
+                Type::F32 => {
+                    let wide = self.builder.build_float_ext(value.into_float_value(), self.context.f64_type(), "widen")
+                        .map_err(|e| format!("Failed to widen: {:?}", e))?;
+                    ("%g", wide.into())
+                },
+                Type::F64 => ("%g", value),
+                Type::Char => ("%s", self.generate_utf8(value.into_int_value())?.into()),
+                Type::String => ("%s", value),
+                Type::Bool => {
+                    let yes = self.string_constant("true", ptr_type);
+                    let no = self.string_constant("false", ptr_type);
+                    let text = self.builder.build_select(value.into_int_value(), yes, no, "bool")
+                        .map_err(|e| format!("Failed to build select: {:?}", e))?;
+                    ("%s", text)
+                },
+                other => return Err(format!("Cannot print a value of type {:?}", other)),
+            };
+            format.push_str(conversion);
+            values.push(value.into());
+        }
+        if newline {
+            format.push('\n');
+        }
+        values.insert(0, self.string_constant(&format, ptr_type).into());
+
+        let printf_type = self.context.i32_type().fn_type(&[ptr_type.into()], true);
+        let printf = self.declare_external("printf", printf_type);
+        self.builder.build_call(printf, &values, "printf")
+            .map_err(|e| format!("Failed to build call: {:?}", e))?;
+        Ok(())
+    }
+
+    /// Spills each argument of `function` to a stack slot in the current
+    /// block and registers it as a variable under its parameter's name, so
+    /// that parameters are read and assigned like any other local.
+    fn bind_params(&mut self, function: FunctionValue<'ctx>, params: &[(String, Type)]) -> Result<(), String> {
+        for (i, (name, ty)) in params.iter().enumerate() {
+            let arg = function.get_nth_param(i as u32)
+                .ok_or_else(|| format!("Missing parameter: {}", name))?;
+            let alloca = self.entry_alloca(arg.get_type(), name)?;
+            self.builder.build_store(alloca, arg)
+                .map_err(|e| format!("Failed to store: {:?}", e))?;
+            self.variables.insert(name.clone(), (alloca, arg.get_type()));
+            self.bind_signature(name, Some(ty))?;
+        }
+        Ok(())
+    }
+
+    /// Records the signature of the variable `name` when `ty` is a function
+    /// type, and forgets any signature an outer `name` had otherwise.
+    fn bind_signature(&mut self, name: &str, ty: Option<&Type>) -> Result<(), String> {
+        let mut ty = ty;
+        while let Some(Type::Struct(alias)) = ty {
+            ty = self.aliases.get(alias);
+        }
+        match ty {
+            Some(Type::Function { params, return_type }) => {
+                let signature = self.function_type(params, return_type)?;
+                self.signatures.insert(name.to_string(), signature);
+            },
+            _ => {
+                self.signatures.remove(name);
+            },
+        }
+        Ok(())
+    }
+
+    /// Points an error at the innermost statement it came from. Nodes built
+    /// outside the parser have no position to report.
+    fn locate(&mut self, error: String, span: Span) -> String {
+        if self.error_located || span == Span::default() {
+            return error;
+        }
+        self.error_located = true;
+        format!("{} at {}", error, span)
+    }
+
+    fn current_function(&self) -> Result<FunctionValue<'ctx>, String> {
+        self.builder.get_insert_block()
+            .and_then(|block| block.get_parent())
+            .ok_or_else(|| "Control flow outside of a function".to_string())
+    }
+
+    /// Memory holding the value of `expr`, with the type stored there. Field
+    /// and index chains such as `a.b[i].c` address into the variable's own
+    /// slot; any other expression is spilled to a temporary.
+    fn place(&mut self, expr: &AstNode) -> Result<(PointerValue<'ctx>, BasicTypeEnum<'ctx>), String> {
+        match expr {
+            AstNode::Identifier(name) => {
+                return self.lookup(name)
+                    .ok_or_else(|| format!("Undefined variable: {}", name));
+            },
+            AstNode::FieldAccess { object, field } => {
+                let (slot, object_type) = self.place(object)?;
+                return self.field_pointer(slot, object_type, field);
+            },
+            AstNode::Index { array, index } => {
+                let (slot, array_type) = self.place(array)?;
+                if !array_type.is_array_type() {
+                    return Err("Cannot index into a non-array value".to_string());
+                }
+                let index = self.generate_value(index)?.into_int_value();
+                let element_ptr = self.element_pointer(slot, array_type, index)?;
+                return Ok((element_ptr, array_type.into_array_type().get_element_type()));
+            },
+            _ => {},
+        }
+
+        let value = self.generate_value(expr)?;
+        let slot = self.entry_alloca(value.get_type(), "tmp")?;
+        self.builder.build_store(slot, value)
+            .map_err(|e| format!("Failed to store: {:?}", e))?;
+        Ok((slot, value.get_type()))
+    }
+
+    fn function_type(&self, params: &[Type], return_type: &Type) -> Result<FunctionType<'ctx>, String> {
+        let params = params.iter()
+            .map(|t| self.llvm_type(t).map(Into::into))
+            .collect::<Result<Vec<BasicMetadataTypeEnum>, _>>()?;
+        Ok(match return_type {
+            Type::Void => self.context.void_type().fn_type(&params, false),
+            _ => self.llvm_type(return_type)?.fn_type(&params, false),
+        })
+    }
+
+    fn llvm_type(&self, ty: &Type) -> Result<BasicTypeEnum<'ctx>, String> {
+        Ok(match ty {
+            Type::I8 | Type::U8 => self.context.i8_type().into(),
+            Type::I16 | Type::U16 => self.context.i16_type().into(),
+            Type::I32 | Type::U32 | Type::Char => self.context.i32_type().into(),
+            Type::I64 | Type::U64 => self.context.i64_type().into(),
+            Type::F32 => self.context.f32_type().into(),
+            Type::F64 => self.context.f64_type().into(),
+            Type::Bool => self.context.bool_type().into(),
+            Type::Array(element, len) => self.llvm_type(element)?.array_type(*len as u32).into(),
+            Type::Enum(_) => self.context.i32_type().into(),
+            // functions are passed around as plain code pointers
+            Type::Function { .. } => self.context.ptr_type(AddressSpace::default()).into(),
+            // strings are pointers to null-terminated bytes
+            Type::String => self.context.ptr_type(AddressSpace::default()).into(),
+            Type::Struct(name) if self.enums.contains_key(name) => self.context.i32_type().into(),
+            Type::Struct(name) if self.aliases.contains_key(name) => self.llvm_type(&self.aliases[name])?,
+            Type::Struct(name) => self.structs.get(name)
+                .map(|(struct_type, _)| (*struct_type).into())
+                .ok_or_else(|| format!("Unknown struct: {}", name))?,
+            _ => return Err(format!("Type {} is not supported by codegen yet", ty)),
+        })
+    }
+
+    /// Address and type of `field` within the struct stored at `slot`.
+    fn field_pointer(
+        &self,
+        slot: PointerValue<'ctx>,
+        object_type: BasicTypeEnum<'ctx>,
+        field: &str,
+    ) -> Result<(PointerValue<'ctx>, BasicTypeEnum<'ctx>), String> {
+        if !object_type.is_struct_type() {
+            return Err(format!("Cannot access field '{}' on a non-struct value", field));
+        }
+        let struct_type = object_type.into_struct_type();
+        let name = struct_type.get_name()
+            .and_then(|name| name.to_str().ok())
+            .ok_or("Field access on an anonymous struct")?;
+        let index = self.structs.get(name)
+            .and_then(|(_, field_names)| field_names.iter().position(|f| f == field))
+            .ok_or_else(|| format!("Struct {} has no field '{}'", name, field))? as u32;
+
+        let field_ptr = self.builder.build_struct_gep(struct_type, slot, index, field)
+            .map_err(|e| format!("Failed to compute field address: {:?}", e))?;
+        let field_type = struct_type.get_field_type_at_index(index)
+            .ok_or_else(|| format!("Struct {} has no field '{}'", name, field))?;
+        Ok((field_ptr, field_type))
+    }
+
+    /// Address of element `index` within the array stored at `slot`.
+    fn element_pointer(
+        &self,
+        slot: PointerValue<'ctx>,
+        array_type: BasicTypeEnum<'ctx>,
+        index: IntValue<'ctx>,
+    ) -> Result<PointerValue<'ctx>, String> {
+        let zero = self.context.i32_type().const_zero();
+        // SAFETY: the leading zero steps through the pointer to the array itself;
+        // the type checker guarantees `array_type` is the array stored at `slot`
+        unsafe { self.builder.build_in_bounds_gep(array_type, slot, &[zero, index], "element_ptr") }
+            .map_err(|e| format!("Failed to compute element address: {:?}", e))
+    }
+
+    /// A stack slot in the entry block of the current function, after the
+    /// slots already there, so that a slot for a local declared in a loop
+    /// is allocated once instead of on every iteration.
+    fn entry_alloca<T: BasicType<'ctx>>(&self, ty: T, name: &str) -> Result<PointerValue<'ctx>, String> {
+        let entry = self.current_function()?
+            .get_first_basic_block()
+            .ok_or("Function has no entry block")?;
+        let mut first = entry.get_first_instruction();
+        while let Some(instruction) = first.filter(|i| i.get_opcode() == InstructionOpcode::Alloca) {
+            first = instruction.get_next_instruction();
+        }
+        let builder = self.context.create_builder();
+        match first {
+            Some(instruction) => builder.position_before(&instruction),
+            None => builder.position_at_end(entry),
+        }
+        builder.build_alloca(ty, name)
+            .map_err(|e| format!("Failed to allocate: {:?}", e))
+    }
+
+    fn is_terminated(&self) -> bool {
+        self.builder.get_insert_block()
+            .and_then(|block| block.get_terminator())
+            .is_some()
+    }
+
+    fn lookup(&self, name: &str) -> Option<(PointerValue<'ctx>, BasicTypeEnum<'ctx>)> {
+        self.variables.get(name)
+            .or_else(|| self.globals.get(name))
+            .copied()
+    }
+
+    /// Lowers a folded constant to an LLVM constant of type `ty`.
+    fn const_initializer(&mut self, value: &ConstValue, ty: &Type) -> Result<BasicValueEnum<'ctx>, String> {
+        match (value, self.llvm_type(ty)?) {
+            (ConstValue::Int(v), BasicTypeEnum::IntType(int_type)) => Ok(int_type.const_int(*v as u64, true).into()),
+            (ConstValue::Float(v), BasicTypeEnum::FloatType(float_type)) => Ok(float_type.const_float(*v).into()),
+            (ConstValue::Bool(b), BasicTypeEnum::IntType(int_type)) => Ok(int_type.const_int(*b as u64, false).into()),
+            (ConstValue::Char(c), BasicTypeEnum::IntType(int_type)) => Ok(int_type.const_int(*c as u64, false).into()),
+            (ConstValue::String(text), BasicTypeEnum::PointerType(ptr_type)) => Ok(self.string_constant(text, ptr_type).into()),
+            _ => Err(format!("Unsupported constant of type {}", ty)),
+        }
+    }
+
+    /// A pointer to a private, null-terminated copy of `text`: the global
+    /// `build_global_string_ptr` emits, for constants, which are built
+    /// outside of any function.
+    fn string_constant(&mut self, text: &str, ptr_type: PointerType<'ctx>) -> PointerValue<'ctx> {
+        if let Some(ptr) = self.strings.get(text) {
+            return *ptr;
+        }
+        let bytes = self.context.const_string(text.as_bytes(), true);
+        let global = self.module.add_global(bytes.get_type(), None, "str");
+        global.set_initializer(&bytes);
+        global.set_constant(true);
+        global.set_linkage(Linkage::Private);
+        global.set_unnamed_address(UnnamedAddress::Global);
+        let ptr = global.as_pointer_value().const_cast(ptr_type);
+        self.strings.insert(text.to_string(), ptr);
+        ptr
+    }
+
     fn load_variable(&self, name: &str) -> Result<BasicValueEnum<'ctx>, String> {
-        match self.variables.get(name) {
-            Some(ptr) => {
-                Ok(self.builder.build_load(self.context.i64_type(), *ptr, name)
+        match self.lookup(name) {
+            Some((ptr, ty)) => {
+                Ok(self.builder.build_load(ty, ptr, name)
                     .map_err(|e| format!("Failed to load variable: {:?}", e))?)
             },
             None => Err(format!("Undefined variable: {}", name)),
@@ -142,7 +1328,7 @@ impl<'ctx> CodeGen<'ctx> {
             let arg = function.get_nth_param(i as u32).unwrap();
             let alloca = self.builder.build_alloca(arg.get_type(), name).unwrap();
             self.builder.build_store(alloca, arg).unwrap();
-            self.variables.insert(name.to_string(), alloca);
+            self.variables.insert(name.to_string(), (alloca, arg.get_type()));
         }
     }
 
@@ -167,7 +1353,8 @@ impl<'ctx> CodeGen<'ctx> {
             TargetMachine::get_host_cpu_name().to_str().unwrap(),
             TargetMachine::get_host_cpu_features().to_str().unwrap(),
             inkwell::OptimizationLevel::Default,
-            RelocMode::Default,
+            // `cc` links position independent executables by default
+            RelocMode::PIC,
             CodeModel::Default,
         ).ok_or("Failed to create target machine")?;
 
@@ -176,16 +1363,577 @@ impl<'ctx> CodeGen<'ctx> {
     }
 }
 
+/// Finds the first variable of the enclosing function a closure body uses.
+/// Nested closures are left to check their own bodies.
+struct Captures<'a, 'ctx> {
+    locals: &'a HashMap<String, (PointerValue<'ctx>, BasicTypeEnum<'ctx>)>,
+    params: &'a [(String, Type)],
+    found: Option<String>,
+}
+
+impl Visitor for Captures<'_, '_> {
+    fn visit_node(&mut self, node: &Spanned<AstNode>) {
+        match &node.node {
+            AstNode::Identifier(name)
+                if self.found.is_none()
+                    && self.locals.contains_key(name)
+                    && !self.params.iter().any(|(param, _)| param == name) =>
+            {
+                self.found = Some(name.clone());
+            },
+            AstNode::Closure { .. } => {},
+            _ => walk_node(self, node),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use inkwell::context::Context;
 
+    /// Checks and generates `source` with `codegen`, returning the IR of the
+    /// module once it verifies.
+    fn generate_checked(codegen: &mut CodeGen, source: &str) -> String {
+        let ast = crate::parser::Parser::from_source(source).unwrap().parse().unwrap();
+        crate::typecheck::TypeChecker::new().check(&ast).unwrap();
+        codegen.generate(&ast).unwrap();
+        let ir = codegen.module.print_to_string().to_string();
+        assert!(codegen.module.verify().is_ok(), "{}", ir);
+        ir
+    }
+
+    /// Checks and generates `source` in a module of its own.
+    fn generate_source(source: &str) -> String {
+        let context = Context::create();
+        let mut codegen = CodeGen::new(&context);
+        generate_checked(&mut codegen, source)
+    }
+
     #[test]
     fn test_codegen() {
         let context = Context::create();
         let mut codegen = CodeGen::new(&context);
-        let ast = AstNode::Program(vec![AstNode::Number(42)]);
+        let ast = AstNode::Program(vec![AstNode::Number(42).into()]);
         assert!(codegen.generate(&ast).is_ok());
     }
+
+    #[test]
+    fn test_unary_codegen() {
+        let ir = generate_source("fn f(a: i32, b: f64, c: bool): i32 { let x = -b; let y = !c; -a }");
+        assert!(ir.contains("fneg double %b"), "{}", ir);
+        // booleans are i1, so `not` is an xor with true
+        assert!(ir.contains("xor i1 %c"), "{}", ir);
+        assert!(ir.contains("sub i32 0, %a"), "{}", ir);
+    }
+
+    #[test]
+    fn test_arithmetic_codegen() {
+        let source = "fn main(): i32 {\n\
+                      let a = 7; let b = 2; let u: u32 = 9; let q = u / 2;\n\
+                      let x = 1.5; let y = x * 2.0 - x / x + x;\n\
+                      return a / b - a * b + a;\n}";
+        let ir = generate_source(source);
+        for instruction in ["sdiv i32 %a", "mul i32 %a", "sub i32 %sdiv", "add i32 %sub", "udiv i32 %u", "fmul double %x"] {
+            assert!(ir.contains(instruction), "{} not in {}", instruction, ir);
+        }
+        assert!(ir.contains("fdiv double %x"), "{}", ir);
+        assert!(ir.contains("fsub double %fmul, %fdiv"), "{}", ir);
+    }
+
+    #[test]
+    fn test_function_params_codegen() {
+        let context = Context::create();
+        let mut codegen = CodeGen::new(&context);
+        let source = "fn scale(n: i32, by: i64, f: f32): i32 { let m = n * 2; m }\nfn main(): i32 { 0 }";
+        let ir = generate_checked(&mut codegen, source);
+        assert!(ir.contains("define i32 @scale(i32 %0, i64 %1, float %2)"), "{}", ir);
+        assert!(ir.contains("%n = alloca i32"), "{}", ir);
+        assert!(ir.contains("store i32 %0, "), "{}", ir);
+        assert!(ir.contains("store i64 %1, "), "{}", ir);
+        assert!(ir.contains("%n1 = load i32, "), "{}", ir);
+        // parameters are not visible from the next function
+        let later = crate::parser::Parser::from_source("fn g(): i32 { n }").unwrap().parse().unwrap();
+        assert!(codegen.generate(&later).is_err());
+    }
+
+    #[test]
+    fn test_call_codegen() {
+        let context = Context::create();
+        let mut codegen = CodeGen::new(&context);
+        let source = "fn main(): i32 { let x = add(2, 3); log(x); add(x, 1) }\n\
+                      fn add(a: i32, b: i32): i32 { a + b }\nfn log(n: i32): void {}";
+        let ir = generate_checked(&mut codegen, source);
+        assert!(ir.contains("%add = call i32 @add(i32 2, i32 3)"), "{}", ir);
+        assert!(ir.contains("call void @log(i32 %x"), "{}", ir);
+        assert!(ir.contains("ret i32 %add"), "{}", ir);
+
+        let unit = crate::parser::Parser::from_source("fn f(): i32 { let v = log(1); 0 }").unwrap().parse().unwrap();
+        assert!(codegen.generate(&unit).unwrap_err().starts_with("Function log returns no value"));
+    }
+
+    #[test]
+    fn test_return_type_codegen() {
+        let context = Context::create();
+        let mut codegen = CodeGen::new(&context);
+        let source = "fn wide(): i64 { 5 }\nfn half(x: f64): f64 { return x / 2.0; }\n\
+                      fn small(b: bool): u8 { if b { return 1; } 2 }\nfn flag(): bool { true }\nfn nothing(): void {}";
+        let ir = generate_checked(&mut codegen, source);
+        for expected in [
+            "define i64 @wide()",
+            "ret i64 5",
+            "define double @half(double %0)",
+            "ret double %fdiv",
+            "define i8 @small(i1 %0)",
+            "ret i8 2",
+            "define i1 @flag()",
+            "ret i1 true",
+            "define void @nothing()",
+            "ret void",
+        ] {
+            assert!(ir.contains(expected), "{} not in {}", expected, ir);
+        }
+
+        // a body that falls off its end returns the zero of its type
+        let unchecked = crate::parser::Parser::from_source("fn zero(): f32 {}").unwrap().parse().unwrap();
+        codegen.generate(&unchecked).unwrap();
+        let ir = codegen.module.print_to_string().to_string();
+        assert!(ir.contains("ret float 0.000000e+00"), "{}", ir);
+    }
+
+    #[test]
+    fn test_float_codegen() {
+        let source = "fn area(r: f32): f32 { let half: f32 = 0.5; half * r * r }\n\
+                      fn mix(a: f64, b: f64): f64 { -(a + b) / (a - 2.0) }";
+        let ir = generate_source(source);
+        for expected in [
+            "store float 5.000000e-01, ",
+            "fmul float %half",
+            "ret float %fmul",
+            "fadd double %a",
+            "fneg double %fadd",
+            "fsub double %a",
+            "fdiv double %fneg, %fsub",
+        ] {
+            assert!(ir.contains(expected), "{} not in {}", expected, ir);
+        }
+    }
+
+    #[test]
+    fn test_string_codegen() {
+        let source = "const GREETING: string = \"hello, \" + \"world\";\n\
+                      fn name(): string { \"nova\" }\n\
+                      fn main(): i32 { let s = \"hi\"; let t: string = s; let g = GREETING; let h = GREETING; let n = name(); 0 }";
+        let ir = generate_source(source);
+        for expected in [
+            "private unnamed_addr constant [13 x i8] c\"hello, world\\00\"",
+            "private unnamed_addr constant [5 x i8] c\"nova\\00\"",
+            "private unnamed_addr constant [3 x i8] c\"hi\\00\"",
+            "@GREETING = constant",
+            "%n = alloca",
+        ] {
+            assert!(ir.contains(expected), "{} not in {}", expected, ir);
+        }
+        // the constant is folded into each use rather than loaded, and the
+        // same text is only emitted once
+        assert_eq!(ir.matches("@GREETING").count(), 1, "{}", ir);
+        assert_eq!(ir.matches("c\"hello, world\\00\"").count(), 1, "{}", ir);
+        assert_eq!(ir.matches("c\"hi\\00\"").count(), 1, "{}", ir);
+    }
+
+    #[test]
+    fn test_print_codegen() {
+        let source = "fn main(): i32 {\n\
+                      let small: u8 = 7; let big: i64 = 9; let ratio: f32 = 0.5;\n\
+                      print(\"n = \", 1, small, big); println(ratio, small > 3, 'c'); println(); 0 }";
+        let ir = generate_source(source);
+        for expected in [
+            "declare i32 @printf(",
+            "c\"%s%d%u%lld\\00\"",
+            "c\"%g%s%s\\0A\\00\"",
+            "c\"\\0A\\00\"",
+            "%widen = zext i8 %small",
+            "= fpext float %ratio",
+            "select i1 %gt",
+        ] {
+            assert!(ir.contains(expected), "{} not in {}", expected, ir);
+        }
+        assert_eq!(ir.matches("declare i32 @printf(").count(), 1, "{}", ir);
+        assert_eq!(ir.matches("call i32 (").count(), 3, "{}", ir);
+    }
+
+    #[test]
+    fn test_print_char_as_utf8() {
+        let ir = generate_source("fn f(c: char): void { println('é', c); }");
+        // a literal is encoded while generating, a variable at run time
+        assert!(ir.contains("c\"\\C3\\A9\\00\\00\\00\""), "{}", ir);
+        assert!(ir.contains("c\"%s%s\\0A\\00\""), "{}", ir);
+        assert!(ir.contains("icmp ult i32 %c1, 2048"), "{}", ir);
+    }
+
+    #[test]
+    fn test_string_operation_codegen() {
+        let source = "fn f(a: string, b: string): bool { let c = a + b; let _same = c == a; b != \"x\" }";
+        let ir = generate_source(source);
+        for expected in ["declare i1 @nova_string_equals(", "@nova_string_concat(", "%ne = xor i1"] {
+            assert!(ir.contains(expected), "{} not in {}", expected, ir);
+        }
+        // declared once, called twice
+        assert_eq!(ir.matches("@nova_string_equals(").count(), 3, "{}", ir);
+    }
+
+    #[test]
+    fn test_comparison_codegen() {
+        let source = "fn main(): i32 {\n\
+                      let a = 1; let b = 2; let u: u8 = 3; let x = 1.5; let t = true;\n\
+                      let lt = a < b; let le = u <= 4; let gt = x > 0.5; let ne = x != x; let eq = t == false;\n\
+                      if a >= b { return 1; }\n0 }";
+        let ir = generate_source(source);
+        for expected in [
+            "%lt = icmp slt i32 %a",
+            "%le = icmp ule i8 %u",
+            "%gt = fcmp ogt double %x",
+            "%ne = fcmp une double %x",
+            "%eq = icmp eq i1 %t",
+            "%ge = icmp sge i32 %a",
+            "br i1 %ge",
+            "store i1 true, ",
+        ] {
+            assert!(ir.contains(expected), "{} not in {}", expected, ir);
+        }
+    }
+
+    #[test]
+    fn test_if_else_codegen() {
+        let ir = generate_source("fn f(c: bool): i32 { if c { return 1; } return 2; }");
+        assert!(ir.contains("label %then, label %else"), "{}", ir);
+        assert!(ir.contains("then:") && ir.contains("ret i32 1"), "{}", ir);
+        // the missing else falls through to the code after the if
+        assert!(ir.contains("else:                                             ; preds = %entry\n  br label %ifcont"), "{}", ir);
+        assert!(ir.contains("ifcont:") && ir.contains("ret i32 2"), "{}", ir);
+    }
+
+    #[test]
+    fn test_if_else_chain_codegen() {
+        let source = "fn sign(n: i32): i64 { if n < 0 { -1 } else if n == 0 { 0 } else { 1 } }\n\
+                      fn pick(b: bool): u8 { let x: u8 = if b { 3 } else { 4 }; x }\n\
+                      fn clamp(n: i32): i32 { if n > 9 { return 9; } else { print(n); } n }";
+        let ir = generate_source(source);
+        for expected in [
+            // the inner if of an else-if chain flows into the outer phi
+            "%iftmp = phi i64 [ 0, %then3 ], [ 1, %else4 ]",
+            "phi i64 [ -1, %then ], [ %iftmp, %ifcont5 ]",
+            "%iftmp = phi i8 [ 3, %then ], [ 4, %else ]",
+            // a branch that returns does not also jump to the merge block
+            "then:                                             ; preds = %entry\n  ret i32 9\n",
+        ] {
+            assert!(ir.contains(expected), "{} not in {}", expected, ir);
+        }
+    }
+
+    #[test]
+    fn test_while_codegen() {
+        let source = "fn sum(n: i32): i32 {\n\
+                      let mut i = 0; let mut total = 0;\n\
+                      while i < n { let step = 1; i = i + step; if i == 3 { continue; } if total > 100 { break; } total = total + i; }\n\
+                      total }\n\
+                      fn spin(): void { while true { while false {} break; } }";
+        let ir = generate_source(source);
+        for expected in [
+            "br i1 %lt, label %whilebody, label %whilecont",
+            "%eq = icmp eq i32",
+            // the nested loop gets blocks of its own
+            "br i1 false, label %whilebody2, label %whilecont3",
+        ] {
+            assert!(ir.contains(expected), "{} not in {}", expected, ir);
+        }
+        // `continue` re-evaluates the condition, `break` leaves the loop,
+        // and the end of the body loops back
+        let sum = &ir[ir.find("@sum").unwrap()..ir.find("@spin").unwrap()];
+        assert_eq!(sum.matches("br label %whilecond\n").count(), 3, "{}", sum);
+        assert_eq!(sum.matches("br label %whilecont\n").count(), 1, "{}", sum);
+        // a local of the body is allocated once, before the loop
+        assert!(sum.find("%step = alloca").unwrap() < sum.find("whilecond:").unwrap(), "{}", sum);
+    }
+
+    #[test]
+    fn test_bare_return_codegen() {
+        let ir = generate_source("fn log(c: bool): void { if c { return; } }");
+        assert!(ir.contains("define void @log(i1 %0)"), "{}", ir);
+        assert_eq!(ir.matches("ret void").count(), 2, "{}", ir);
+    }
+
+    #[test]
+    fn test_if_expression_codegen() {
+        let ir = generate_source("fn f(c: bool): i32 { if c { 1 } else { 2 } }");
+        assert!(ir.contains("phi i32 [ 1, %then ], [ 2, %else ]"), "{}", ir);
+    }
+
+    #[test]
+    fn test_conditional_codegen() {
+        let ir = generate_source("fn f(c: bool): i32 { if c then 1 else 2 }");
+        assert!(ir.contains("phi i32 [ 1, %then ], [ 2, %else ]"), "{}", ir);
+    }
+
+    #[test]
+    fn test_power_codegen() {
+        let ir = generate_source("fn f(x: f64, n: i32): i32 { let root = x ** 0.5; 2 ** n }");
+        assert!(ir.contains("call double @llvm.pow.f64(double %x1, double 5.000000e-01)"), "{}", ir);
+        // integers are multiplied out, never rounded through a double
+        assert!(ir.contains("powcond:"), "{}", ir);
+        assert!(ir.contains("call void @llvm.trap()"), "{}", ir);
+        assert!(!ir.contains("fptosi"), "{}", ir);
+
+        let ir = generate_source(
+            "fn f(x: f32, n: i32, u: u8, k: u32): u8 { let a = x ** 0.5; let b = x ** n; let c = x ** k; u ** k }",
+        );
+        assert!(ir.contains("call float @llvm.pow.f32(float %x1, float 5.000000e-01)"), "{}", ir);
+        assert!(ir.contains("call float @llvm.powi.f32"), "{}", ir);
+        // an unsigned exponent is never negative, so needs no check
+        assert!(ir.contains("powmul = mul i8"), "{}", ir);
+        assert!(!ir.contains("@llvm.trap"), "{}", ir);
+        assert!(!ir.contains("uitofp") && !ir.contains("fptoui"), "{}", ir);
+    }
+
+    #[test]
+    fn test_block_tail_codegen() {
+        let ir = generate_source("fn main(): i32 { let a = { 7 }; { a } }");
+        assert!(ir.contains("store i32 7, ptr %a"), "{}", ir);
+        assert!(ir.contains("ret i32 %a"), "{}", ir);
+    }
+
+    #[test]
+    fn test_typed_literal_codegen() {
+        let ir = generate_source("fn main(): i32 { let wide: i64 = 1; let narrow: f32 = -0.5; 0 }");
+        assert!(ir.contains("store i64 1, ptr %wide"), "{}", ir);
+        assert!(ir.contains("store float -5.000000e-01, ptr %narrow"), "{}", ir);
+    }
+
+    #[test]
+    fn test_cast_codegen() {
+        let ir = generate_source(
+            "fn f(a: i8, u: u8): i32 { let wide = a as i64; let zero = u as u32; let narrow = wide as i16; \
+             let real = u as f32; let double = real as f64; let back = double as u16; a as i32 }",
+        );
+        for instruction in [
+            "sext i8 %a1 to i64",
+            "zext i8 %u2 to i32",
+            "trunc i64 %wide3 to i16",
+            "uitofp i8 %u4 to float",
+            "fpext float %real5 to double",
+            "fptoui double %double6 to i16",
+            "sext i8 %a7 to i32",
+        ] {
+            assert!(ir.contains(instruction), "{} missing from\n{}", instruction, ir);
+        }
+    }
+
+    #[test]
+    fn test_assignment_codegen() {
+        let ir = generate_source("fn main(): i32 { let mut x = 1; x = 2; x }");
+        // the new value goes to the variable's slot, not a fresh one
+        assert_eq!(ir.matches("%x = alloca i32").count(), 1, "{}", ir);
+        assert!(ir.contains("store i32 1, ptr %x, align 4\n  store i32 2, ptr %x, align 4\n  %x1 = load i32, ptr %x"), "{}", ir);
+        assert!(ir.contains("ret i32 %x1"), "{}", ir);
+    }
+
+    #[test]
+    fn test_array_codegen() {
+        let ir = generate_source("fn main(): i32 { let a = [4, 2]; a[1] }");
+        assert!(ir.contains("store i32 2, ptr %element_ptr1"), "{}", ir);
+        assert!(ir.contains("getelementptr inbounds [2 x i32], ptr %a, i32 0, i32 1"), "{}", ir);
+    }
+
+    #[test]
+    fn test_struct_codegen() {
+        let ir = generate_source("struct Point { x: i32, y: i32 } fn main(): i32 { let p = Point { y: 2, x: 1 }; p.y }");
+        assert!(ir.contains("%Point = type { i32, i32 }"), "{}", ir);
+        assert!(ir.contains("getelementptr inbounds %Point, ptr %p, i32 0, i32 1"), "{}", ir);
+
+        // literals stored into fields and elements take the declared widths
+        let ir = generate_source(
+            "struct Pixel { shade: u8, weight: f32 }\n\
+             fn f(): i64 { let p = Pixel { shade: 200, weight: 0.5 }; let e: [u8; 0] = []; let a: [i64; 2] = [1, 2]; a[1] }",
+        );
+        assert!(ir.contains("store i8 -56"), "{}", ir);
+        assert!(ir.contains("store float 5.000000e-01"), "{}", ir);
+        assert!(ir.contains("alloca [2 x i64]") && ir.contains("store i64 2"), "{}", ir);
+        assert!(ir.contains("store [0 x i8] zeroinitializer"), "{}", ir);
+    }
+
+    #[test]
+    fn test_signatures_name_types_declared_anywhere() {
+        let ir = generate_source(
+            "fn get(p: P): i32 { p.x }\n\
+             fn pick(): C { C::B }\n\
+             fn main(): M { get(P { x: 1, next: Q { y: 2 } }) }\n\
+             type M = i32; struct Q { y: M } struct P { x: i32, next: Q } enum C { A, B }",
+        );
+        for expected in ["%P = type { i32, %Q }", "%Q = type { i32 }", "define i32 @get(%P", "define i32 @pick()", "ret i32 1"] {
+            assert!(ir.contains(expected), "{} not in {}", expected, ir);
+        }
+    }
+
+    #[test]
+    fn test_enum_codegen() {
+        let ir = generate_source("enum Color { Red, Green, Blue } fn pick(): Color { Color::Blue }");
+        // a variant is its index
+        assert!(ir.contains("define i32 @pick()"), "{}", ir);
+        assert!(ir.contains("ret i32 2"), "{}", ir);
+    }
+
+    #[test]
+    fn test_nested_field_access_codegen() {
+        let ir = generate_source(
+            "struct Point { x: i32, y: i32 } struct Line { start: Point, end: Point }\n\
+             fn main(): i32 { let line = Line { start: Point { x: 1, y: 2 }, end: Point { x: 3, y: 4 } }; line.end.y }",
+        );
+        assert!(ir.contains("%Line = type { %Point, %Point }"), "{}", ir);
+        // `line.end.y` addresses straight into `line` rather than copying `line.end`
+        assert!(!ir.contains("%tmp"), "{}", ir);
+        assert!(ir.contains("getelementptr inbounds %Point, ptr %end7, i32 0, i32 1"), "{}", ir);
+    }
+
+    #[test]
+    fn test_closure_codegen() {
+        let ir = generate_source("fn main(): i32 { let f = |x: i32| -x; 0 }");
+        assert!(ir.contains("define private i32 @closure(i32 %0)"), "{}", ir);
+        assert!(ir.contains("store ptr @closure, ptr %f"), "{}", ir);
+
+        // the checker rejects a capture first, but codegen refuses one too
+        let ast = crate::parser::Parser::from_source("fn main(): i32 { let y = 1; let f = |x: i32| y; 0 }")
+            .unwrap()
+            .parse()
+            .unwrap();
+        let context = Context::create();
+        assert_eq!(
+            CodeGen::new(&context).generate(&ast),
+            Err("Closures cannot capture variables yet: y at line 1, column 29".to_string())
+        );
+    }
+
+    #[test]
+    fn test_closure_calls() {
+        let ir = generate_source(
+            "fn apply(f: fn(i32) -> i32, x: i32): i32 { f(x) } \
+             fn main(): i32 { let g = |x: i32| x + 1; apply(g, 2) + g(2) }",
+        );
+        assert!(ir.contains("define private i32 @closure(i32 %0)"), "{}", ir);
+        // both calls go through a pointer loaded from the variable
+        assert_eq!(ir.matches("call i32 %").count(), 2, "{}", ir);
+        assert!(ir.contains("call i32 @apply("), "{}", ir);
+
+        // the checker works out what a closure returns
+        let ir = generate_source("fn main(): i32 { let f = |x: bool| !x; if f(false) { 1 } else { 0 } }");
+        assert!(ir.contains("define private i1 @closure(i1 %0)"), "{}", ir);
+    }
+
+    #[test]
+    fn test_logical_codegen() {
+        let ir = generate_source("fn f(a: bool, b: i32): bool { a && b > 0 } fn g(a: bool, b: bool): bool { a || b }");
+        assert_eq!(ir.matches("phi i1 [ false, %entry ]").count(), 1, "{}", ir);
+        assert_eq!(ir.matches("phi i1 [ true, %entry ]").count(), 1, "{}", ir);
+        // the right operand is evaluated in a block of its own
+        assert!(ir.contains("icmp sgt i32 %b2, 0\n  br label %logiccont"), "{}", ir);
+
+        // a chain joins the phi of the inner operator
+        let ir = generate_source("fn f(a: bool, b: bool, c: bool): bool { a && b || c }");
+        assert_eq!(ir.matches("phi i1").count(), 2, "{}", ir);
+        assert!(ir.contains("[ true, %logiccont ]"), "{}", ir);
+    }
+
+    #[test]
+    fn test_generic_functions_are_skipped() {
+        let source = "fn id<T>(x: T): T { x } fn main(): i32 { 0 }";
+        let ir = generate_source(source);
+        assert!(!ir.contains("@id"), "{}", ir);
+
+        let source = "fn id<T>(x: T): T { x } fn main(): i32 { id(1) }";
+        let ast = crate::parser::Parser::from_source(source).unwrap().parse().unwrap();
+        crate::typecheck::TypeChecker::new().check(&ast).unwrap();
+        let context = Context::create();
+        assert_eq!(
+            CodeGen::new(&context).generate(&ast),
+            Err("Generic function id cannot be called by codegen yet at line 1, column 42".to_string())
+        );
+        assert_eq!(
+            CodeGen::new(&context).llvm_type(&Type::Param("T".to_string())),
+            Err("Type T is not supported by codegen yet".to_string())
+        );
+    }
+
+    #[test]
+    fn test_type_alias_codegen() {
+        let context = Context::create();
+        let mut codegen = CodeGen::new(&context);
+        codegen.generate(&AstNode::TypeAlias { name: "Meters".to_string(), target: Type::I64, doc: None }).unwrap();
+        codegen.generate(&AstNode::TypeAlias {
+            name: "Path".to_string(),
+            target: Type::array(Type::Struct("Meters".to_string()), 2),
+            doc: None,
+        }).unwrap();
+        let path = codegen.llvm_type(&Type::Struct("Path".to_string())).unwrap();
+        assert_eq!(path, context.i64_type().array_type(2).into());
+
+        let looped = AstNode::TypeAlias { name: "A".to_string(), target: Type::Struct("A".to_string()), doc: None };
+        assert!(codegen.generate(&looped).is_err());
+    }
+
+    #[test]
+    fn test_const_codegen() {
+        let ir = generate_source("const MAX: i32 = 100; const MIN: i32 = -MAX; fn main(): i32 { MIN }");
+        assert!(ir.contains("@MAX = constant i32 100"), "{}", ir);
+        assert!(ir.contains("@MIN = constant i32 -100"), "{}", ir);
+        assert!(ir.contains("ret i32 -100"), "{}", ir);
+    }
+
+    #[test]
+    fn test_folded_const_codegen() {
+        let context = Context::create();
+        let mut codegen = CodeGen::new(&context);
+        let binary = |op, left: AstNode, right: AstNode| AstNode::BinaryOp {
+            op,
+            left: Box::new(left.into()),
+            right: Box::new(right.into()),
+        };
+        let ast = AstNode::Program(vec![
+            AstNode::Const {
+                name: "SIDE".to_string(),
+                type_annotation: Type::I64,
+                value: Box::new(binary(BinaryOperator::Add, AstNode::Number(1), AstNode::Number(2)).into()),
+                doc: None,
+            }.into(),
+            AstNode::Const {
+                name: "AREA".to_string(),
+                type_annotation: Type::I64,
+                value: Box::new(binary(
+                    BinaryOperator::Power,
+                    AstNode::Identifier("SIDE".to_string()),
+                    AstNode::Number(2),
+                ).into()),
+                doc: None,
+            }.into(),
+        ]);
+        codegen.generate(&ast).unwrap();
+        let ir = codegen.module.print_to_string().to_string();
+        assert!(ir.contains("@AREA = constant i64 9"), "{}", ir);
+
+        let zero = AstNode::Const {
+            name: "BAD".to_string(),
+            type_annotation: Type::I32,
+            value: Box::new(binary(BinaryOperator::Divide, AstNode::Number(1), AstNode::Number(0)).into()),
+            doc: None,
+        };
+        assert_eq!(codegen.generate(&zero), Err("Division by zero in constant expression".to_string()));
+    }
+
+    #[test]
+    fn test_checked_constants_are_propagated() {
+        let source = "const N: i64 = 4;\nfn main(): i32 { let wide = N * N - 1; return 2 + 3; }";
+        let ir = generate_source(source);
+        assert!(ir.contains("store i64 15"), "{}", ir);
+        assert!(ir.contains("ret i32 5"), "{}", ir);
+        // the global is still emitted, but never read
+        assert_eq!(ir.matches("@N").count(), 1, "{}", ir);
+    }
 }
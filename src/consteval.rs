@@ -0,0 +1,250 @@
+use std::collections::HashMap;
+
+use crate::lexer::Span;
+use crate::parser::{AstNode, BinaryOperator, Spanned, UnaryOperator};
+use crate::types::Type;
+
+/// The value of an expression that was folded at compile time.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ConstValue {
+    /// Wide enough for every value of every integer type, so that overflow
+    /// can be detected by checking the result against the type's range.
+    Int(i128),
+    Float(f64),
+    Bool(bool),
+    Char(char),
+    String(String),
+}
+
+/// Why a constant expression could not be folded, with the span of the
+/// node that failed; the default span for trees not built by the parser.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConstError {
+    pub message: String,
+    pub span: Span,
+}
+
+impl std::fmt::Display for ConstError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.span == Span::default() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{} at {}", self.message, self.span)
+        }
+    }
+}
+
+impl From<ConstError> for String {
+    fn from(error: ConstError) -> Self {
+        error.to_string()
+    }
+}
+
+/// Folds a constant expression, such as the value of a `const`, looking up
+/// identifiers in `constants`. Integer arithmetic is done at the width the
+/// type checker annotated the node with, or `i32` for unchecked nodes, and
+/// division by zero and overflow are errors rather than run-time traps.
+pub fn evaluate(node: &Spanned<AstNode>, constants: &HashMap<String, ConstValue>) -> Result<ConstValue, ConstError> {
+    let value = match &node.node {
+        AstNode::Number(n) => ConstValue::Int(*n as i128),
+        AstNode::Float(n) => ConstValue::Float(*n),
+        AstNode::Boolean(b) => ConstValue::Bool(*b),
+        AstNode::Char(c) => ConstValue::Char(*c),
+        AstNode::StringLiteral(s) => ConstValue::String(s.clone()),
+        AstNode::Identifier(name) => constants.get(name)
+            .cloned()
+            .ok_or_else(|| locate(format!("{} is not a constant", name), node.span))?,
+        AstNode::UnaryOp { op, operand } => match (op, evaluate(operand, constants)?) {
+            (UnaryOperator::Negate, ConstValue::Int(v)) => ConstValue::Int(-v),
+            (UnaryOperator::Negate, ConstValue::Float(v)) => ConstValue::Float(-v),
+            (UnaryOperator::Not, ConstValue::Bool(v)) => ConstValue::Bool(!v),
+            (op, value) => return Err(locate(format!("Cannot apply {:?} to {:?}", op, value), node.span)),
+        },
+        AstNode::BinaryOp { op, left, right } => {
+            let left = evaluate(left, constants)?;
+            let right = evaluate(right, constants)?;
+            binary(op, left, right).map_err(|error| locate(error, node.span))?
+        },
+        AstNode::Cast { value, target } => {
+            // the checker resolves an alias to the type it names, and a cast
+            // always fits its target
+            let target = node.ty().unwrap_or_else(|| target.clone());
+            return cast(evaluate(value, constants)?, &target).map_err(|error| locate(error, node.span));
+        },
+        _ => return Err(locate("Expected a constant expression".to_string(), node.span)),
+    };
+
+    if let ConstValue::Int(v) = value {
+        let ty = node.ty().unwrap_or(Type::I32);
+        if let Some((min, max)) = ty.integer_range() {
+            if v < min || v > max {
+                return Err(locate(format!("Constant expression overflows {}: {}", ty, v), node.span));
+            }
+        }
+    }
+    Ok(value)
+}
+
+fn binary(op: &BinaryOperator, left: ConstValue, right: ConstValue) -> Result<ConstValue, String> {
+    use ConstValue::*;
+    // comparisons hold for any pair of same-kind values
+    if op.is_comparison() {
+        let ordering = match (&left, &right) {
+            (Int(l), Int(r)) => l.partial_cmp(r),
+            (Float(l), Float(r)) => l.partial_cmp(r),
+            (Bool(l), Bool(r)) => l.partial_cmp(r),
+            (Char(l), Char(r)) => l.partial_cmp(r),
+            (String(l), String(r)) => l.partial_cmp(r),
+            _ => return Err(format!("Cannot compare {:?} with {:?}", left, right)),
+        };
+        let holds = ordering.is_some_and(|ordering| match op {
+            BinaryOperator::Eq => ordering.is_eq(),
+            BinaryOperator::Ne => ordering.is_ne(),
+            BinaryOperator::Lt => ordering.is_lt(),
+            BinaryOperator::Le => ordering.is_le(),
+            BinaryOperator::Gt => ordering.is_gt(),
+            _ => ordering.is_ge(),
+        });
+        // NaN is unequal to everything, itself included
+        return Ok(Bool(holds || (ordering.is_none() && *op == BinaryOperator::Ne)));
+    }
+
+    match (op, left, right) {
+        (BinaryOperator::And, Bool(l), Bool(r)) => Ok(Bool(l && r)),
+        (BinaryOperator::Or, Bool(l), Bool(r)) => Ok(Bool(l || r)),
+        (BinaryOperator::Divide, Int(_), Int(0)) => Err("Division by zero in constant expression".to_string()),
+        (BinaryOperator::Power, Int(_), Int(r)) if r < 0 => {
+            Err(format!("Negative exponent {} in integer constant expression", r))
+        },
+        (op, Int(l), Int(r)) => {
+            let result = match op {
+                BinaryOperator::Add => l.checked_add(r),
+                BinaryOperator::Subtract => l.checked_sub(r),
+                BinaryOperator::Multiply => l.checked_mul(r),
+                BinaryOperator::Divide => l.checked_div(r),
+                BinaryOperator::Power => u32::try_from(r).ok().and_then(|r| l.checked_pow(r)),
+                _ => return Err(format!("Cannot apply {:?} to integers", op)),
+            };
+            result.map(Int).ok_or_else(|| "Constant expression overflows".to_string())
+        },
+        (BinaryOperator::Add, String(l), String(r)) => Ok(String(l + &r)),
+        (BinaryOperator::Power, Float(l), Int(r)) => match i32::try_from(r) {
+            Ok(r) => Ok(Float(l.powi(r))),
+            Err(_) => Err(format!("Exponent {} of a float constant expression does not fit in i32", r)),
+        },
+        (op, Float(l), Float(r)) => match op {
+            BinaryOperator::Add => Ok(Float(l + r)),
+            BinaryOperator::Subtract => Ok(Float(l - r)),
+            BinaryOperator::Multiply => Ok(Float(l * r)),
+            BinaryOperator::Divide => Ok(Float(l / r)),
+            BinaryOperator::Power => Ok(Float(l.powf(r))),
+            _ => Err(format!("Cannot apply {:?} to floats", op)),
+        },
+        (op, left, right) => Err(format!("Cannot apply {:?} to {:?} and {:?}", op, left, right)),
+    }
+}
+
+/// Converts `value` to the numeric type `target` as the generated code
+/// does: an integer wraps around to the target's width, and a float is
+/// rounded toward zero, which is an error when the result doesn't fit, as
+/// the program's result would be undefined.
+fn cast(value: ConstValue, target: &Type) -> Result<ConstValue, String> {
+    use ConstValue::*;
+    let narrow = |v: f64| if *target == Type::F32 { v as f32 as f64 } else { v };
+    match (value, target.integer_range()) {
+        (Int(v), Some((min, max))) => Ok(Int((v - min).rem_euclid(max - min + 1) + min)),
+        (Float(v), Some((min, max))) => {
+            let truncated = v.trunc();
+            if truncated.is_nan() || truncated < min as f64 || truncated > max as f64 {
+                return Err(format!("Cannot cast {} to {}, which it does not fit in", v, target));
+            }
+            Ok(Int(truncated as i128))
+        },
+        (Int(v), None) if target.is_float() => Ok(Float(narrow(v as f64))),
+        (Float(v), None) if target.is_float() => Ok(Float(narrow(v))),
+        (value, _) if !target.is_numeric() => Ok(value),
+        (value, _) => Err(format!("Cannot cast {:?} to {}", value, target)),
+    }
+}
+
+fn locate(message: String, span: Span) -> ConstError {
+    ConstError { message, span }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(source: &str) -> Result<ConstValue, String> {
+        let ast = crate::parser::Parser::from_source(&format!("const X: i32 = {};", source)).unwrap().parse().unwrap();
+        let AstNode::Program(items) = ast else { panic!("Expected program node") };
+        let AstNode::Const { value, .. } = &items[0].node else { panic!("Expected constant") };
+        evaluate(value, &HashMap::new()).map_err(|error| error.to_string())
+    }
+
+    #[test]
+    fn test_folds_literal_arithmetic() {
+        assert_eq!(eval("1 + 2 * 3"), Ok(ConstValue::Int(7)));
+        assert_eq!(eval("-7 / 2"), Ok(ConstValue::Int(-3)));
+        assert_eq!(eval("2 ** 10"), Ok(ConstValue::Int(1024)));
+        assert_eq!(eval("-2 ** 2"), Ok(ConstValue::Int(-4)));
+        assert_eq!(eval("(-2) ** 3"), Ok(ConstValue::Int(-8)));
+        assert_eq!(eval("1.5 * 2.0"), Ok(ConstValue::Float(3.0)));
+        assert_eq!(eval("1 < 2 && !false"), Ok(ConstValue::Bool(true)));
+        assert_eq!(eval("'a' == 'b'"), Ok(ConstValue::Bool(false)));
+        assert_eq!(eval("\"ab\" + \"c\""), Ok(ConstValue::String("abc".to_string())));
+    }
+
+    #[test]
+    fn test_reports_division_by_zero_and_overflow() {
+        assert_eq!(eval("1 / (2 - 2)"), Err("Division by zero in constant expression at line 1, column 16".to_string()));
+        assert_eq!(eval("2147483647 + 1"), Err("Constant expression overflows i32: 2147483648 at line 1, column 16".to_string()));
+        assert_eq!(eval("-2147483647 - 1"), Ok(ConstValue::Int(i32::MIN as i128)));
+        assert_eq!(eval("2.0 ** -2"), Ok(ConstValue::Float(0.25)));
+        assert_eq!(
+            eval("1e10 as i32"),
+            Err("Cannot cast 10000000000 to i32, which it does not fit in at line 1, column 16".to_string())
+        );
+
+        let exponent = Spanned::from(AstNode::Number(5_000_000_000));
+        exponent.set_ty(Type::I64);
+        let power = Spanned::from(AstNode::BinaryOp {
+            op: BinaryOperator::Power,
+            left: Box::new(AstNode::Float(1.0).into()),
+            right: Box::new(exponent),
+        });
+        assert_eq!(
+            evaluate(&power, &HashMap::new()).map_err(|error| error.to_string()),
+            Err("Exponent 5000000000 of a float constant expression does not fit in i32".to_string())
+        );
+    }
+
+    #[test]
+    fn test_folds_casts() {
+        assert_eq!(eval("300 as u8"), Ok(ConstValue::Int(44)));
+        assert_eq!(eval("-1 as u16"), Ok(ConstValue::Int(65535)));
+        assert_eq!(eval("255 as u8 as i8"), Ok(ConstValue::Int(-1)));
+        assert_eq!(eval("-1 as u64"), Ok(ConstValue::Int(u64::MAX as i128)));
+        assert_eq!(eval("-2.9 as i8"), Ok(ConstValue::Int(-2)));
+        assert_eq!(eval("7 as f64 / 2.0"), Ok(ConstValue::Float(3.5)));
+        assert_eq!(eval("0.1 as f32"), Ok(ConstValue::Float(0.1f32 as f64)));
+        assert_eq!(eval("'a' as char"), Ok(ConstValue::Char('a')));
+    }
+
+    #[test]
+    fn test_looks_up_constants() {
+        let constants = HashMap::from([("MAX".to_string(), ConstValue::Int(10))]);
+        let node = Spanned::from(AstNode::BinaryOp {
+            op: BinaryOperator::Multiply,
+            left: Box::new(AstNode::Identifier("MAX".to_string()).into()),
+            right: Box::new(AstNode::Number(2).into()),
+        });
+        assert_eq!(evaluate(&node, &constants), Ok(ConstValue::Int(20)));
+        let unknown = Spanned::from(AstNode::Identifier("MIN".to_string()));
+        assert_eq!(
+            evaluate(&unknown, &constants),
+            Err(ConstError { message: "MIN is not a constant".to_string(), span: Span::default() })
+        );
+    }
+}
@@ -0,0 +1,531 @@
+use crate::consteval::ConstValue;
+use crate::lexer::Span;
+use crate::parser::{AstNode, BinaryOperator, Spanned, UnaryOperator};
+use crate::types::{Type, TypeEnvironment};
+
+/// A checked program, lowered from the AST. Every expression carries the
+/// type the checker gave it, every type is resolved, and the AST's sugar
+/// is gone: method calls are plain calls, both forms of `if` are one node,
+/// operations on strings are calls of the runtime helpers in `runtime`,
+/// constant expressions are the literals the checker folded them to, and
+/// aliases, traits and imports, which only matter to the checker, are
+/// dropped. It is a view of the program for tools to inspect; codegen still
+/// generates from the checked AST, so a lowering here does not change the
+/// code that is compiled.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Program {
+    pub items: Vec<Item>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Item {
+    Function(Function),
+    Const {
+        name: String,
+        ty: Type,
+        value: Expr,
+    },
+    /// A top-level `let`.
+    Global {
+        name: String,
+        mutable: bool,
+        ty: Type,
+        value: Expr,
+    },
+    Struct {
+        name: String,
+        fields: Vec<(String, Type)>,
+    },
+    Enum {
+        name: String,
+        variants: Vec<(String, Vec<Type>)>,
+    },
+    /// The methods of `impl Trait for target`, called by static dispatch
+    /// on the type of their first argument.
+    Impl {
+        trait_name: String,
+        target: Type,
+        methods: Vec<Function>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Function {
+    pub name: String,
+    /// Names of the type parameters; a generic function's types mention
+    /// them as `Type::Param`.
+    pub type_params: Vec<String>,
+    pub params: Vec<(String, Type)>,
+    pub return_type: Type,
+    pub body: Expr,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Expr {
+    pub kind: ExprKind,
+    /// `Type::Void` for statements.
+    pub ty: Type,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ExprKind {
+    Literal(ConstValue),
+    FormatString(Vec<Expr>),
+    Variable(String),
+    Unary {
+        op: UnaryOperator,
+        operand: Box<Expr>,
+    },
+    /// Converts the value to the type of the cast expression.
+    Cast(Box<Expr>),
+    Binary {
+        op: BinaryOperator,
+        left: Box<Expr>,
+        right: Box<Expr>,
+    },
+    /// A call of a function or closure by name; a method call has its
+    /// receiver as the first argument.
+    Call {
+        function: String,
+        args: Vec<Expr>,
+    },
+    Closure {
+        params: Vec<(String, Type)>,
+        body: Box<Expr>,
+    },
+    Array(Vec<Expr>),
+    Index {
+        array: Box<Expr>,
+        index: Box<Expr>,
+    },
+    Struct {
+        name: String,
+        fields: Vec<(String, Expr)>,
+    },
+    Field {
+        object: Box<Expr>,
+        field: String,
+    },
+    Variant {
+        enum_name: String,
+        variant: String,
+        args: Vec<Expr>,
+    },
+    /// Both `if cond { .. } else { .. }` and `if cond then a else b`.
+    If {
+        cond: Box<Expr>,
+        then_branch: Box<Expr>,
+        else_branch: Option<Box<Expr>>,
+    },
+    Match {
+        scrutinee: Box<Expr>,
+        arms: Vec<(Pattern, Expr)>,
+    },
+    /// Statements whose values are discarded, then the optional tail
+    /// expression that is the value of the block.
+    Block {
+        statements: Vec<Expr>,
+        tail: Option<Box<Expr>>,
+    },
+    /// A local `let`, or a local `const`, which binds the same way.
+    Let {
+        name: String,
+        mutable: bool,
+        value: Box<Expr>,
+    },
+    Assign {
+        name: String,
+        value: Box<Expr>,
+    },
+    While {
+        cond: Box<Expr>,
+        body: Box<Expr>,
+    },
+    Break,
+    Continue,
+    Return(Option<Box<Expr>>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Pattern {
+    Wildcard,
+    Literal(ConstValue),
+    /// Each binding comes with the type of the payload value it names.
+    Variant {
+        enum_name: String,
+        variant: String,
+        bindings: Vec<(String, Type)>,
+    },
+}
+
+/// The functions the runtime provides for operations that LLVM has no
+/// instruction for, as they are named in calls in the HIR and in the code
+/// generated for the operations.
+pub mod runtime {
+    /// `(a: string, b: string): string`, a new string holding `a` then `b`.
+    pub const STRING_CONCAT: &str = "nova_string_concat";
+    /// `(a: string, b: string): bool`, whether `a` and `b` hold the same text.
+    pub const STRING_EQUALS: &str = "nova_string_equals";
+}
+
+/// Lowers a program that has been checked, using the environment the
+/// checker built to resolve types. Fails on a node the checker never
+/// annotated, which means the program was not checked first.
+pub fn lower(program: &AstNode, env: &TypeEnvironment) -> Result<Program, String> {
+    let AstNode::Program(nodes) = program else {
+        return Err("Expected a program".to_string());
+    };
+    let lowering = Lowering { env };
+    let mut items = Vec::new();
+    for node in nodes {
+        if let Some(item) = lowering.item(node)? {
+            items.push(item);
+        }
+    }
+    Ok(Program { items })
+}
+
+struct Lowering<'env> {
+    env: &'env TypeEnvironment,
+}
+
+impl Lowering<'_> {
+    fn item(&self, node: &Spanned<AstNode>) -> Result<Option<Item>, String> {
+        let item = match &node.node {
+            AstNode::Function { .. } => Item::Function(self.function(node)?),
+            AstNode::Const { name, type_annotation, value, .. } => Item::Const {
+                name: name.clone(),
+                ty: self.env.resolve(type_annotation),
+                value: self.expr(value)?,
+            },
+            AstNode::Let { name, mutable, value, .. } => {
+                let value = self.expr(value)?;
+                Item::Global { name: name.clone(), mutable: *mutable, ty: value.ty.clone(), value }
+            },
+            AstNode::StructDef { name, .. } => Item::Struct {
+                name: name.clone(),
+                fields: self.env.get_struct(name).unwrap_or_default().to_vec(),
+            },
+            AstNode::EnumDef { name, .. } => Item::Enum {
+                name: name.clone(),
+                variants: self.env.get_enum(name).unwrap_or_default().to_vec(),
+            },
+            AstNode::Impl { trait_name, target, methods, .. } => Item::Impl {
+                trait_name: trait_name.clone(),
+                target: self.env.resolve(target),
+                methods: methods.iter().map(|method| self.function(method)).collect::<Result<_, _>>()?,
+            },
+            AstNode::TypeAlias { .. } | AstNode::TraitDef { .. } | AstNode::Import(_) | AstNode::Module(_) => {
+                return Ok(None);
+            },
+            _ => return Err(locate("Expected declaration".to_string(), node.span)),
+        };
+        Ok(Some(item))
+    }
+
+    fn function(&self, node: &Spanned<AstNode>) -> Result<Function, String> {
+        let AstNode::Function { name, type_params, params, return_type, body, .. } = &node.node else {
+            return Err(locate("Expected function".to_string(), node.span));
+        };
+        Ok(Function {
+            name: name.clone(),
+            type_params: type_params.iter().map(|(name, _)| name.clone()).collect(),
+            params: self.params(params),
+            return_type: self.env.resolve(return_type),
+            body: self.expr(body)?,
+            span: node.span,
+        })
+    }
+
+    fn params(&self, params: &[(String, Type)]) -> Vec<(String, Type)> {
+        params.iter().map(|(name, ty)| (name.clone(), self.env.resolve(ty))).collect()
+    }
+
+    /// Lowers a statement, whose type is `Void` whatever it evaluates to.
+    fn statement(&self, node: &Spanned<AstNode>) -> Result<Expr, String> {
+        let kind = match &node.node {
+            AstNode::ExpressionStatement(expr) => return self.expr(expr),
+            AstNode::Let { name, mutable, value, .. } => ExprKind::Let {
+                name: name.clone(),
+                mutable: *mutable,
+                value: Box::new(self.expr(value)?),
+            },
+            AstNode::Const { name, value, .. } => ExprKind::Let {
+                name: name.clone(),
+                mutable: false,
+                value: Box::new(self.expr(value)?),
+            },
+            AstNode::Assign { name, value } => ExprKind::Assign { name: name.clone(), value: Box::new(self.expr(value)?) },
+            AstNode::While { cond, body } => ExprKind::While {
+                cond: Box::new(self.expr(cond)?),
+                body: Box::new(self.expr(body)?),
+            },
+            AstNode::Break => ExprKind::Break,
+            AstNode::Continue => ExprKind::Continue,
+            AstNode::Return(value) => {
+                ExprKind::Return(value.as_deref().map(|value| self.expr(value).map(Box::new)).transpose()?)
+            },
+            _ if node.is_expression() => return self.expr(node),
+            _ => return Err(locate("Declarations cannot be lowered inside a block".to_string(), node.span)),
+        };
+        Ok(Expr { kind, ty: Type::Void, span: node.span })
+    }
+
+    fn expr(&self, node: &Spanned<AstNode>) -> Result<Expr, String> {
+        if !node.is_expression() {
+            return self.statement(node);
+        }
+        let ty = node.ty().ok_or_else(|| locate("Expression was not type checked".to_string(), node.span))?;
+        if let Some(value) = node.folded() {
+            return Ok(Expr { kind: ExprKind::Literal(value), ty, span: node.span });
+        }
+        let kind = match &node.node {
+            AstNode::Number(_)
+            | AstNode::Float(_)
+            | AstNode::Char(_)
+            | AstNode::StringLiteral(_)
+            | AstNode::Boolean(_) => ExprKind::Literal(literal(&node.node).expect("a literal node")),
+            AstNode::FormatString { parts } => ExprKind::FormatString(self.exprs(parts)?),
+            AstNode::Identifier(name) => ExprKind::Variable(name.clone()),
+            AstNode::UnaryOp { op, operand } => ExprKind::Unary { op: op.clone(), operand: Box::new(self.expr(operand)?) },
+            AstNode::Cast { value, .. } => ExprKind::Cast(Box::new(self.expr(value)?)),
+            AstNode::BinaryOp { op, left, right } if left.ty() == Some(Type::String) => {
+                self.string_operation(op, left, right, node.span)?
+            },
+            AstNode::BinaryOp { op, left, right } => ExprKind::Binary {
+                op: op.clone(),
+                left: Box::new(self.expr(left)?),
+                right: Box::new(self.expr(right)?),
+            },
+            AstNode::Call { callee, args } => ExprKind::Call { function: callee.clone(), args: self.exprs(args)? },
+            AstNode::MethodCall { receiver, method, args } => {
+                let mut lowered = vec![self.expr(receiver)?];
+                lowered.extend(self.exprs(args)?);
+                ExprKind::Call { function: method.clone(), args: lowered }
+            },
+            AstNode::Closure { params, body, .. } => ExprKind::Closure {
+                params: self.params(params),
+                body: Box::new(self.expr(body)?),
+            },
+            AstNode::ArrayLiteral(elements) => ExprKind::Array(self.exprs(elements)?),
+            AstNode::Index { array, index } => ExprKind::Index {
+                array: Box::new(self.expr(array)?),
+                index: Box::new(self.expr(index)?),
+            },
+            AstNode::StructLiteral { name, fields } => ExprKind::Struct {
+                name: name.clone(),
+                fields: fields.iter()
+                    .map(|(field, value)| Ok((field.clone(), self.expr(value)?)))
+                    .collect::<Result<_, String>>()?,
+            },
+            AstNode::FieldAccess { object, field } => ExprKind::Field {
+                object: Box::new(self.expr(object)?),
+                field: field.clone(),
+            },
+            AstNode::EnumVariant { enum_name, variant, args } => ExprKind::Variant {
+                enum_name: enum_name.clone(),
+                variant: variant.clone(),
+                args: self.exprs(args)?,
+            },
+            AstNode::If { cond, then_block, else_block } => ExprKind::If {
+                cond: Box::new(self.expr(cond)?),
+                then_branch: Box::new(self.expr(then_block)?),
+                else_branch: else_block.as_deref().map(|block| self.expr(block).map(Box::new)).transpose()?,
+            },
+            AstNode::Conditional { cond, then_value, else_value } => ExprKind::If {
+                cond: Box::new(self.expr(cond)?),
+                then_branch: Box::new(self.expr(then_value)?),
+                else_branch: Some(Box::new(self.expr(else_value)?)),
+            },
+            AstNode::Match { scrutinee, arms } => {
+                let scrutinee = self.expr(scrutinee)?;
+                let arms = arms.iter()
+                    .map(|(pattern, body)| Ok((self.pattern(&pattern.node, &scrutinee.ty), self.expr(body)?)))
+                    .collect::<Result<_, String>>()?;
+                ExprKind::Match { scrutinee: Box::new(scrutinee), arms }
+            },
+            AstNode::Program(nodes) => {
+                let mut statements = nodes.iter().map(|node| self.statement(node)).collect::<Result<Vec<_>, _>>()?;
+                // a block is only as valuable as its trailing expression
+                let tail = match nodes.last() {
+                    Some(last) if last.is_expression() => statements.pop().map(Box::new),
+                    _ => None,
+                };
+                ExprKind::Block { statements, tail }
+            },
+            _ => return Err(locate("Expected expression".to_string(), node.span)),
+        };
+        Ok(Expr { kind, ty, span: node.span })
+    }
+
+    /// Lowers `+`, `==` or `!=` on strings to a call of the runtime helper
+    /// that implements it; `a != b` is `!nova_string_equals(a, b)`.
+    fn string_operation(
+        &self,
+        op: &BinaryOperator,
+        left: &Spanned<AstNode>,
+        right: &Spanned<AstNode>,
+        span: Span,
+    ) -> Result<ExprKind, String> {
+        let args = vec![self.expr(left)?, self.expr(right)?];
+        let call = |function: &str, args| ExprKind::Call { function: function.to_string(), args };
+        Ok(match op {
+            BinaryOperator::Add => call(runtime::STRING_CONCAT, args),
+            BinaryOperator::Eq => call(runtime::STRING_EQUALS, args),
+            BinaryOperator::Ne => ExprKind::Unary {
+                op: UnaryOperator::Not,
+                operand: Box::new(Expr { kind: call(runtime::STRING_EQUALS, args), ty: Type::Bool, span }),
+            },
+            _ => return Err(locate(format!("Strings have no runtime helper for {:?}", op), span)),
+        })
+    }
+
+    fn exprs(&self, nodes: &[Spanned<AstNode>]) -> Result<Vec<Expr>, String> {
+        nodes.iter().map(|node| self.expr(node)).collect()
+    }
+
+    fn pattern(&self, pattern: &crate::parser::Pattern, scrutinee: &Type) -> Pattern {
+        match pattern {
+            crate::parser::Pattern::Wildcard => Pattern::Wildcard,
+            crate::parser::Pattern::Literal(value) => literal(value).map_or(Pattern::Wildcard, Pattern::Literal),
+            crate::parser::Pattern::Variant { enum_name, variant, bindings } => {
+                let payload = self.env.variants(scrutinee)
+                    .and_then(|variants| variants.into_iter().find(|(name, _)| name == variant))
+                    .map(|(_, payload)| payload)
+                    .unwrap_or_default();
+                Pattern::Variant {
+                    enum_name: enum_name.clone(),
+                    variant: variant.clone(),
+                    bindings: bindings.iter().cloned().zip(payload).collect(),
+                }
+            },
+        }
+    }
+}
+
+fn literal(node: &AstNode) -> Option<ConstValue> {
+    Some(match node {
+        AstNode::Number(n) => ConstValue::Int(*n as i128),
+        AstNode::Float(n) => ConstValue::Float(*n),
+        AstNode::Char(c) => ConstValue::Char(*c),
+        AstNode::StringLiteral(s) => ConstValue::String(s.clone()),
+        AstNode::Boolean(b) => ConstValue::Bool(*b),
+        _ => return None,
+    })
+}
+
+fn locate(error: String, span: Span) -> String {
+    if span == Span::default() {
+        error
+    } else {
+        format!("{} at {}", error, span)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::typecheck::TypeChecker;
+
+    fn lower_source(source: &str) -> Result<Program, String> {
+        let ast = crate::parser::Parser::from_source(source).unwrap().parse()?;
+        let mut checker = TypeChecker::new();
+        checker.check(&ast).map_err(|error| error.summary())?;
+        checker.lower(&ast)
+    }
+
+    #[test]
+    fn test_lowers_typed_functions() {
+        let program = lower_source("type Meters = i64;\nfn twice(m: Meters): Meters { let d = m * 2; d }").unwrap();
+        let [Item::Function(function)] = program.items.as_slice() else {
+            panic!("Expected a single function, got {:?}", program.items);
+        };
+        assert_eq!(function.params, [("m".to_string(), Type::I64)]);
+        assert_eq!(function.return_type, Type::I64);
+        let ExprKind::Block { statements, tail: Some(tail) } = &function.body.kind else {
+            panic!("Expected a block with a tail");
+        };
+        let ExprKind::Let { value, .. } = &statements[0].kind else { panic!("Expected let") };
+        assert_eq!(statements[0].ty, Type::Void);
+        assert_eq!(value.ty, Type::I64);
+        // the literal took on the type of the other operand
+        let ExprKind::Binary { right, .. } = &value.kind else { panic!("Expected binary operation") };
+        assert_eq!((&right.kind, &right.ty), (&ExprKind::Literal(ConstValue::Int(2)), &Type::I64));
+        assert_eq!((&tail.kind, &tail.ty), (&ExprKind::Variable("d".to_string()), &Type::I64));
+    }
+
+    #[test]
+    fn test_desugars_methods_and_conditionals() {
+        let source = "trait Show { fn show(self): string; }\nstruct P { x: i32 }\n\
+                      impl Show for P { fn show(self): string { if self.x > 0 then \"+\" else \"-\" } }\n\
+                      fn main(): i32 { let p = P { x: 1 }; p.show(); 0 }";
+        let program = lower_source(source).unwrap();
+        assert!(matches!(&program.items[0], Item::Struct { name, fields } if name == "P" && fields == &[("x".to_string(), Type::I32)]));
+        let Item::Impl { methods, .. } = &program.items[1] else { panic!("Expected impl") };
+        let ExprKind::Block { tail: Some(tail), .. } = &methods[0].body.kind else { panic!("Expected block") };
+        assert!(matches!(&tail.kind, ExprKind::If { else_branch: Some(_), .. }));
+
+        let Item::Function(main) = &program.items[2] else { panic!("Expected main") };
+        let ExprKind::Block { statements, .. } = &main.body.kind else { panic!("Expected block") };
+        let ExprKind::Call { function, args } = &statements[1].kind else { panic!("Expected call") };
+        assert_eq!(function, "show");
+        assert_eq!(args[0].ty, Type::Struct("P".to_string()));
+    }
+
+    #[test]
+    fn test_string_operations_call_runtime_helpers() {
+        let program = lower_source("fn f(a: string, b: string): bool { let c = a + b; c != \"\" }").unwrap();
+        let [Item::Function(function)] = program.items.as_slice() else { panic!("Expected a function") };
+        let ExprKind::Block { statements, tail: Some(tail) } = &function.body.kind else { panic!("Expected block") };
+        let ExprKind::Let { value, .. } = &statements[0].kind else { panic!("Expected let") };
+        let ExprKind::Call { function, args } = &value.kind else { panic!("Expected call") };
+        assert_eq!((function.as_str(), &value.ty), (runtime::STRING_CONCAT, &Type::String));
+        assert_eq!(args[1].kind, ExprKind::Variable("b".to_string()));
+        let ExprKind::Unary { op: UnaryOperator::Not, operand } = &tail.kind else { panic!("Expected not") };
+        assert!(matches!(&operand.kind, ExprKind::Call { function, .. } if function == runtime::STRING_EQUALS));
+        assert_eq!(operand.ty, Type::Bool);
+    }
+
+    #[test]
+    fn test_constant_expressions_are_literals() {
+        let source = "const GREETING: string = \"hello, \" + \"world\";\nfn f(): bool { GREETING == \"\" || 2 > 1 }";
+        let program = lower_source(source).unwrap();
+        let Item::Const { value, .. } = &program.items[0] else { panic!("Expected constant") };
+        assert_eq!(value.kind, ExprKind::Literal(ConstValue::String("hello, world".to_string())));
+        let Item::Function(function) = &program.items[1] else { panic!("Expected a function") };
+        let ExprKind::Block { tail: Some(tail), .. } = &function.body.kind else { panic!("Expected block") };
+        assert_eq!((&tail.kind, &tail.ty), (&ExprKind::Literal(ConstValue::Bool(true)), &Type::Bool));
+    }
+
+    #[test]
+    fn test_pattern_bindings_are_typed() {
+        let program = lower_source("fn f(x: Option<u8>): u8 { match x { Option::Some(n) => n, Option::None => 0 } }").unwrap();
+        let [Item::Function(function)] = program.items.as_slice() else { panic!("Expected a function") };
+        let ExprKind::Block { tail: Some(tail), .. } = &function.body.kind else { panic!("Expected block") };
+        let ExprKind::Match { arms, .. } = &tail.kind else { panic!("Expected match") };
+        assert_eq!(arms[0].0, Pattern::Variant {
+            enum_name: "Option".to_string(),
+            variant: "Some".to_string(),
+            bindings: vec![("n".to_string(), Type::U8)],
+        });
+        assert_eq!(arms[1].1.kind, ExprKind::Literal(ConstValue::Int(0)));
+        assert_eq!(arms[1].1.ty, Type::U8);
+    }
+
+    #[test]
+    fn test_requires_a_checked_program() {
+        let ast = crate::parser::Parser::from_source("fn f(): i32 { 1 }").unwrap().parse().unwrap();
+        assert_eq!(
+            lower(&ast, &TypeEnvironment::new()),
+            Err("Expression was not type checked at line 1, column 13".to_string())
+        );
+    }
+}
@@ -1,7 +1,8 @@
-use logos::Logos;
+use logos::{FilterResult, Logos};
 use std::fmt;
+use thiserror::Error;
 
-#[derive(Logos, Debug, PartialEq)]
+#[derive(Logos, Debug, Clone, PartialEq)]
 pub enum Token {
     #[token("fn")]
     Function,
@@ -9,18 +10,79 @@ pub enum Token {
     #[token("let")]
     Let,
 
+    #[token("mut")]
+    Mut,
+
+    #[token("const")]
+    Const,
+
     #[token("return")]
     Return,
 
     #[token("if")]
     If,
 
-    #[regex("[A-Za-z][A-Za-z0-9_]*", |lex| String::from(lex.slice()))]
+    #[token("else")]
+    Else,
+
+    #[token("then")]
+    Then,
+
+    #[token("while")]
+    While,
+
+    #[token("for")]
+    For,
+
+    #[token("break")]
+    Break,
+
+    #[token("continue")]
+    Continue,
+
+    #[token("struct")]
+    Struct,
+
+    #[token("enum")]
+    Enum,
+
+    #[token("type")]
+    Type,
+
+    #[token("match")]
+    Match,
+
+    #[token("impl")]
+    Impl,
+
+    #[token("trait")]
+    Trait,
+
+    #[token("as")]
+    As,
+
+    #[token("import")]
+    Import,
+
+    #[token("module")]
+    Module,
+
+    #[regex(r"[_\p{XID_Start}]\p{XID_Continue}*", |lex| String::from(lex.slice()))]
     Identifier(String),
 
-    #[regex(r"[0-9]+", |lex| lex.slice().parse().ok())]
+    #[regex(r"[0-9][0-9_]*", |lex| parse_integer(lex.slice(), 10))]
+    #[regex(r"0[xX][0-9a-fA-F_]+", |lex| parse_integer(&lex.slice()[2..], 16))]
+    #[regex(r"0[bB][01_]+", |lex| parse_integer(&lex.slice()[2..], 2))]
+    #[regex(r"0[oO][0-7_]+", |lex| parse_integer(&lex.slice()[2..], 8))]
     Number(i64),
 
+    #[regex(r"[0-9]+\.[0-9]+([eE][+-]?[0-9]+)?", |lex| lex.slice().parse().ok())]
+    #[regex(r"[0-9]+[eE][+-]?[0-9]+", |lex| lex.slice().parse().ok())]
+    FloatLiteral(f64),
+
+    #[regex(r"'(\\[^\n]|[^'\\\n])[^'\n]*'", |lex| parse_char(lex.slice()))]
+    CharLiteral(char),
+
     #[token("(")]
     LeftParen,
 
@@ -33,15 +95,45 @@ pub enum Token {
     #[token("}")]
     RightBrace,
 
+    #[token("[")]
+    LeftBracket,
+
+    #[token("]")]
+    RightBracket,
+
     #[token(";")]
     Semicolon,
 
     #[token("=")]
     Equals,
 
+    #[token("i8")]
+    TypeI8,
+
+    #[token("i16")]
+    TypeI16,
+
     #[token("i32")]
     TypeInt,
 
+    #[token("i64")]
+    TypeI64,
+
+    #[token("u8")]
+    TypeU8,
+
+    #[token("u16")]
+    TypeU16,
+
+    #[token("u32")]
+    TypeU32,
+
+    #[token("u64")]
+    TypeU64,
+
+    #[token("f32")]
+    TypeF32,
+
     #[token("f64")]
     TypeFloat,
 
@@ -51,6 +143,9 @@ pub enum Token {
     #[token("string")]
     TypeString,
 
+    #[token("void")]
+    TypeVoid,
+
     #[token("+")]
     Plus,
 
@@ -63,54 +158,581 @@ pub enum Token {
     #[token("/")]
     Divide,
 
+    #[token("+=")]
+    PlusEquals,
+
+    #[token("-=")]
+    MinusEquals,
+
+    #[token("*=")]
+    MultiplyEquals,
+
+    #[token("/=")]
+    DivideEquals,
+
+    #[token("**")]
+    Power,
+
+    #[token("==")]
+    EqualEqual,
+
+    #[token("!=")]
+    NotEqual,
+
+    #[token("<")]
+    LessThan,
+
+    #[token("<=")]
+    LessEqual,
+
+    #[token(">")]
+    GreaterThan,
+
+    #[token(">=")]
+    GreaterEqual,
+
+    #[token("&&")]
+    And,
+
+    #[token("||")]
+    Or,
+
+    #[token("!")]
+    Not,
+
+    #[token("&")]
+    BitAnd,
+
+    #[token("|")]
+    BitOr,
+
+    #[token("^")]
+    BitXor,
+
+    #[token("<<")]
+    ShiftLeft,
+
+    #[token(">>")]
+    ShiftRight,
+
+    #[token("~")]
+    BitNot,
+
     #[token(":")]
     Colon,
 
+    #[token("::")]
+    DoubleColon,
+
+    #[token("->")]
+    Arrow,
+
+    #[token("=>")]
+    FatArrow,
+
+    #[token(".")]
+    Dot,
+
+    #[token("..")]
+    DotDot,
+
+    #[token("..=")]
+    DotDotEqual,
+
     #[token("true")]
     True,
 
     #[token("false")]
     False,
 
-    #[regex(r#""[^"]*""#)]
-    StringLiteral,
+    #[regex(r#""([^"\\]|\\.)*""#, |lex| unescape(&lex.slice()[1..lex.slice().len() - 1]))]
+    StringLiteral(String),
+
+    /// `f"x = {x}"`: literal text interleaved with embedded expressions.
+    #[regex(r#"f"([^"\\]|\\.)*""#, format_string)]
+    FormatString(Vec<FormatSegment>),
 
     #[token(",")]
     Comma,
 
+    #[token("?")]
+    Question,
+
+    #[regex(r"///[^\n]*", |lex| doc_text(lex.slice()))]
+    DocComment(String),
+
     #[error]
     #[regex(r"[ \t\n\f]+", logos::skip)]
+    #[regex(r"//[^\n]*", logos::skip)]
+    #[token("/*", block_comment)]
+    #[token("*/")]
     Error,
 }
 
+/// A piece of a format string: decoded literal text, or the tokens of an
+/// expression written between `{` and `}`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FormatSegment {
+    Text(String),
+    Code(Vec<(Token, Span)>),
+}
+
+/// Location of a token in the source: the byte range it covers plus the
+/// 1-based line and column where it starts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    /// The span running from the start of `self` to the end of `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            end: other.end,
+            ..self
+        }
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
+/// A problem found while lexing, reported with the position it starts at.
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum LexError {
+    #[error("unexpected character {found:?} at {span}")]
+    UnexpectedCharacter { found: char, span: Span },
+
+    #[error("invalid literal `{text}` at {span}")]
+    InvalidLiteral { text: String, span: Span },
+
+    #[error("unterminated block comment at {span}")]
+    UnterminatedComment { span: Span },
+
+    #[error("unmatched '*/' at {span}")]
+    UnmatchedCommentClose { span: Span },
+}
+
+impl LexError {
+    fn from_slice(text: &str, span: Span) -> Self {
+        if text.starts_with("/*") {
+            LexError::UnterminatedComment { span }
+        } else if text == "*/" {
+            LexError::UnmatchedCommentClose { span }
+        } else if text.chars().count() > 1 {
+            LexError::InvalidLiteral { text: text.to_string(), span }
+        } else {
+            let found = text.chars().next().unwrap_or_default();
+            LexError::UnexpectedCharacter { found, span }
+        }
+    }
+}
+
+/// Lexes `source` into tokens paired with their spans. Every `Error` token is
+/// turned into a `LexError`, so callers never see `Token::Error`.
+pub fn tokenize(source: &str) -> Result<Vec<(Token, Span)>, Vec<LexError>> {
+    let mut tokens = Vec::new();
+    let mut errors = Vec::new();
+    let mut line = 1;
+    let mut line_start = 0;
+    let mut scanned = 0;
+
+    for (token, range) in Token::lexer(source).spanned() {
+        for (offset, c) in source[scanned..range.start].char_indices() {
+            if c == '\n' {
+                line += 1;
+                line_start = scanned + offset + 1;
+            }
+        }
+        scanned = range.start;
+
+        let span = Span {
+            start: range.start,
+            end: range.end,
+            line,
+            column: source[line_start..range.start].chars().count() + 1,
+        };
+        match token {
+            Token::Error => errors.push(LexError::from_slice(&source[range], span)),
+            Token::FormatString(mut segments) => {
+                for segment in &mut segments {
+                    if let FormatSegment::Code(code) = segment {
+                        for (_, nested) in code.iter_mut() {
+                            relocate(source, nested);
+                        }
+                    }
+                }
+                tokens.push((Token::FormatString(segments), span));
+            },
+            token => tokens.push((token, span)),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(tokens)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Cursor over lexed tokens used by the parser. Looking past the last token
+/// yields `None`, so running out of input is reported like any other
+/// unexpected token instead of panicking.
+pub struct TokenStream {
+    tokens: Vec<(Token, Span)>,
+    position: usize,
+}
+
+impl TokenStream {
+    pub fn new(tokens: Vec<(Token, Span)>) -> Self {
+        TokenStream { tokens, position: 0 }
+    }
+
+    pub fn peek(&self) -> Option<&Token> {
+        self.peek_n(0)
+    }
+
+    /// Looks `n` tokens ahead without consuming anything; `peek_n(0)` is `peek()`.
+    pub fn peek_n(&self, n: usize) -> Option<&Token> {
+        self.tokens.get(self.position + n).map(|(token, _)| token)
+    }
+
+    /// Span of the next token, or of the last token once the input is exhausted.
+    pub fn span(&self) -> Span {
+        self.tokens.get(self.position)
+            .or_else(|| self.tokens.last())
+            .map(|(_, span)| *span)
+            .unwrap_or_default()
+    }
+
+    /// Span of the most recently consumed token.
+    pub fn previous_span(&self) -> Span {
+        self.position.checked_sub(1)
+            .and_then(|i| self.tokens.get(i))
+            .map(|(_, span)| *span)
+            .unwrap_or_default()
+    }
+
+    pub fn is_at_end(&self) -> bool {
+        self.position >= self.tokens.len()
+    }
+
+    pub fn advance(&mut self) -> Option<Token> {
+        let token = self.peek().cloned();
+        if token.is_some() {
+            self.position += 1;
+        }
+        token
+    }
+
+    /// Whether the next token has the same kind as `kind`. Payloads are not
+    /// compared, so `Token::Identifier(String::new())` matches any identifier.
+    pub fn check(&self, kind: &Token) -> bool {
+        self.peek().is_some_and(|token| std::mem::discriminant(token) == std::mem::discriminant(kind))
+    }
+
+    /// Consumes the next token if it has the same kind as `kind`.
+    pub fn eat(&mut self, kind: &Token) -> bool {
+        let matched = self.check(kind);
+        if matched {
+            self.position += 1;
+        }
+        matched
+    }
+
+    /// Consumes the `>` closing a list of type arguments. A `>>`, as in
+    /// `Option<Option<i32>>`, closes two lists, so it is split in place and
+    /// its second `>` left for the enclosing list.
+    pub fn eat_closing_angle(&mut self) -> bool {
+        match self.tokens.get_mut(self.position) {
+            Some((token @ Token::ShiftRight, span)) => {
+                *token = Token::GreaterThan;
+                span.start += 1;
+                span.column += 1;
+                true
+            },
+            _ => self.eat(&Token::GreaterThan),
+        }
+    }
+
+    /// Consumes a token of the given kind and returns its span, or fails with
+    /// `message` and the current position.
+    pub fn expect(&mut self, kind: &Token, message: &str) -> Result<Span, String> {
+        let span = self.span();
+        if self.eat(kind) {
+            Ok(span)
+        } else {
+            Err(self.error(message))
+        }
+    }
+
+    /// Formats an error message with the position of the next token. Once the
+    /// input is exhausted there is no next token, so `message` (an
+    /// "Expected ..." phrase) is reported as what the input stopped short of.
+    pub fn error(&self, message: &str) -> String {
+        if self.is_at_end() {
+            let expected = message.strip_prefix("Expected ").unwrap_or(message);
+            return format!("unexpected end of input, expected {}", expected);
+        }
+        format!("{} at {}", message, self.span())
+    }
+}
+
+/// Parses the digits of an integer literal in the given radix, ignoring `_`
+/// separators. Returns `None` (an `Error` token) on overflow or when no digits
+/// remain, e.g. `0x_`.
+fn parse_integer(digits: &str, radix: u32) -> Option<i64> {
+    let digits: String = digits.chars().filter(|c| *c != '_').collect();
+    i64::from_str_radix(&digits, radix).ok()
+}
+
+/// Decodes the backslash escapes `\n`, `\t`, `\r`, `\0`, `\\`, `\'`, `\"` and
+/// `\u{...}`. Returns `None` for an unknown or malformed escape.
+fn unescape(raw: &str) -> Option<String> {
+    let mut out = String::with_capacity(raw.len());
+    let mut chars = raw.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        let decoded = match chars.next()? {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            '0' => '\0',
+            '\\' => '\\',
+            '\'' => '\'',
+            '"' => '"',
+            'u' => {
+                if chars.next()? != '{' {
+                    return None;
+                }
+                let mut hex = String::new();
+                loop {
+                    match chars.next()? {
+                        '}' => break,
+                        digit => hex.push(digit),
+                    }
+                }
+                char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?
+            },
+            _ => return None,
+        };
+        out.push(decoded);
+    }
+
+    Some(out)
+}
+
+/// Splits the body of `f"..."` into segments. `{{` and `}}` stand for literal
+/// braces; anything else between braces is lexed as an expression, with spans
+/// shifted to byte offsets in the enclosing source.
+fn format_string(lex: &mut logos::Lexer<Token>) -> Option<Vec<FormatSegment>> {
+    let slice = lex.slice();
+    let body_start = 2;
+    let body = &slice[body_start..slice.len() - 1];
+    let mut segments = Vec::new();
+    let mut text = String::new();
+    let mut chars = body.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '{' if chars.next_if(|(_, c)| *c == '{').is_some() => text.push('{'),
+            '}' if chars.next_if(|(_, c)| *c == '}').is_some() => text.push('}'),
+            '}' => return None,
+            '{' => {
+                if !text.is_empty() {
+                    segments.push(FormatSegment::Text(unescape(&std::mem::take(&mut text))?));
+                }
+                let mut depth = 1;
+                let end = loop {
+                    match chars.next()? {
+                        (_, '{') => depth += 1,
+                        (j, '}') if depth == 1 => break j,
+                        (_, '}') => depth -= 1,
+                        _ => {},
+                    }
+                };
+                let offset = lex.span().start + body_start + i + 1;
+                let mut code = tokenize(&body[i + 1..end]).ok()?;
+                for (_, span) in &mut code {
+                    span.start += offset;
+                    span.end += offset;
+                }
+                segments.push(FormatSegment::Code(code));
+            },
+            c => text.push(c),
+        }
+    }
+
+    if !text.is_empty() {
+        segments.push(FormatSegment::Text(unescape(&text)?));
+    }
+    Some(segments)
+}
+
+/// Recomputes the line and column of a span from its byte offset.
+fn relocate(source: &str, span: &mut Span) {
+    let before = &source[..span.start];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    span.line = before.matches('\n').count() + 1;
+    span.column = source[line_start..span.start].chars().count() + 1;
+}
+
+/// Decodes a quoted character literal, which must hold exactly one character.
+fn parse_char(slice: &str) -> Option<char> {
+    let decoded = unescape(&slice[1..slice.len() - 1])?;
+    let mut chars = decoded.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => None,
+    }
+}
+
+/// Strips the `///` marker and the single space conventionally following it.
+fn doc_text(slice: &str) -> String {
+    let text = &slice[3..];
+    text.strip_prefix(' ').unwrap_or(text).trim_end().to_string()
+}
+
+/// Skips a `/* ... */` comment, honouring nested `/*` openers. An unterminated
+/// comment consumes the rest of the input and is reported as a single `Error`.
+fn block_comment(lex: &mut logos::Lexer<Token>) -> FilterResult<()> {
+    let mut depth = 1;
+    let mut chars = lex.remainder().char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        match (c, chars.peek()) {
+            ('/', Some((_, '*'))) => {
+                chars.next();
+                depth += 1;
+            },
+            ('*', Some((_, '/'))) => {
+                chars.next();
+                depth -= 1;
+                if depth == 0 {
+                    lex.bump(i + 2);
+                    return FilterResult::Skip;
+                }
+            },
+            _ => {},
+        }
+    }
+
+    lex.bump(lex.remainder().len());
+    FilterResult::Error
+}
+
 impl fmt::Display for Token {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Token::Function => write!(f, "fn"),
             Token::Let => write!(f, "let"),
+            Token::Mut => write!(f, "mut"),
+            Token::Const => write!(f, "const"),
             Token::Return => write!(f, "return"),
             Token::If => write!(f, "if"),
+            Token::Else => write!(f, "else"),
+            Token::Then => write!(f, "then"),
+            Token::While => write!(f, "while"),
+            Token::For => write!(f, "for"),
+            Token::Break => write!(f, "break"),
+            Token::Continue => write!(f, "continue"),
+            Token::Struct => write!(f, "struct"),
+            Token::Enum => write!(f, "enum"),
+            Token::Type => write!(f, "type"),
+            Token::Match => write!(f, "match"),
+            Token::Impl => write!(f, "impl"),
+            Token::Trait => write!(f, "trait"),
+            Token::As => write!(f, "as"),
+            Token::Import => write!(f, "import"),
+            Token::Module => write!(f, "module"),
             Token::Identifier(s) => write!(f, "{}", s),
             Token::Number(n) => write!(f, "{}", n),
+            Token::FloatLiteral(n) => write!(f, "{:?}", n),
+            Token::CharLiteral(c) => write!(f, "{:?}", c),
             Token::LeftParen => write!(f, "("),
             Token::RightParen => write!(f, ")"),
             Token::LeftBrace => write!(f, "{{"),
             Token::RightBrace => write!(f, "}}"),
+            Token::LeftBracket => write!(f, "["),
+            Token::RightBracket => write!(f, "]"),
             Token::Semicolon => write!(f, ";"),
             Token::Equals => write!(f, "="),
+            Token::TypeI8 => write!(f, "i8"),
+            Token::TypeI16 => write!(f, "i16"),
             Token::TypeInt => write!(f, "i32"),
+            Token::TypeI64 => write!(f, "i64"),
+            Token::TypeU8 => write!(f, "u8"),
+            Token::TypeU16 => write!(f, "u16"),
+            Token::TypeU32 => write!(f, "u32"),
+            Token::TypeU64 => write!(f, "u64"),
+            Token::TypeF32 => write!(f, "f32"),
             Token::TypeFloat => write!(f, "f64"),
             Token::TypeBool => write!(f, "bool"),
             Token::TypeString => write!(f, "string"),
+            Token::TypeVoid => write!(f, "void"),
             Token::Plus => write!(f, "+"),
             Token::Minus => write!(f, "-"),
             Token::Multiply => write!(f, "*"),
             Token::Divide => write!(f, "/"),
+            Token::PlusEquals => write!(f, "+="),
+            Token::MinusEquals => write!(f, "-="),
+            Token::MultiplyEquals => write!(f, "*="),
+            Token::DivideEquals => write!(f, "/="),
+            Token::EqualEqual => write!(f, "=="),
+            Token::NotEqual => write!(f, "!="),
+            Token::LessThan => write!(f, "<"),
+            Token::LessEqual => write!(f, "<="),
+            Token::GreaterThan => write!(f, ">"),
+            Token::GreaterEqual => write!(f, ">="),
+            Token::And => write!(f, "&&"),
+            Token::Or => write!(f, "||"),
+            Token::Not => write!(f, "!"),
+            Token::BitAnd => write!(f, "&"),
+            Token::BitOr => write!(f, "|"),
+            Token::BitXor => write!(f, "^"),
+            Token::Power => write!(f, "**"),
+            Token::ShiftLeft => write!(f, "<<"),
+            Token::ShiftRight => write!(f, ">>"),
+            Token::BitNot => write!(f, "~"),
             Token::Colon => write!(f, ":"),
+            Token::DoubleColon => write!(f, "::"),
+            Token::Arrow => write!(f, "->"),
+            Token::FatArrow => write!(f, "=>"),
+            Token::Dot => write!(f, "."),
+            Token::DotDot => write!(f, ".."),
+            Token::DotDotEqual => write!(f, "..="),
             Token::True => write!(f, "true"),
             Token::False => write!(f, "false"),
-            Token::StringLiteral => write!(f, "string literal"),
+            Token::StringLiteral(s) => write!(f, "\"{}\"", s.escape_debug()),
+            Token::FormatString(segments) => {
+                write!(f, "f\"")?;
+                for segment in segments {
+                    match segment {
+                        FormatSegment::Text(text) => {
+                            write!(f, "{}", text.escape_debug().to_string().replace('{', "{{").replace('}', "}}"))?
+                        },
+                        FormatSegment::Code(tokens) => {
+                            let tokens: Vec<String> = tokens.iter().map(|(token, _)| token.to_string()).collect();
+                            write!(f, "{{{}}}", tokens.join(" "))?
+                        },
+                    }
+                }
+                write!(f, "\"")
+            },
             Token::Comma => write!(f, ","),
+            Token::Question => write!(f, "?"),
+            Token::DocComment(text) => write!(f, "/// {}", text),
             Token::Error => write!(f, "error"),
         }
     }
@@ -134,4 +756,415 @@ mod tests {
         assert_eq!(lexer.next(), Some(Token::LeftParen));
         assert_eq!(lexer.next(), Some(Token::RightParen));
     }
+
+    #[test]
+    fn test_block_comments() {
+        let tokens: Vec<Token> = Token::lexer("let /* outer /* inner */ still comment */ x").collect();
+        assert_eq!(tokens, vec![Token::Let, Token::Identifier("x".to_string())]);
+
+        let tokens: Vec<Token> = Token::lexer("let /* never /* closed */ x").collect();
+        assert_eq!(tokens, vec![Token::Let, Token::Error]);
+    }
+
+    #[test]
+    fn test_float_literals() {
+        let tokens: Vec<Token> = Token::lexer("2.5 1.5e3 2E-2 7").collect();
+        assert_eq!(tokens, vec![
+            Token::FloatLiteral(2.5),
+            Token::FloatLiteral(1500.0),
+            Token::FloatLiteral(0.02),
+            Token::Number(7),
+        ]);
+    }
+
+    #[test]
+    fn test_integer_radixes() {
+        let tokens: Vec<Token> = Token::lexer("0xFF 0b1010 0o777 1_000_000 0x_").collect();
+        assert_eq!(tokens, vec![
+            Token::Number(255),
+            Token::Number(10),
+            Token::Number(511),
+            Token::Number(1_000_000),
+            Token::Error,
+        ]);
+    }
+
+    #[test]
+    fn test_char_literals() {
+        let tokens: Vec<Token> = Token::lexer(r"'a' '\n' '\'' '\u{e9}' 'ab'").collect();
+        assert_eq!(tokens, vec![
+            Token::CharLiteral('a'),
+            Token::CharLiteral('\n'),
+            Token::CharLiteral('\''),
+            Token::CharLiteral('é'),
+            Token::Error,
+        ]);
+    }
+
+    #[test]
+    fn test_string_escapes() {
+        let tokens: Vec<Token> = Token::lexer(r#""a\tb\n" "say \"hi\"" "\\" "\u{1F600}" "\q""#).collect();
+        assert_eq!(tokens, vec![
+            Token::StringLiteral("a\tb\n".to_string()),
+            Token::StringLiteral("say \"hi\"".to_string()),
+            Token::StringLiteral("\\".to_string()),
+            Token::StringLiteral("\u{1F600}".to_string()),
+            Token::Error,
+        ]);
+    }
+
+    #[test]
+    fn test_format_strings() {
+        let tokens = tokenize("let s = f\"x = {p.x + 1}, {{literal}}\\n\";").unwrap();
+        match &tokens[3].0 {
+            Token::FormatString(segments) => {
+                assert_eq!(segments.len(), 3);
+                assert_eq!(segments[0], FormatSegment::Text("x = ".to_string()));
+                match &segments[1] {
+                    FormatSegment::Code(code) => {
+                        let code: Vec<&Token> = code.iter().map(|(token, _)| token).collect();
+                        assert_eq!(code, vec![
+                            &Token::Identifier("p".to_string()),
+                            &Token::Dot,
+                            &Token::Identifier("x".to_string()),
+                            &Token::Plus,
+                            &Token::Number(1),
+                        ]);
+                    },
+                    other => panic!("Expected embedded code, got {:?}", other),
+                }
+                assert_eq!(segments[2], FormatSegment::Text(", {literal}\n".to_string()));
+            },
+            other => panic!("Expected format string, got {:?}", other),
+        }
+
+        let errors = tokenize("f\"unmatched }\" f\"{1 +\"").unwrap_err();
+        assert_eq!(errors.len(), 2);
+    }
+
+    #[test]
+    fn test_format_string_spans() {
+        let tokens = tokenize("let a = 1;\nlet s = f\"a is {a}\";").unwrap();
+        match &tokens[8].0 {
+            Token::FormatString(segments) => match &segments[1] {
+                FormatSegment::Code(code) => {
+                    assert_eq!(code[0].1, Span { start: 27, end: 28, line: 2, column: 17 });
+                },
+                other => panic!("Expected embedded code, got {:?}", other),
+            },
+            other => panic!("Expected format string, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_comparison_operators() {
+        let tokens: Vec<Token> = Token::lexer("a == b != c < d <= e > f >= g = h").collect();
+        let ops: Vec<Token> = tokens.into_iter()
+            .filter(|t| !matches!(t, Token::Identifier(_)))
+            .collect();
+        assert_eq!(ops, vec![
+            Token::EqualEqual,
+            Token::NotEqual,
+            Token::LessThan,
+            Token::LessEqual,
+            Token::GreaterThan,
+            Token::GreaterEqual,
+            Token::Equals,
+        ]);
+    }
+
+    #[test]
+    fn test_logical_operators() {
+        let tokens: Vec<Token> = Token::lexer("!a && b || !=").collect();
+        assert_eq!(tokens, vec![
+            Token::Not,
+            Token::Identifier("a".to_string()),
+            Token::And,
+            Token::Identifier("b".to_string()),
+            Token::Or,
+            Token::NotEqual,
+        ]);
+    }
+
+    #[test]
+    fn test_bitwise_operators() {
+        let tokens: Vec<Token> = Token::lexer("& && | || ^ << <= >> >= ~").collect();
+        assert_eq!(tokens, vec![
+            Token::BitAnd,
+            Token::And,
+            Token::BitOr,
+            Token::Or,
+            Token::BitXor,
+            Token::ShiftLeft,
+            Token::LessEqual,
+            Token::ShiftRight,
+            Token::GreaterEqual,
+            Token::BitNot,
+        ]);
+
+        let rendered: Vec<String> = tokens.iter().map(|t| t.to_string()).collect();
+        assert_eq!(rendered.join(" "), "& && | || ^ << <= >> >= ~");
+    }
+
+    #[test]
+    fn test_compound_assignment() {
+        let tokens: Vec<Token> = Token::lexer("x += 1; x -= 2; x *= 3; x /= 4;").collect();
+        let ops: Vec<Token> = tokens.into_iter()
+            .filter(|t| !matches!(t, Token::Identifier(_) | Token::Number(_) | Token::Semicolon))
+            .collect();
+        assert_eq!(ops, vec![
+            Token::PlusEquals,
+            Token::MinusEquals,
+            Token::MultiplyEquals,
+            Token::DivideEquals,
+        ]);
+    }
+
+    #[test]
+    fn test_power_operator() {
+        let tokens: Vec<Token> = Token::lexer("a ** b * c *= d").collect();
+        assert_eq!(tokens, vec![
+            Token::Identifier("a".to_string()),
+            Token::Power,
+            Token::Identifier("b".to_string()),
+            Token::Multiply,
+            Token::Identifier("c".to_string()),
+            Token::MultiplyEquals,
+            Token::Identifier("d".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_arrow() {
+        let tokens: Vec<Token> = Token::lexer("-> - > -= => ==").collect();
+        assert_eq!(
+            tokens,
+            vec![Token::Arrow, Token::Minus, Token::GreaterThan, Token::MinusEquals, Token::FatArrow, Token::EqualEqual]
+        );
+    }
+
+    #[test]
+    fn test_control_flow_keywords() {
+        let tokens: Vec<Token> = Token::lexer("else then while for break continue").collect();
+        assert_eq!(tokens, vec![
+            Token::Else,
+            Token::Then,
+            Token::While,
+            Token::For,
+            Token::Break,
+            Token::Continue,
+        ]);
+    }
+
+    #[test]
+    fn test_data_definition_keywords() {
+        let tokens: Vec<Token> = Token::lexer("struct enum type match impl trait").collect();
+        assert_eq!(tokens, vec![
+            Token::Struct,
+            Token::Enum,
+            Token::Type,
+            Token::Match,
+            Token::Impl,
+            Token::Trait,
+        ]);
+    }
+
+    #[test]
+    fn test_mut_const_keywords() {
+        let tokens: Vec<Token> = Token::lexer("mut const").collect();
+        assert_eq!(tokens, vec![
+            Token::Mut,
+            Token::Const,
+        ]);
+    }
+
+    #[test]
+    fn test_token_spans() {
+        let tokens = tokenize("fn main() {\n    return 42;\n}").unwrap();
+        assert_eq!(tokens[0], (Token::Function, Span { start: 0, end: 2, line: 1, column: 1 }));
+        assert_eq!(tokens[5], (Token::Return, Span { start: 16, end: 22, line: 2, column: 5 }));
+        assert_eq!(tokens[6].1.to_string(), "line 2, column 12");
+    }
+
+    #[test]
+    fn test_lex_errors() {
+        let errors = tokenize("fn main() {\n  let x = 1;\n      @\n}").unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].to_string(), "unexpected character '@' at line 3, column 7");
+
+        let errors = tokenize("let c = 'ab'; /* open").unwrap_err();
+        assert_eq!(errors, vec![
+            LexError::InvalidLiteral {
+                text: "'ab'".to_string(),
+                span: Span { start: 8, end: 12, line: 1, column: 9 },
+            },
+            LexError::UnterminatedComment {
+                span: Span { start: 14, end: 21, line: 1, column: 15 },
+            },
+        ]);
+    }
+
+    #[test]
+    fn test_unicode_identifiers() {
+        let tokens: Vec<Token> = Token::lexer("_private größe 変数 x1").collect();
+        assert_eq!(tokens, vec![
+            Token::Identifier("_private".to_string()),
+            Token::Identifier("größe".to_string()),
+            Token::Identifier("変数".to_string()),
+            Token::Identifier("x1".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_comments() {
+        let source = "// plain comment\n/// Adds one.\n///\nfn";
+        let tokens: Vec<Token> = Token::lexer(source).collect();
+        assert_eq!(tokens, vec![
+            Token::DocComment("Adds one.".to_string()),
+            Token::DocComment(String::new()),
+            Token::Function,
+        ]);
+    }
+
+    #[test]
+    fn test_range_operators() {
+        let tokens: Vec<Token> = Token::lexer("0..10 0..=9 1.5").collect();
+        assert_eq!(tokens, vec![
+            Token::Number(0),
+            Token::DotDot,
+            Token::Number(10),
+            Token::Number(0),
+            Token::DotDotEqual,
+            Token::Number(9),
+            Token::FloatLiteral(1.5),
+        ]);
+    }
+
+    #[test]
+    fn test_member_access_dot() {
+        let tokens: Vec<Token> = Token::lexer("point.x a..b").collect();
+        assert_eq!(tokens, vec![
+            Token::Identifier("point".to_string()),
+            Token::Dot,
+            Token::Identifier("x".to_string()),
+            Token::Identifier("a".to_string()),
+            Token::DotDot,
+            Token::Identifier("b".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_brackets() {
+        let tokens: Vec<Token> = Token::lexer("[i32; 4] a[0]").collect();
+        assert_eq!(tokens, vec![
+            Token::LeftBracket,
+            Token::TypeInt,
+            Token::Semicolon,
+            Token::Number(4),
+            Token::RightBracket,
+            Token::Identifier("a".to_string()),
+            Token::LeftBracket,
+            Token::Number(0),
+            Token::RightBracket,
+        ]);
+    }
+
+    #[test]
+    fn test_question_mark() {
+        let tokens = tokenize("read()?").unwrap();
+        assert_eq!(tokens.last().map(|(t, _)| t), Some(&Token::Question));
+    }
+
+    #[test]
+    fn test_token_stream() {
+        let mut stream = TokenStream::new(tokenize("let x = 1;").unwrap());
+        assert_eq!(stream.peek(), Some(&Token::Let));
+        assert_eq!(stream.peek_n(2), Some(&Token::Equals));
+        assert!(stream.eat(&Token::Let));
+        assert!(!stream.eat(&Token::Let));
+        assert!(stream.check(&Token::Identifier(String::new())));
+        assert_eq!(stream.advance(), Some(Token::Identifier("x".to_string())));
+        assert_eq!(
+            stream.expect(&Token::Semicolon, "Expected ';'"),
+            Err("Expected ';' at line 1, column 7".to_string())
+        );
+        stream.advance();
+        stream.advance();
+        assert!(stream.expect(&Token::Semicolon, "Expected ';'").is_ok());
+        assert!(stream.is_at_end());
+        assert_eq!(stream.peek(), None);
+        assert_eq!(stream.advance(), None);
+    }
+
+    #[test]
+    fn test_error_at_end_of_input() {
+        let mut stream = TokenStream::new(tokenize("return").unwrap());
+        assert_eq!(stream.error("Expected expression"), "Expected expression at line 1, column 1");
+        stream.advance();
+        assert_eq!(
+            stream.expect(&Token::Semicolon, "Expected ';' after return statement"),
+            Err("unexpected end of input, expected ';' after return statement".to_string())
+        );
+    }
+
+    #[test]
+    fn test_span_tracking() {
+        let mut stream = TokenStream::new(tokenize("let value = 1;").unwrap());
+        let start = stream.span();
+        stream.advance();
+        stream.advance();
+        assert_eq!(stream.previous_span(), Span { start: 4, end: 9, line: 1, column: 5 });
+        assert_eq!(start.to(stream.previous_span()), Span { start: 0, end: 9, line: 1, column: 1 });
+    }
+
+    #[test]
+    fn test_numeric_type_keywords() {
+        let tokens: Vec<Token> = Token::lexer("i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 i128").collect();
+        assert_eq!(tokens, vec![
+            Token::TypeI8,
+            Token::TypeI16,
+            Token::TypeInt,
+            Token::TypeI64,
+            Token::TypeU8,
+            Token::TypeU16,
+            Token::TypeU32,
+            Token::TypeU64,
+            Token::TypeF32,
+            Token::TypeFloat,
+            Token::Identifier("i128".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_import_keyword() {
+        let tokens: Vec<Token> = Token::lexer("import module").collect();
+        assert_eq!(tokens, vec![
+            Token::Import,
+            Token::Module,
+        ]);
+    }
+
+    #[test]
+    fn test_as_keyword() {
+        let tokens: Vec<Token> = Token::lexer("x as f64 ask").collect();
+        assert_eq!(tokens, vec![
+            Token::Identifier("x".to_string()),
+            Token::As,
+            Token::TypeFloat,
+            Token::Identifier("ask".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_double_colon() {
+        let tokens: Vec<Token> = Token::lexer("Color::Red x: i32").collect();
+        assert_eq!(tokens, vec![
+            Token::Identifier("Color".to_string()),
+            Token::DoubleColon,
+            Token::Identifier("Red".to_string()),
+            Token::Identifier("x".to_string()),
+            Token::Colon,
+            Token::TypeInt,
+        ]);
+    }
 }
@@ -1,5 +1,8 @@
 pub mod codegen;
+pub mod consteval;
+pub mod hir;
 pub mod parser;
+pub mod pretty;
 pub mod lexer;
 pub mod types;
 pub mod typecheck;
@@ -1,16 +1,8 @@
-use crate::lexer::Token;
-use crate::parser::Parser;
-use crate::codegen::CodeGen;
+use nova_lang::parser::Parser;
+use nova_lang::{CodeGen, TypeChecker};
 use inkwell::context::Context;
-use logos::Logos;
 use std::process::Command;
 
-mod lexer;
-mod parser;
-mod types;
-mod codegen;
-mod typecheck;
-
 fn main() -> Result<(), String> {
     let source = r#"
         fn main(): i32 {
@@ -18,17 +10,25 @@ fn main() -> Result<(), String> {
         }
     "#;
 
-    // Lexing
-    let lexer = Token::lexer(source);
-    let tokens: Vec<Token> = lexer.collect();
-    
-    // Parsing
-    let mut parser = Parser::new(tokens);
+    // Lexing & parsing
+    let mut parser = Parser::from_source(source).map_err(|errors| {
+        errors.iter().map(|e| e.to_string()).collect::<Vec<_>>().join("\n")
+    })?;
     let ast = parser.parse()?;
 
     // Type checking
-    let mut type_checker = typecheck::TypeChecker::new();
-    type_checker.check(&ast)?;
+    let mut type_checker = TypeChecker::new();
+    if std::env::args().any(|arg| arg == "--deny-warnings") {
+        type_checker.deny_warnings();
+    }
+    type_checker.check(&ast).map_err(|error| error.render(source))?;
+    type_checker.check_entry_point(&ast).map_err(|error| error.render(source))?;
+    for diagnostic in type_checker.diagnostics() {
+        eprintln!("{}", diagnostic.render(source));
+    }
+    if type_checker.has_errors() {
+        return Err("Aborting because of denied warnings".to_string());
+    }
 
     // Codegen
     let context = Context::create();
@@ -39,7 +39,7 @@ fn main() -> Result<(), String> {
     codegen.write_object_file("output.o")?;
 
     let status = Command::new("cc")
-        .args(&["output.o", "-o", "program"])
+        .args(["output.o", "-o", "program"])
         .status()
         .map_err(|e| format!("Failed to link program: {}", e))?;
 
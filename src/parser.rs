@@ -1,296 +1,1512 @@
+use crate::consteval::ConstValue;
 use crate::types::Type;
-use crate::lexer::Token;
+use crate::lexer::{tokenize, FormatSegment, LexError, Span, Token, TokenStream};
 
 #[allow(dead_code)]
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum AstNode {
-    Program(Vec<AstNode>),
+    Program(Vec<Spanned<AstNode>>),
     Number(i64),
+    Float(f64),
+    Char(char),
     Identifier(String),
     Let {
         name: String,
-        type_annotation: Option<String>,
-        value: Box<AstNode>,
+        mutable: bool,
+        type_annotation: Option<Type>,
+        value: Box<Spanned<AstNode>>,
+    },
+    Const {
+        name: String,
+        type_annotation: Type,
+        value: Box<Spanned<AstNode>>,
+        doc: Option<String>,
     },
     Function {
         name: String,
-        params: Vec<(String, String)>,
-        body: Box<AstNode>,
+        /// Names from `fn id<T, U: Show>`, each with the traits it is bound
+        /// by; uses inside the signature and body parse as `Type::Param`.
+        type_params: Vec<(String, Vec<String>)>,
+        params: Vec<(String, Type)>,
+        return_type: Type,
+        body: Box<Spanned<AstNode>>,
+        doc: Option<String>,
+    },
+    /// `return expr;`, or a bare `return;` leaving a void function.
+    Return(Option<Box<Spanned<AstNode>>>),
+    If {
+        cond: Box<Spanned<AstNode>>,
+        then_block: Box<Spanned<AstNode>>,
+        else_block: Option<Box<Spanned<AstNode>>>,
+    },
+    /// `if cond then a else b`: a single-expression if whose branches are
+    /// plain expressions rather than blocks.
+    Conditional {
+        cond: Box<Spanned<AstNode>>,
+        then_value: Box<Spanned<AstNode>>,
+        else_value: Box<Spanned<AstNode>>,
+    },
+    While {
+        cond: Box<Spanned<AstNode>>,
+        body: Box<Spanned<AstNode>>,
+    },
+    Break,
+    Continue,
+    ExpressionStatement(Box<Spanned<AstNode>>),
+    Assign {
+        name: String,
+        value: Box<Spanned<AstNode>>,
     },
-    Return(Box<AstNode>),
     BinaryOp {
         op: BinaryOperator,
-        left: Box<AstNode>,
-        right: Box<AstNode>,
+        left: Box<Spanned<AstNode>>,
+        right: Box<Spanned<AstNode>>,
+    },
+    UnaryOp {
+        op: UnaryOperator,
+        operand: Box<Spanned<AstNode>>,
+    },
+    /// `x as f64`, converting a number to another numeric type.
+    Cast {
+        value: Box<Spanned<AstNode>>,
+        target: Type,
     },
     StringLiteral(String),
+    /// `f"sum = {a + b}"`: `StringLiteral` segments interleaved with the
+    /// embedded expressions, in source order.
+    FormatString {
+        parts: Vec<Spanned<AstNode>>,
+    },
     Boolean(bool),
+    ArrayLiteral(Vec<Spanned<AstNode>>),
+    StructDef {
+        name: String,
+        fields: Vec<(String, Type)>,
+        doc: Option<String>,
+    },
+    /// `enum Shape { Empty, Circle(f64) }`: each variant with the types of
+    /// its payload, if it has one.
+    EnumDef {
+        name: String,
+        variants: Vec<(String, Vec<Type>)>,
+        doc: Option<String>,
+    },
+    /// `type Meters = i32;`
+    TypeAlias {
+        name: String,
+        target: Type,
+        doc: Option<String>,
+    },
+    EnumVariant {
+        enum_name: String,
+        variant: String,
+        args: Vec<Spanned<AstNode>>,
+    },
+    StructLiteral {
+        name: String,
+        fields: Vec<(String, Spanned<AstNode>)>,
+    },
+    FieldAccess {
+        object: Box<Spanned<AstNode>>,
+        field: String,
+    },
+    Call {
+        callee: String,
+        args: Vec<Spanned<AstNode>>,
+    },
+    /// `|x: i32| x + 1`; a missing return type is inferred by the checker.
+    Closure {
+        params: Vec<(String, Type)>,
+        return_type: Option<Type>,
+        body: Box<Spanned<AstNode>>,
+    },
+    /// `receiver.method(args)`, resolved to a call of `method` with the
+    /// receiver as its first argument.
+    MethodCall {
+        receiver: Box<Spanned<AstNode>>,
+        method: String,
+        args: Vec<Spanned<AstNode>>,
+    },
+    Index {
+        array: Box<Spanned<AstNode>>,
+        index: Box<Spanned<AstNode>>,
+    },
+    /// `import foo.bar;`, holding each segment of the dotted path.
+    Import(Vec<String>),
+    /// `module foo.bar;`, naming the module the file belongs to.
+    Module(Vec<String>),
+    /// `match shape { Shape::Circle(r) => r, _ => 0.0 }`; the first arm
+    /// whose pattern matches is taken.
+    Match {
+        scrutinee: Box<Spanned<AstNode>>,
+        arms: Vec<(Spanned<Pattern>, Spanned<AstNode>)>,
+    },
+    /// `trait Show { fn show(self): string; }`, declaring the methods an
+    /// implementing type must provide.
+    TraitDef {
+        name: String,
+        methods: Vec<TraitMethod>,
+        doc: Option<String>,
+    },
+    /// `impl Show for Point { ... }`; the methods are `Function` nodes whose
+    /// `self` parameter has the target type.
+    Impl {
+        trait_name: String,
+        target: Type,
+        methods: Vec<Spanned<AstNode>>,
+        doc: Option<String>,
+    },
+}
+
+/// A method signature in a trait, whose `self` parameter and any `Self` in
+/// it are `Type::Param("Self")`.
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TraitMethod {
+    pub name: String,
+    pub params: Vec<(String, Type)>,
+    pub return_type: Type,
+    pub doc: Option<String>,
+}
+
+/// What a `match` arm compares the scrutinee against.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Pattern {
+    /// `_`, matching anything.
+    Wildcard,
+    /// A number, boolean, char or string literal.
+    Literal(AstNode),
+    /// `Shape::Circle(r)`, binding each payload value to a name.
+    Variant {
+        enum_name: String,
+        variant: String,
+        bindings: Vec<String>,
+    },
+}
+
+impl AstNode {
+    /// Whether the node produces a value. Such a node at the end of a block,
+    /// without a trailing `;`, becomes the value of the block; a nested block
+    /// in turn takes the value of its own tail.
+    pub fn is_expression(&self) -> bool {
+        matches!(
+            self,
+            AstNode::Program(_)
+                | AstNode::Number(_)
+                | AstNode::Float(_)
+                | AstNode::Char(_)
+                | AstNode::StringLiteral(_)
+                | AstNode::FormatString { .. }
+                | AstNode::Boolean(_)
+                | AstNode::Identifier(_)
+                | AstNode::BinaryOp { .. }
+                | AstNode::UnaryOp { .. }
+                | AstNode::Cast { .. }
+                | AstNode::If { .. }
+                | AstNode::Match { .. }
+                | AstNode::Conditional { .. }
+                | AstNode::ArrayLiteral(_)
+                | AstNode::Index { .. }
+                | AstNode::StructLiteral { .. }
+                | AstNode::EnumVariant { .. }
+                | AstNode::FieldAccess { .. }
+                | AstNode::Call { .. }
+                | AstNode::MethodCall { .. }
+                | AstNode::Closure { .. }
+        )
+    }
+}
+
+impl AstNode {
+    /// The direct children of the node, in source order.
+    pub fn children(&self) -> Vec<&Spanned<AstNode>> {
+        match self {
+            AstNode::Program(nodes)
+            | AstNode::ArrayLiteral(nodes)
+            | AstNode::FormatString { parts: nodes } => nodes.iter().collect(),
+            AstNode::Let { value, .. }
+            | AstNode::Const { value, .. }
+            | AstNode::Assign { value, .. }
+            | AstNode::ExpressionStatement(value)
+            | AstNode::UnaryOp { operand: value, .. }
+            | AstNode::Cast { value, .. }
+            | AstNode::FieldAccess { object: value, .. }
+            | AstNode::Function { body: value, .. }
+            | AstNode::Closure { body: value, .. } => vec![value],
+            AstNode::Return(value) => value.as_deref().into_iter().collect(),
+            AstNode::If { cond, then_block, else_block } => {
+                let mut children = vec![&**cond, &**then_block];
+                children.extend(else_block.as_deref());
+                children
+            },
+            AstNode::Conditional { cond, then_value, else_value } => vec![cond, then_value, else_value],
+            AstNode::While { cond, body } => vec![cond, body],
+            AstNode::BinaryOp { left, right, .. } => vec![left, right],
+            AstNode::Index { array, index } => vec![array, index],
+            AstNode::StructLiteral { fields, .. } => fields.iter().map(|(_, value)| value).collect(),
+            AstNode::Call { args, .. }
+            | AstNode::EnumVariant { args, .. }
+            | AstNode::Impl { methods: args, .. } => args.iter().collect(),
+            AstNode::Match { scrutinee, arms } => {
+                std::iter::once(&**scrutinee).chain(arms.iter().map(|(_, body)| body)).collect()
+            },
+            AstNode::MethodCall { receiver, args, .. } => {
+                std::iter::once(&**receiver).chain(args).collect()
+            },
+            AstNode::Number(_)
+            | AstNode::Float(_)
+            | AstNode::Char(_)
+            | AstNode::Identifier(_)
+            | AstNode::StringLiteral(_)
+            | AstNode::Boolean(_)
+            | AstNode::Break
+            | AstNode::Continue
+            | AstNode::StructDef { .. }
+            | AstNode::EnumDef { .. }
+            | AstNode::TraitDef { .. }
+            | AstNode::TypeAlias { .. }
+            | AstNode::Import(_)
+            | AstNode::Module(_) => vec![],
+        }
+    }
+
+    /// Mutable access to the direct children of the node, in source order.
+    pub fn children_mut(&mut self) -> Vec<&mut Spanned<AstNode>> {
+        match self {
+            AstNode::Program(nodes)
+            | AstNode::ArrayLiteral(nodes)
+            | AstNode::FormatString { parts: nodes } => nodes.iter_mut().collect(),
+            AstNode::Let { value, .. }
+            | AstNode::Const { value, .. }
+            | AstNode::Assign { value, .. }
+            | AstNode::ExpressionStatement(value)
+            | AstNode::UnaryOp { operand: value, .. }
+            | AstNode::Cast { value, .. }
+            | AstNode::FieldAccess { object: value, .. }
+            | AstNode::Function { body: value, .. }
+            | AstNode::Closure { body: value, .. } => vec![value],
+            AstNode::Return(value) => value.as_deref_mut().into_iter().collect(),
+            AstNode::If { cond, then_block, else_block } => {
+                let mut children = vec![&mut **cond, &mut **then_block];
+                children.extend(else_block.as_deref_mut());
+                children
+            },
+            AstNode::Conditional { cond, then_value, else_value } => vec![cond, then_value, else_value],
+            AstNode::While { cond, body } => vec![cond, body],
+            AstNode::BinaryOp { left, right, .. } => vec![left, right],
+            AstNode::Index { array, index } => vec![array, index],
+            AstNode::StructLiteral { fields, .. } => fields.iter_mut().map(|(_, value)| value).collect(),
+            AstNode::Call { args, .. }
+            | AstNode::EnumVariant { args, .. }
+            | AstNode::Impl { methods: args, .. } => args.iter_mut().collect(),
+            AstNode::Match { scrutinee, arms } => {
+                std::iter::once(&mut **scrutinee).chain(arms.iter_mut().map(|(_, body)| body)).collect()
+            },
+            AstNode::MethodCall { receiver, args, .. } => {
+                std::iter::once(&mut **receiver).chain(args).collect()
+            },
+            AstNode::Number(_)
+            | AstNode::Float(_)
+            | AstNode::Char(_)
+            | AstNode::Identifier(_)
+            | AstNode::StringLiteral(_)
+            | AstNode::Boolean(_)
+            | AstNode::Break
+            | AstNode::Continue
+            | AstNode::StructDef { .. }
+            | AstNode::EnumDef { .. }
+            | AstNode::TraitDef { .. }
+            | AstNode::TypeAlias { .. }
+            | AstNode::Import(_)
+            | AstNode::Module(_) => vec![],
+        }
+    }
+}
+
+/// Read-only traversal of the AST. Override `visit_node` to act on nodes of
+/// interest and call `walk_node` from it to keep descending.
+pub trait Visitor {
+    fn visit_node(&mut self, node: &Spanned<AstNode>) {
+        walk_node(self, node);
+    }
+}
+
+/// Visits every direct child of `node`.
+pub fn walk_node<V: Visitor + ?Sized>(visitor: &mut V, node: &AstNode) {
+    for child in node.children() {
+        visitor.visit_node(child);
+    }
+}
+
+/// In-place rewriting traversal of the AST, the mutable twin of `Visitor`.
+#[allow(dead_code)]
+pub trait MutVisitor {
+    fn visit_node_mut(&mut self, node: &mut Spanned<AstNode>) {
+        walk_node_mut(self, node);
+    }
+}
+
+/// Visits every direct child of `node` mutably.
+#[allow(dead_code)]
+pub fn walk_node_mut<V: MutVisitor + ?Sized>(visitor: &mut V, node: &mut AstNode) {
+    for child in node.children_mut() {
+        visitor.visit_node_mut(child);
+    }
+}
+
+/// A node together with the source range it was parsed from. It derefs to
+/// the node, so code that only cares about the shape can ignore the span.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Spanned<T> {
+    pub node: T,
+    pub span: Span,
+    /// The type the checker resolved for an expression; `None` until the
+    /// node has been checked, and for statements.
+    ty: std::cell::RefCell<Option<Type>>,
+    /// The value the checker folded a constant expression to, which code
+    /// generation and lowering use in place of the expression itself.
+    folded: std::cell::RefCell<Option<ConstValue>>,
+}
+
+impl<T> Spanned<T> {
+    pub fn new(node: T, span: Span) -> Self {
+        Spanned { node, span, ty: std::cell::RefCell::new(None), folded: std::cell::RefCell::new(None) }
+    }
+
+    pub fn folded(&self) -> Option<ConstValue> {
+        self.folded.borrow().clone()
+    }
+
+    pub fn set_folded(&self, value: ConstValue) {
+        *self.folded.borrow_mut() = Some(value);
+    }
+
+    pub fn ty(&self) -> Option<Type> {
+        self.ty.borrow().clone()
+    }
+
+    /// Annotates the node; the checker does this through a shared
+    /// reference so that it never needs to own or rebuild the tree.
+    pub fn set_ty(&self, ty: Type) {
+        *self.ty.borrow_mut() = Some(ty);
+    }
+}
+
+impl<T> std::ops::Deref for Spanned<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.node
+    }
+}
+
+impl<T> std::ops::DerefMut for Spanned<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.node
+    }
+}
+
+/// Nodes built outside the parser, such as in tests, have an empty span.
+impl From<AstNode> for Spanned<AstNode> {
+    fn from(node: AstNode) -> Self {
+        Spanned::new(node, Span::default())
+    }
 }
 
 #[allow(dead_code)]
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum BinaryOperator {
     Add,
     Subtract,
     Multiply,
     Divide,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+    Power,
+}
+
+impl BinaryOperator {
+    fn from_token(token: &Token) -> Option<Self> {
+        Some(match token {
+            Token::Plus => BinaryOperator::Add,
+            Token::Minus => BinaryOperator::Subtract,
+            Token::Multiply => BinaryOperator::Multiply,
+            Token::Divide => BinaryOperator::Divide,
+            Token::EqualEqual => BinaryOperator::Eq,
+            Token::NotEqual => BinaryOperator::Ne,
+            Token::LessThan => BinaryOperator::Lt,
+            Token::LessEqual => BinaryOperator::Le,
+            Token::GreaterThan => BinaryOperator::Gt,
+            Token::GreaterEqual => BinaryOperator::Ge,
+            Token::And => BinaryOperator::And,
+            Token::Or => BinaryOperator::Or,
+            Token::Power => BinaryOperator::Power,
+            _ => return None,
+        })
+    }
+
+    /// Binding strength, lowest first: `||`, `&&`, comparisons, `+ -`, `* /`,
+    /// `**`.
+    pub fn precedence(&self) -> u8 {
+        match self {
+            BinaryOperator::Or => 1,
+            BinaryOperator::And => 2,
+            BinaryOperator::Eq
+            | BinaryOperator::Ne
+            | BinaryOperator::Lt
+            | BinaryOperator::Le
+            | BinaryOperator::Gt
+            | BinaryOperator::Ge => 3,
+            BinaryOperator::Add | BinaryOperator::Subtract => 4,
+            BinaryOperator::Multiply | BinaryOperator::Divide => 5,
+            BinaryOperator::Power => 6,
+        }
+    }
+
+    /// `2 ** 3 ** 2` groups as `2 ** (3 ** 2)`; every other operator folds
+    /// to the left.
+    pub fn is_right_associative(&self) -> bool {
+        matches!(self, BinaryOperator::Power)
+    }
+
+    /// `==`, `!=`, `<`, `<=`, `>`, `>=`: compares two operands of the same
+    /// type and yields a `bool`.
+    pub fn is_comparison(&self) -> bool {
+        matches!(
+            self,
+            BinaryOperator::Eq
+                | BinaryOperator::Ne
+                | BinaryOperator::Lt
+                | BinaryOperator::Le
+                | BinaryOperator::Gt
+                | BinaryOperator::Ge
+        )
+    }
+
+    /// `&&` and `||`, which take and yield `bool`.
+    pub fn is_logical(&self) -> bool {
+        matches!(self, BinaryOperator::And | BinaryOperator::Or)
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum UnaryOperator {
+    Negate,
+    Not,
 }
 
 #[allow(dead_code)]
 pub struct Parser {
-    tokens: Vec<Token>,
-    current: usize,
+    tokens: TokenStream,
+    /// Set while parsing `if`/`while` conditions, where `name {` opens the
+    /// body rather than a struct literal.
+    no_struct_literal: bool,
+    /// Type parameters of the function being parsed.
+    type_params: Vec<String>,
+    /// Type of `self` and `Self` in the trait or impl being parsed.
+    self_type: Option<Type>,
+    /// Every enum declared anywhere in the source, and `Option`: `Name.Variant`
+    /// names a variant rather than a field when `Name` is one of them.
+    enum_names: std::collections::HashSet<String>,
 }
 
 impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Self {
+    pub fn new(tokens: Vec<(Token, Span)>) -> Self {
+        let mut enum_names: std::collections::HashSet<String> = tokens
+            .windows(2)
+            .filter_map(|pair| match pair {
+                [(Token::Enum, _), (Token::Identifier(name), _)] => Some(name.clone()),
+                _ => None,
+            })
+            .collect();
+        enum_names.insert("Option".to_string());
         Parser {
-            tokens,
-            current: 0,
+            tokens: TokenStream::new(tokens),
+            no_struct_literal: false,
+            type_params: Vec::new(),
+            self_type: None,
+            enum_names,
         }
     }
 
+
+    /// Lexes `source` and returns a parser over its tokens, or every lexer
+    /// error if the source does not tokenize.
+    pub fn from_source(source: &str) -> Result<Self, Vec<LexError>> {
+        Ok(Parser::new(tokenize(source)?))
+    }
+
     pub fn parse(&mut self) -> Result<AstNode, String> {
         let mut program = vec![];
-        while self.current < self.tokens.len() {
+        while !self.tokens.is_at_end() {
             program.push(self.parse_declaration()?);
         }
         Ok(AstNode::Program(program))
     }
 
+    /// Wraps `node` with the span from `start` through the last consumed token.
+    fn finish<T>(&self, start: Span, node: T) -> Spanned<T> {
+        Spanned::new(node, start.to(self.tokens.previous_span()))
+    }
+
     fn parse_type(&mut self) -> Result<Type, String> {
-        match self.current_token() {
-            Token::TypeInt => {
-                self.advance();
-                Ok(Type::Int)
-            },
-            Token::TypeFloat => {
-                self.advance();
-                Ok(Type::Float)
-            },
-            // TODO; add more types
-            _ => Err("Expected type".to_string()),
+        if self.tokens.eat(&Token::LeftBracket) {
+            // `[T; N]`
+            let element = self.parse_type()?;
+            self.tokens.expect(&Token::Semicolon, "Expected ';' after array element type")?;
+            let len = match self.tokens.advance() {
+                Some(Token::Number(n)) if n >= 0 => n as usize,
+                _ => return Err(self.tokens.error("Expected array length")),
+            };
+            self.tokens.expect(&Token::RightBracket, "Expected ']' after array length")?;
+            return Ok(Type::array(element, len));
         }
-    }
 
-    fn current_token(&self) -> &Token {
-        &self.tokens[self.current]
-    }
+        if self.tokens.eat(&Token::Function) {
+            // `fn(T, U) -> R`
+            self.tokens.expect(&Token::LeftParen, "Expected '(' after 'fn' in function type")?;
+            let mut params = Vec::new();
+            while !self.tokens.eat(&Token::RightParen) {
+                params.push(self.parse_type()?);
+                if !self.tokens.eat(&Token::Comma) {
+                    self.tokens.expect(&Token::RightParen, "Expected ',' or ')' in function type")?;
+                    break;
+                }
+            }
+            self.tokens.expect(&Token::Arrow, "Expected '->' in function type")?;
+            return Ok(Type::function(params, self.parse_type()?));
+        }
 
-    fn advance(&mut self) {
-        self.current += 1;
+        if self.tokens.peek() == Some(&Token::Identifier("Option".to_string())) {
+            // `Option<T>`
+            self.tokens.advance();
+            self.tokens.expect(&Token::LessThan, "Expected '<' after 'Option'")?;
+            let inner = self.parse_type()?;
+            if !self.tokens.eat_closing_angle() {
+                return Err(self.tokens.error("Expected '>' after the type of an Option"));
+            }
+            return Ok(Type::option(inner));
+        }
+
+        let type_ = match self.tokens.peek() {
+            Some(Token::Identifier(name)) if self.type_params.contains(name) => Type::Param(name.clone()),
+            Some(Token::Identifier(name)) if name == "Self" && self.self_type.is_some() => {
+                self.self_type.clone().unwrap_or(Type::Void)
+            },
+            // type keywords print as the name of their type, and `char` is
+            // an identifier
+            Some(token) => match Type::from_name(&token.to_string()) {
+                Some(primitive) => primitive,
+                None => match token {
+                    Token::Identifier(name) => Type::Struct(name.clone()),
+                    _ => return Err(self.tokens.error("Expected type")),
+                },
+            },
+            None => return Err(self.tokens.error("Expected type")),
+        };
+        self.tokens.advance();
+        Ok(type_)
     }
 
-    fn parse_declaration(&mut self) -> Result<AstNode, String> {
-        match self.current_token() {
-            Token::Function => self.parse_function(),
-            Token::Let => self.parse_let_statement(),
-            _ => Err("Expected declaration".to_string()),
+    fn expect_identifier(&mut self, message: &str) -> Result<String, String> {
+        match self.tokens.peek() {
+            Some(Token::Identifier(name)) => {
+                let name = name.clone();
+                self.tokens.advance();
+                Ok(name)
+            },
+            _ => Err(self.tokens.error(message)),
         }
     }
 
-    fn parse_function(&mut self) -> Result<AstNode, String> {
-        self.advance(); // consume 'fn'
-        
-        let name = match self.current_token() {
-            Token::Identifier(id) => {
-                let name = id.clone();
-                self.advance();
-                name
+    fn parse_declaration(&mut self) -> Result<Spanned<AstNode>, String> {
+        let doc = self.parse_doc_comments();
+        match self.tokens.peek() {
+            Some(Token::Function) => self.parse_function(doc),
+            Some(Token::Struct) => self.parse_struct(doc),
+            Some(Token::Enum) => self.parse_enum(doc),
+            Some(Token::Type) => self.parse_type_alias(doc),
+            Some(Token::Const) => self.parse_const(doc),
+            Some(Token::Trait) => self.parse_trait(doc),
+            Some(Token::Impl) => self.parse_impl(doc),
+            // nothing keeps the docs of a variable or an import, so they are
+            // refused rather than lost
+            Some(Token::Let | Token::Import | Token::Module) if doc.is_some() => {
+                Err(self.tokens.error("Doc comments can only document a declaration, not a variable or an import"))
             },
-            _ => return Err("Expected function name".to_string()),
-        };
-
-        if !matches!(self.current_token(), Token::LeftParen) {
-            return Err("Expected '(' after function name".to_string());
+            Some(Token::Let) => self.parse_let_statement(),
+            Some(Token::Import | Token::Module) => self.parse_import(),
+            _ if doc.is_some() => Err(self.tokens.error("Expected declaration after doc comment")),
+            _ => Err(self.tokens.error("Expected declaration")),
         }
-        self.advance();
+    }
 
-        let mut params = Vec::new();
-        while !matches!(self.current_token(), Token::RightParen) {
-            match self.current_token() {
-                Token::Identifier(param) => {
-                    let param_name = param.clone();
-                    self.advance();
-                    
-                    if !matches!(self.current_token(), Token::Colon) {
-                        return Err("Expected ':' after parameter name".to_string());
-                    }
-                    self.advance();
+    /// Collects consecutive `///` lines into a single newline-separated string.
+    fn parse_doc_comments(&mut self) -> Option<String> {
+        let mut lines = Vec::new();
+        while let Some(Token::DocComment(text)) = self.tokens.peek() {
+            lines.push(text.clone());
+            self.tokens.advance();
+        }
+        if lines.is_empty() {
+            None
+        } else {
+            Some(lines.join("\n"))
+        }
+    }
 
-                    let param_type = match self.current_token() {
-                        Token::Identifier(type_name) => {
-                            let type_name = type_name.clone();
-                            self.advance();
-                            type_name
-                        },
-                        _ => return Err("Expected type name after ':'".to_string()),
-                    };
+    fn parse_function(&mut self, doc: Option<String>) -> Result<Spanned<AstNode>, String> {
+        let start = self.tokens.span();
+        self.tokens.advance(); // consume 'fn'
 
-                    params.push((param_name, param_type));
+        let name = self.expect_identifier("Expected function name")?;
 
-                    if matches!(self.current_token(), Token::Comma) {
-                        self.advance();
+        let mut type_params = Vec::new();
+        if self.tokens.eat(&Token::LessThan) {
+            while !self.tokens.eat(&Token::GreaterThan) {
+                let param = self.expect_identifier("Expected type parameter name")?;
+                if type_params.iter().any(|(other, _)| *other == param) {
+                    return Err(self.tokens.error(&format!("Duplicate type parameter {}", param)));
+                }
+                // `T: A + B` requires `T` to implement both traits
+                let mut bounds = Vec::new();
+                if self.tokens.eat(&Token::Colon) {
+                    loop {
+                        bounds.push(self.expect_identifier("Expected trait name in type parameter bound")?);
+                        if !self.tokens.eat(&Token::Plus) {
+                            break;
+                        }
                     }
-                },
-                _ => return Err("Expected parameter name".to_string()),
+                }
+                type_params.push((param, bounds));
+                if !self.tokens.eat(&Token::Comma) {
+                    self.tokens.expect(&Token::GreaterThan, "Expected ',' or '>' after type parameter")?;
+                    break;
+                }
             }
         }
-        self.advance(); // consume ')'
 
-        if !matches!(self.current_token(), Token::Colon) {
-            return Err("Expected ':' after parameters".to_string());
-        }
-        self.advance();
+        // type parameters are in scope for the signature and the body only
+        let names = type_params.iter().map(|(name, _)| name.clone()).collect();
+        let outer = std::mem::replace(&mut self.type_params, names);
+        let function = self.parse_function_rest(start, name, type_params, doc);
+        self.type_params = outer;
+        function
+    }
 
-        let _return_type = self.parse_type()?;
+    /// Parses the parameter list, return type and body of a function.
+    fn parse_function_rest(
+        &mut self,
+        start: Span,
+        name: String,
+        type_params: Vec<(String, Vec<String>)>,
+        doc: Option<String>,
+    ) -> Result<Spanned<AstNode>, String> {
+        let params = self.parse_params()?;
 
-        if !matches!(self.current_token(), Token::LeftBrace) {
-            return Err("Expected '{' to begin function body".to_string());
+        // both `fn f(): i32` and `fn f() -> i32` are accepted
+        if !self.tokens.eat(&Token::Colon) && !self.tokens.eat(&Token::Arrow) {
+            return Err(self.tokens.error("Expected ':' or '->' after parameters"));
         }
-        self.advance();
+
+        let return_type = self.parse_type()?;
+
+        self.tokens.expect(&Token::LeftBrace, "Expected '{' to begin function body")?;
 
         let body = self.parse_block()?;
 
-        Ok(AstNode::Function {
+        Ok(self.finish(start, AstNode::Function {
             name,
+            type_params,
             params,
+            return_type,
             body: Box::new(body),
-        })
+            doc,
+        }))
     }
 
-    fn parse_block(&mut self) -> Result<AstNode, String> {
-        let mut statements = Vec::new();
-        
-        while !matches!(self.current_token(), Token::RightBrace) {
-            if matches!(self.current_token(), Token::Return) {
-                self.advance();
-                let expr = self.parse_expression()?;
-                if !matches!(self.current_token(), Token::Semicolon) {
-                    return Err("Expected ';' after return statement".to_string());
-                }
-                self.advance();
-                statements.push(AstNode::Return(Box::new(expr)));
+    /// Parses a parenthesized parameter list. Inside a trait or impl the
+    /// list must start with a bare `self`, which takes the `Self` type.
+    fn parse_params(&mut self) -> Result<Vec<(String, Type)>, String> {
+        self.tokens.expect(&Token::LeftParen, "Expected '(' after function name")?;
+
+        let mut params = Vec::new();
+        if let Some(self_type) = self.self_type.clone() {
+            if !matches!(self.tokens.peek(), Some(Token::Identifier(name)) if name == "self") {
+                return Err(self.tokens.error("Expected 'self' as the first parameter of a method"));
+            }
+            self.tokens.advance();
+            params.push(("self".to_string(), self_type));
+            if !self.tokens.eat(&Token::Comma) {
+                self.tokens.expect(&Token::RightParen, "Expected ',' or ')' after parameter")?;
+                return Ok(params);
+            }
+        }
+        while !self.tokens.eat(&Token::RightParen) {
+            if matches!(self.tokens.peek(), Some(Token::Identifier(name)) if name == "self") {
+                return Err(self.tokens.error("'self' is only allowed as the first parameter of a method"));
+            }
+            let param_name = self.expect_identifier("Expected parameter name")?;
+            self.tokens.expect(&Token::Colon, "Expected ':' after parameter name")?;
+            let param_type = self.parse_type()?;
+
+            params.push((param_name, param_type));
+            if !self.tokens.eat(&Token::Comma) {
+                self.tokens.expect(&Token::RightParen, "Expected ',' or ')' after parameter")?;
+                break;
+            }
+        }
+        Ok(params)
+    }
+
+    /// Parses `trait Name { fn method(self, ...): T; ... }`. A method
+    /// without a return type returns `void`.
+    fn parse_trait(&mut self, doc: Option<String>) -> Result<Spanned<AstNode>, String> {
+        let start = self.tokens.span();
+        self.tokens.advance(); // consume 'trait'
+        let name = self.expect_identifier("Expected trait name")?;
+        self.tokens.expect(&Token::LeftBrace, "Expected '{' after trait name")?;
+
+        let outer = self.self_type.replace(Type::Param("Self".to_string()));
+        let methods = self.parse_trait_methods();
+        self.self_type = outer;
+        Ok(self.finish(start, AstNode::TraitDef { name, methods: methods?, doc }))
+    }
+
+    fn parse_trait_methods(&mut self) -> Result<Vec<TraitMethod>, String> {
+        let mut methods = Vec::new();
+        while !self.tokens.eat(&Token::RightBrace) {
+            let doc = self.parse_doc_comments();
+            self.tokens.expect(&Token::Function, "Expected 'fn' or '}' in trait")?;
+            let name = self.expect_identifier("Expected method name")?;
+            let params = self.parse_params()?;
+            let return_type = if self.tokens.eat(&Token::Colon) || self.tokens.eat(&Token::Arrow) {
+                self.parse_type()?
             } else {
-                return Err("Unexpected token in function body".to_string());
+                Type::Void
+            };
+            self.tokens.expect(&Token::Semicolon, "Expected ';' after trait method")?;
+            methods.push(TraitMethod { name, params, return_type, doc });
+        }
+        Ok(methods)
+    }
+
+    /// Parses `impl Trait for Type { fn ... }`.
+    fn parse_impl(&mut self, doc: Option<String>) -> Result<Spanned<AstNode>, String> {
+        let start = self.tokens.span();
+        self.tokens.advance(); // consume 'impl'
+        let trait_name = self.expect_identifier("Expected trait name after 'impl'")?;
+        self.tokens.expect(&Token::For, "Expected 'for' after trait name")?;
+        let target = self.parse_type()?;
+        self.tokens.expect(&Token::LeftBrace, "Expected '{' after impl type")?;
+
+        let outer = self.self_type.replace(target.clone());
+        let mut methods = Vec::new();
+        let result = loop {
+            if self.tokens.eat(&Token::RightBrace) {
+                break Ok(());
+            }
+            let method_doc = self.parse_doc_comments();
+            if self.tokens.peek() != Some(&Token::Function) {
+                break Err(self.tokens.error("Expected 'fn' or '}' in impl"));
+            }
+            match self.parse_function(method_doc) {
+                Ok(method) => methods.push(method),
+                Err(error) => break Err(error),
+            }
+        };
+        self.self_type = outer;
+        result?;
+        Ok(self.finish(start, AstNode::Impl { trait_name, target, methods, doc }))
+    }
+
+    fn parse_struct(&mut self, doc: Option<String>) -> Result<Spanned<AstNode>, String> {
+        let start = self.tokens.span();
+        self.tokens.advance(); // consume 'struct'
+        let name = self.expect_identifier("Expected struct name")?;
+        self.tokens.expect(&Token::LeftBrace, "Expected '{' after struct name")?;
+
+        let mut fields = Vec::new();
+        while !self.tokens.eat(&Token::RightBrace) {
+            // a field may be documented, though nothing keeps its docs
+            self.parse_doc_comments();
+            let field = self.expect_identifier("Expected field name")?;
+            self.tokens.expect(&Token::Colon, "Expected ':' after field name")?;
+            fields.push((field, self.parse_type()?));
+            if !self.tokens.eat(&Token::Comma) {
+                self.tokens.expect(&Token::RightBrace, "Expected ',' or '}' after struct field")?;
+                break;
             }
         }
-        self.advance(); // consume '}'
-        
-        Ok(AstNode::Program(statements))
+
+        Ok(self.finish(start, AstNode::StructDef { name, fields, doc }))
     }
 
-    fn parse_expression(&mut self) -> Result<AstNode, String> {
-        self.parse_binary_expression()
+    fn parse_enum(&mut self, doc: Option<String>) -> Result<Spanned<AstNode>, String> {
+        let start = self.tokens.span();
+        self.tokens.advance(); // consume 'enum'
+        let name = self.expect_identifier("Expected enum name")?;
+        self.tokens.expect(&Token::LeftBrace, "Expected '{' after enum name")?;
+
+        let mut variants = Vec::new();
+        while !self.tokens.eat(&Token::RightBrace) {
+            // likewise a variant
+            self.parse_doc_comments();
+            let variant = self.expect_identifier("Expected variant name")?;
+            let mut payload = Vec::new();
+            if self.tokens.eat(&Token::LeftParen) {
+                while !self.tokens.eat(&Token::RightParen) {
+                    payload.push(self.parse_type()?);
+                    if !self.tokens.eat(&Token::Comma) {
+                        self.tokens.expect(&Token::RightParen, "Expected ',' or ')' in variant payload")?;
+                        break;
+                    }
+                }
+            }
+            variants.push((variant, payload));
+            if !self.tokens.eat(&Token::Comma) {
+                self.tokens.expect(&Token::RightBrace, "Expected ',' or '}' after enum variant")?;
+                break;
+            }
+        }
+
+        Ok(self.finish(start, AstNode::EnumDef { name, variants, doc }))
     }
 
-    fn parse_binary_expression(&mut self) -> Result<AstNode, String> {
-        let mut left = self.parse_primary()?;
+    fn parse_type_alias(&mut self, doc: Option<String>) -> Result<Spanned<AstNode>, String> {
+        let start = self.tokens.span();
+        self.tokens.advance(); // consume 'type'
+        let name = self.expect_identifier("Expected type alias name")?;
+        self.tokens.expect(&Token::Equals, "Expected '=' after type alias name")?;
+        let target = self.parse_type()?;
+        self.tokens.expect(&Token::Semicolon, "Expected ';' after type alias")?;
 
-        while let Token::Plus | Token::Minus | Token::Multiply | Token::Divide = self.current_token() {
-            let op = match self.current_token() {
-                Token::Plus => BinaryOperator::Add,
-                Token::Minus => BinaryOperator::Subtract,
-                Token::Multiply => BinaryOperator::Multiply,
-                Token::Divide => BinaryOperator::Divide,
-                _ => unreachable!(),
-            };
-            self.advance();
+        Ok(self.finish(start, AstNode::TypeAlias { name, target, doc }))
+    }
 
-            let right = self.parse_primary()?;
-            left = AstNode::BinaryOp {
-                op,
-                left: Box::new(left),
-                right: Box::new(right),
-            };
+    /// Parses the statements of a block whose `{` was just consumed.
+    fn parse_block(&mut self) -> Result<Spanned<AstNode>, String> {
+        let start = self.tokens.previous_span();
+        let mut statements = Vec::new();
+
+        while !self.tokens.eat(&Token::RightBrace) {
+            // inside a body there is no declaration to document
+            if self.parse_doc_comments().is_some() {
+                continue;
+            }
+            statements.push(self.parse_statement()?);
         }
 
-        Ok(left)
+        Ok(self.finish(start, AstNode::Program(statements)))
     }
 
-    fn parse_primary(&mut self) -> Result<AstNode, String> {
-        match self.current_token() {
-            Token::Number(n) => {
-                let num = *n;
-                self.advance();
-                Ok(AstNode::Number(num))
+    fn parse_statement(&mut self) -> Result<Spanned<AstNode>, String> {
+        let start = self.tokens.span();
+        match self.tokens.peek() {
+            Some(Token::Let) => self.parse_let_statement(),
+            Some(Token::Return) => {
+                self.tokens.advance();
+                let expr = if self.tokens.check(&Token::Semicolon) {
+                    None
+                } else {
+                    Some(Box::new(self.parse_expression()?))
+                };
+                self.tokens.expect(&Token::Semicolon, "Expected ';' after return statement")?;
+                Ok(self.finish(start, AstNode::Return(expr)))
             },
-            Token::StringLiteral => {
-                let value = self.current_token().to_string();
-                self.advance();
-                Ok(AstNode::StringLiteral(value))
+            Some(Token::If) => {
+                let node = self.parse_if()?;
+                match node.node {
+                    // the single-expression form ends like any other expression
+                    AstNode::Conditional { .. } => self.finish_expression_statement(start, node),
+                    _ => Ok(node),
+                }
+            },
+            Some(Token::Match) => {
+                // like a block, a match needs no ';' to end the statement
+                let node = self.parse_match()?;
+                if self.tokens.eat(&Token::Semicolon) {
+                    return Ok(self.finish(start, AstNode::ExpressionStatement(Box::new(node))));
+                }
+                Ok(node)
+            },
+            Some(Token::While) => self.parse_while(),
+            Some(Token::Break) => {
+                self.tokens.advance();
+                self.tokens.expect(&Token::Semicolon, "Expected ';' after 'break'")?;
+                Ok(self.finish(start, AstNode::Break))
             },
-            Token::True => {
-                self.advance();
-                Ok(AstNode::Boolean(true))
+            Some(Token::Continue) => {
+                self.tokens.advance();
+                self.tokens.expect(&Token::Semicolon, "Expected ';' after 'continue'")?;
+                Ok(self.finish(start, AstNode::Continue))
             },
-            Token::False => {
-                self.advance();
-                Ok(AstNode::Boolean(false))
+            Some(Token::LeftBrace) => {
+                self.tokens.advance();
+                self.parse_block()
             },
-            Token::Identifier(name) => {
-                let id = name.clone();
-                self.advance();
-                Ok(AstNode::Identifier(id))
+            Some(Token::Identifier(_)) if matches!(
+                self.tokens.peek_n(1),
+                Some(Token::Equals | Token::PlusEquals | Token::MinusEquals | Token::MultiplyEquals | Token::DivideEquals)
+            ) => self.parse_assignment(),
+            _ => {
+                let expr = self.parse_expression()?;
+                self.finish_expression_statement(start, expr)
             },
-            _ => Err("Expected expression".to_string()),
         }
     }
 
-    fn parse_let_statement(&mut self) -> Result<AstNode, String> {
-        self.advance(); // consume 'let'
-        
-        let name = match self.current_token() {
-            Token::Identifier(id) => {
-                let name = id.clone();
-                self.advance();
-                name
-            },
-            _ => return Err("Expected variable name".to_string()),
+    fn finish_expression_statement(&mut self, start: Span, expr: Spanned<AstNode>) -> Result<Spanned<AstNode>, String> {
+        if self.tokens.eat(&Token::Semicolon) {
+            Ok(self.finish(start, AstNode::ExpressionStatement(Box::new(expr))))
+        } else if self.tokens.check(&Token::RightBrace) {
+            // a trailing expression without ';' is the block's value
+            Ok(expr)
+        } else {
+            Err(self.tokens.error("Expected ';' after expression"))
+        }
+    }
+
+    /// Parses `x = expr;`, desugaring compound forms like `x += expr` into
+    /// `x = x + expr`.
+    fn parse_assignment(&mut self) -> Result<Spanned<AstNode>, String> {
+        let start = self.tokens.span();
+        let name = self.expect_identifier("Expected variable name")?;
+        let target = self.finish(start, AstNode::Identifier(name.clone()));
+
+        let op = match self.tokens.advance() {
+            Some(Token::PlusEquals) => Some(BinaryOperator::Add),
+            Some(Token::MinusEquals) => Some(BinaryOperator::Subtract),
+            Some(Token::MultiplyEquals) => Some(BinaryOperator::Multiply),
+            Some(Token::DivideEquals) => Some(BinaryOperator::Divide),
+            _ => None,
         };
 
-        let type_annotation = if matches!(self.current_token(), Token::Colon) {
-            self.advance();
-            match self.current_token() {
-                Token::Identifier(type_name) => {
-                    let type_name = Some(type_name.clone());
-                    self.advance();
-                    type_name
-                },
-                _ => return Err("Expected type name after ':'".to_string()),
+        let mut value = self.parse_expression()?;
+
+        if let Some(op) = op {
+            value = self.finish(start, AstNode::BinaryOp {
+                op,
+                left: Box::new(target),
+                right: Box::new(value),
+            });
+        }
+
+        self.tokens.expect(&Token::Semicolon, "Expected ';' after assignment")?;
+
+        Ok(self.finish(start, AstNode::Assign {
+            name,
+            value: Box::new(value),
+        }))
+    }
+
+    fn parse_while(&mut self) -> Result<Spanned<AstNode>, String> {
+        let start = self.tokens.span();
+        self.tokens.advance(); // consume 'while'
+
+        let cond = self.parse_condition()?;
+        self.tokens.expect(&Token::LeftBrace, "Expected '{' after while condition")?;
+        let body = self.parse_block()?;
+
+        Ok(self.finish(start, AstNode::While {
+            cond: Box::new(cond),
+            body: Box::new(body),
+        }))
+    }
+
+    fn parse_if(&mut self) -> Result<Spanned<AstNode>, String> {
+        let start = self.tokens.span();
+        self.tokens.advance(); // consume 'if'
+
+        let cond = self.parse_condition()?;
+        if self.tokens.eat(&Token::Then) {
+            let then_value = self.parse_expression()?;
+            self.tokens.expect(&Token::Else, "Expected 'else' after the 'then' branch")?;
+            let else_value = self.parse_expression()?;
+            return Ok(self.finish(start, AstNode::Conditional {
+                cond: Box::new(cond),
+                then_value: Box::new(then_value),
+                else_value: Box::new(else_value),
+            }));
+        }
+        self.tokens.expect(&Token::LeftBrace, "Expected '{' or 'then' after if condition")?;
+        let then_block = self.parse_block()?;
+
+        let else_block = if self.tokens.eat(&Token::Else) {
+            if self.tokens.check(&Token::If) {
+                // `else if` chains nest as an if inside the else branch
+                Some(Box::new(self.parse_if()?))
+            } else {
+                self.tokens.expect(&Token::LeftBrace, "Expected '{' or 'if' after 'else'")?;
+                Some(Box::new(self.parse_block()?))
             }
         } else {
             None
         };
 
-        if !matches!(self.current_token(), Token::Equals) {
-            return Err("Expected '=' after type annotation".to_string());
-        }
-        self.advance();
+        Ok(self.finish(start, AstNode::If {
+            cond: Box::new(cond),
+            then_block: Box::new(then_block),
+            else_block,
+        }))
+    }
 
-        let value = self.parse_expression()?;
+    fn parse_match(&mut self) -> Result<Spanned<AstNode>, String> {
+        let start = self.tokens.span();
+        self.tokens.advance(); // consume 'match'
 
-        if !matches!(self.current_token(), Token::Semicolon) {
-            return Err("Expected ';' after let statement".to_string());
+        let scrutinee = self.parse_condition()?;
+        self.tokens.expect(&Token::LeftBrace, "Expected '{' after match value")?;
+        let mut arms = Vec::new();
+        while !self.tokens.eat(&Token::RightBrace) {
+            let pattern = self.parse_pattern()?;
+            self.tokens.expect(&Token::FatArrow, "Expected '=>' after match pattern")?;
+            let body = self.parse_expression()?;
+            let is_block = matches!(body.node, AstNode::Program(_));
+            arms.push((pattern, body));
+            // a block body ends the arm by itself
+            if !self.tokens.eat(&Token::Comma) && !is_block {
+                self.tokens.expect(&Token::RightBrace, "Expected ',' or '}' after match arm")?;
+                break;
+            }
         }
-        self.advance();
 
-        Ok(AstNode::Let {
-            name,
-            type_annotation,
-            value: Box::new(value),
-        })
+        Ok(self.finish(start, AstNode::Match { scrutinee: Box::new(scrutinee), arms }))
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::lexer::Token;
+    fn parse_pattern(&mut self) -> Result<Spanned<Pattern>, String> {
+        let start = self.tokens.span();
+        let pattern = match self.tokens.peek().cloned() {
+            Some(Token::Identifier(name)) if name == "_" => {
+                self.tokens.advance();
+                Pattern::Wildcard
+            },
+            Some(Token::Identifier(_)) => {
+                let enum_name = self.expect_identifier("Expected enum name in pattern")?;
+                if !self.tokens.eat(&Token::Dot) {
+                    self.tokens.expect(&Token::DoubleColon, "Expected '::' or '.' after enum name in pattern")?;
+                }
+                let variant = self.expect_identifier("Expected variant name after the enum name")?;
+                let mut bindings = Vec::new();
+                if self.tokens.eat(&Token::LeftParen) {
+                    while !self.tokens.eat(&Token::RightParen) {
+                        bindings.push(self.expect_identifier("Expected a name for the payload value")?);
+                        if !self.tokens.eat(&Token::Comma) {
+                            self.tokens.expect(&Token::RightParen, "Expected ',' or ')' after payload name")?;
+                            break;
+                        }
+                    }
+                }
+                Pattern::Variant { enum_name, variant, bindings }
+            },
+            Some(Token::Number(n)) => self.literal_pattern(AstNode::Number(n)),
+            Some(Token::CharLiteral(c)) => self.literal_pattern(AstNode::Char(c)),
+            Some(Token::StringLiteral(s)) => self.literal_pattern(AstNode::StringLiteral(s)),
+            Some(Token::True) => self.literal_pattern(AstNode::Boolean(true)),
+            Some(Token::False) => self.literal_pattern(AstNode::Boolean(false)),
+            _ => return Err(self.tokens.error("Expected pattern")),
+        };
+        Ok(self.finish(start, pattern))
+    }
 
-    #[test]
-    fn test_parse_function() {
-        let tokens = vec![
+    fn literal_pattern(&mut self, literal: AstNode) -> Pattern {
+        self.tokens.advance();
+        Pattern::Literal(literal)
+    }
+
+    fn parse_condition(&mut self) -> Result<Spanned<AstNode>, String> {
+        let previous = std::mem::replace(&mut self.no_struct_literal, true);
+        let cond = self.parse_expression();
+        self.no_struct_literal = previous;
+        cond
+    }
+
+    fn parse_expression(&mut self) -> Result<Spanned<AstNode>, String> {
+        self.parse_binary_expression(0)
+    }
+
+    /// Precedence climbing: parses operators binding at least as tightly as
+    /// `min_precedence`, folding equal levels to the left unless the operator
+    /// is right-associative.
+    fn parse_binary_expression(&mut self, min_precedence: u8) -> Result<Spanned<AstNode>, String> {
+        let start = self.tokens.span();
+        let mut left = self.parse_cast()?;
+
+        loop {
+            let op = match self.tokens.peek().and_then(BinaryOperator::from_token) {
+                Some(op) if op.precedence() >= min_precedence => op,
+                _ => break,
+            };
+            self.tokens.advance();
+
+            let next = if op.is_right_associative() { op.precedence() } else { op.precedence() + 1 };
+            let right = self.parse_binary_expression(next)?;
+            left = self.finish(start, AstNode::BinaryOp {
+                op,
+                left: Box::new(left),
+                right: Box::new(right),
+            });
+        }
+
+        Ok(left)
+    }
+
+    /// `as` binds tighter than any binary operator but looser than unary
+    /// ones, so `-x as f64 * y` is `((-x) as f64) * y`.
+    fn parse_cast(&mut self) -> Result<Spanned<AstNode>, String> {
+        let start = self.tokens.span();
+        let mut value = self.parse_unary()?;
+        while self.tokens.eat(&Token::As) {
+            let target = self.parse_type()?;
+            value = self.finish(start, AstNode::Cast {
+                value: Box::new(value),
+                target,
+            });
+        }
+        Ok(value)
+    }
+
+    fn parse_unary(&mut self) -> Result<Spanned<AstNode>, String> {
+        let start = self.tokens.span();
+        let op = match self.tokens.peek() {
+            Some(Token::Minus) => {
+                // `-` directly before a numeric literal folds into a negative
+                // constant, unless the literal is the base of a power
+                let literal = match self.tokens.peek_n(1) {
+                    _ if self.tokens.peek_n(2) == Some(&Token::Power) => None,
+                    Some(Token::Number(n)) => Some(AstNode::Number(-n)),
+                    Some(Token::FloatLiteral(n)) => Some(AstNode::Float(-n)),
+                    _ => None,
+                };
+                if let Some(literal) = literal {
+                    self.tokens.advance();
+                    self.tokens.advance();
+                    return Ok(self.finish(start, literal));
+                }
+                UnaryOperator::Negate
+            },
+            Some(Token::Not) => UnaryOperator::Not,
+            _ => return self.parse_power(),
+        };
+        self.tokens.advance();
+
+        let operand = self.parse_unary()?;
+        Ok(self.finish(start, AstNode::UnaryOp {
+            op,
+            operand: Box::new(operand),
+        }))
+    }
+
+    /// `**` binds tighter than a prefix operator on its base, so `-a ** b`
+    /// is `-(a ** b)`, while its exponent may carry one, as in `a ** -b`. It
+    /// groups to the right and takes a cast exponent whole.
+    fn parse_power(&mut self) -> Result<Spanned<AstNode>, String> {
+        let start = self.tokens.span();
+        let base = self.parse_postfix()?;
+        if !self.tokens.eat(&Token::Power) {
+            return Ok(base);
+        }
+        let exponent = self.parse_cast()?;
+        Ok(self.finish(start, AstNode::BinaryOp {
+            op: BinaryOperator::Power,
+            left: Box::new(base),
+            right: Box::new(exponent),
+        }))
+    }
+
+    fn parse_postfix(&mut self) -> Result<Spanned<AstNode>, String> {
+        let start = self.tokens.span();
+        let mut expr = self.parse_primary()?;
+
+        loop {
+            if self.tokens.eat(&Token::LeftBracket) {
+                let index = self.parse_expression()?;
+                self.tokens.expect(&Token::RightBracket, "Expected ']' after index")?;
+                expr = self.finish(start, AstNode::Index {
+                    array: Box::new(expr),
+                    index: Box::new(index),
+                });
+            } else if self.tokens.eat(&Token::Dot) {
+                let field = self.expect_identifier("Expected field name after '.'")?;
+                let node = if self.tokens.eat(&Token::LeftParen) {
+                    AstNode::MethodCall {
+                        receiver: Box::new(expr),
+                        method: field,
+                        args: self.parse_arguments()?,
+                    }
+                } else {
+                    AstNode::FieldAccess {
+                        object: Box::new(expr),
+                        field,
+                    }
+                };
+                expr = self.finish(start, node);
+            } else if self.tokens.check(&Token::Question) {
+                // `?` is reserved for Result/Option propagation
+                return Err(self.tokens.error("The '?' operator is not yet supported"));
+            } else {
+                break;
+            }
+        }
+
+        Ok(expr)
+    }
+
+    /// Parses call arguments up to and including the closing ')'.
+    fn parse_arguments(&mut self) -> Result<Vec<Spanned<AstNode>>, String> {
+        let mut args = Vec::new();
+        while !self.tokens.eat(&Token::RightParen) {
+            args.push(self.parse_expression()?);
+            if !self.tokens.eat(&Token::Comma) {
+                self.tokens.expect(&Token::RightParen, "Expected ',' or ')' after argument")?;
+                break;
+            }
+        }
+        Ok(args)
+    }
+
+    fn parse_closure(&mut self) -> Result<Spanned<AstNode>, String> {
+        let start = self.tokens.span();
+        // `||` lexes as a single token and starts a closure without parameters
+        let mut params = Vec::new();
+        if !self.tokens.eat(&Token::Or) {
+            self.tokens.expect(&Token::BitOr, "Expected '|' to begin closure")?;
+            while !self.tokens.eat(&Token::BitOr) {
+                let param_name = self.expect_identifier("Expected closure parameter name")?;
+                self.tokens.expect(&Token::Colon, "Expected ':' after closure parameter name")?;
+                params.push((param_name, self.parse_type()?));
+                if !self.tokens.eat(&Token::Comma) {
+                    self.tokens.expect(&Token::BitOr, "Expected ',' or '|' after closure parameter")?;
+                    break;
+                }
+            }
+        }
+
+        let return_type = if self.tokens.eat(&Token::Arrow) {
+            Some(self.parse_type()?)
+        } else {
+            None
+        };
+        let body = self.parse_expression()?;
+
+        Ok(self.finish(start, AstNode::Closure {
+            params,
+            return_type,
+            body: Box::new(body),
+        }))
+    }
+
+    fn parse_array_literal(&mut self) -> Result<Spanned<AstNode>, String> {
+        let start = self.tokens.span();
+        self.tokens.advance(); // consume '['
+
+        let mut elements = Vec::new();
+        while !self.tokens.eat(&Token::RightBracket) {
+            elements.push(self.parse_expression()?);
+            if !self.tokens.eat(&Token::Comma) {
+                self.tokens.expect(&Token::RightBracket, "Expected ',' or ']' in array literal")?;
+                break;
+            }
+        }
+
+        Ok(self.finish(start, AstNode::ArrayLiteral(elements)))
+    }
+
+    fn parse_struct_literal(&mut self) -> Result<Spanned<AstNode>, String> {
+        let start = self.tokens.span();
+        let name = self.expect_identifier("Expected struct name")?;
+        self.tokens.advance(); // consume '{'
+
+        let mut fields = Vec::new();
+        while !self.tokens.eat(&Token::RightBrace) {
+            let field = self.expect_identifier("Expected field name")?;
+            self.tokens.expect(&Token::Colon, "Expected ':' after field name")?;
+            fields.push((field, self.parse_expression()?));
+            if !self.tokens.eat(&Token::Comma) {
+                self.tokens.expect(&Token::RightBrace, "Expected ',' or '}' in struct literal")?;
+                break;
+            }
+        }
+
+        Ok(self.finish(start, AstNode::StructLiteral { name, fields }))
+    }
+
+    fn parse_primary(&mut self) -> Result<Spanned<AstNode>, String> {
+        let start = self.tokens.span();
+        let node = match self.tokens.peek() {
+            Some(Token::Number(n)) => AstNode::Number(*n),
+            Some(Token::FloatLiteral(n)) => AstNode::Float(*n),
+            Some(Token::CharLiteral(c)) => AstNode::Char(*c),
+            Some(Token::StringLiteral(s)) => AstNode::StringLiteral(s.clone()),
+            Some(Token::FormatString(_)) => return self.parse_format_string(),
+            Some(Token::True) => AstNode::Boolean(true),
+            Some(Token::False) => AstNode::Boolean(false),
+            Some(Token::Identifier(_))
+                if !self.no_struct_literal && self.tokens.peek_n(1) == Some(&Token::LeftBrace) =>
+            {
+                return self.parse_struct_literal();
+            },
+            Some(Token::Identifier(name))
+                if self.tokens.peek_n(1) == Some(&Token::DoubleColon)
+                    || (self.tokens.peek_n(1) == Some(&Token::Dot) && self.enum_names.contains(name)) =>
+            {
+                let enum_name = self.expect_identifier("Expected enum name")?;
+                self.tokens.advance(); // consume '::' or '.'
+                let variant = self.expect_identifier("Expected variant name after the enum name")?;
+                let args = if self.tokens.eat(&Token::LeftParen) { self.parse_arguments()? } else { Vec::new() };
+                return Ok(self.finish(start, AstNode::EnumVariant { enum_name, variant, args }));
+            },
+            Some(Token::Identifier(_)) if self.tokens.peek_n(1) == Some(&Token::LeftParen) => {
+                let callee = self.expect_identifier("Expected function name")?;
+                self.tokens.advance(); // consume '('
+                let args = self.parse_arguments()?;
+                return Ok(self.finish(start, AstNode::Call { callee, args }));
+            },
+            Some(Token::Identifier(name)) => AstNode::Identifier(name.clone()),
+            Some(Token::If) => return self.parse_if(),
+            Some(Token::Match) => return self.parse_match(),
+            Some(Token::LeftBracket) => return self.parse_array_literal(),
+            Some(Token::LeftBrace) => {
+                self.tokens.advance();
+                return self.parse_block();
+            },
+            Some(Token::LeftParen) => {
+                self.tokens.advance();
+                // struct literals are unambiguous again inside parentheses
+                let previous = std::mem::replace(&mut self.no_struct_literal, false);
+                let expr = self.parse_expression();
+                self.no_struct_literal = previous;
+                let mut expr = expr?;
+                self.tokens.expect(&Token::RightParen, "Expected ')' after expression")?;
+                expr.span = start.to(self.tokens.previous_span());
+                return Ok(expr);
+            },
+            Some(Token::BitOr | Token::Or) => return self.parse_closure(),
+            _ => return Err(self.tokens.error("Expected expression")),
+        };
+        self.tokens.advance();
+        Ok(self.finish(start, node))
+    }
+
+    /// Turns the segments of an `f"..."` token into a `FormatString`, parsing
+    /// each embedded expression with a parser of its own.
+    fn parse_format_string(&mut self) -> Result<Spanned<AstNode>, String> {
+        let span = self.tokens.span();
+        let Some(Token::FormatString(segments)) = self.tokens.advance() else {
+            return Err(self.tokens.error("Expected format string"));
+        };
+
+        let mut parts = Vec::new();
+        for segment in segments {
+            match segment {
+                FormatSegment::Text(text) => parts.push(Spanned::new(AstNode::StringLiteral(text), span)),
+                FormatSegment::Code(code) if code.is_empty() => {
+                    return Err(format!("Expected expression between '{{' and '}}' in format string at {}", span));
+                },
+                FormatSegment::Code(code) => {
+                    // the enums are declared in the program, not in the braces
+                    let mut parser = Parser::new(code);
+                    parser.enum_names = self.enum_names.clone();
+                    let expr = parser.parse_expression()?;
+                    if !parser.tokens.is_at_end() {
+                        return Err(parser.tokens.error("Expected '}' after interpolated expression"));
+                    }
+                    parts.push(expr);
+                },
+            }
+        }
+
+        Ok(Spanned::new(AstNode::FormatString { parts }, span))
+    }
+
+    /// Parses `import a.b;` or `module a.b;`.
+    fn parse_import(&mut self) -> Result<Spanned<AstNode>, String> {
+        let start = self.tokens.span();
+        let is_module = self.tokens.advance() == Some(Token::Module);
+        let keyword = if is_module { "module" } else { "import" };
+
+        let mut path = vec![self.expect_identifier(&format!("Expected module name after '{}'", keyword))?];
+        while self.tokens.eat(&Token::Dot) {
+            path.push(self.expect_identifier("Expected module name after '.'")?);
+        }
+        self.tokens.expect(&Token::Semicolon, &format!("Expected ';' after {}", keyword))?;
+
+        let node = if is_module { AstNode::Module(path) } else { AstNode::Import(path) };
+        Ok(self.finish(start, node))
+    }
+
+    fn parse_const(&mut self, doc: Option<String>) -> Result<Spanned<AstNode>, String> {
+        let start = self.tokens.span();
+        self.tokens.advance(); // consume 'const'
+
+        let name = self.expect_identifier("Expected constant name")?;
+        self.tokens.expect(&Token::Colon, "Expected ':' and a type after constant name")?;
+        let type_annotation = self.parse_type()?;
+        self.tokens.expect(&Token::Equals, "Expected '=' after constant type")?;
+        let value = self.parse_expression()?;
+        self.tokens.expect(&Token::Semicolon, "Expected ';' after constant declaration")?;
+
+        Ok(self.finish(start, AstNode::Const {
+            name,
+            type_annotation,
+            value: Box::new(value),
+            doc,
+        }))
+    }
+
+    fn parse_let_statement(&mut self) -> Result<Spanned<AstNode>, String> {
+        let start = self.tokens.span();
+        self.tokens.advance(); // consume 'let'
+
+        let mutable = self.tokens.eat(&Token::Mut);
+        let name = self.expect_identifier("Expected variable name")?;
+
+        let type_annotation = if self.tokens.eat(&Token::Colon) {
+            Some(self.parse_type()?)
+        } else {
+            None
+        };
+
+        self.tokens.expect(&Token::Equals, "Expected '=' after type annotation")?;
+
+        let value = self.parse_expression()?;
+
+        self.tokens.expect(&Token::Semicolon, "Expected ';' after let statement")?;
+
+        Ok(self.finish(start, AstNode::Let {
+            name,
+            mutable,
+            type_annotation,
+            value: Box::new(value),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Token;
+
+    fn spanned(tokens: Vec<Token>) -> Vec<(Token, Span)> {
+        tokens.into_iter().map(|token| (token, Span::default())).collect()
+    }
+
+    #[test]
+    fn test_parse_function() {
+        let tokens = vec![
             Token::Function,
             Token::Identifier("main".to_string()),
             Token::LeftParen,
@@ -304,22 +1520,23 @@ mod tests {
             Token::RightBrace,
         ];
         
-        let mut parser = Parser::new(tokens);
+        let mut parser = Parser::new(spanned(tokens));
         let ast = parser.parse().unwrap();
         
         match ast {
             AstNode::Program(nodes) => {
                 assert_eq!(nodes.len(), 1);
-                match &nodes[0] {
-                    AstNode::Function { name, params, body } => {
+                match &nodes[0].node {
+                    AstNode::Function { name, params, return_type, body, .. } => {
                         assert_eq!(name, "main");
                         assert!(params.is_empty());
-                        match &**body {
+                        assert_eq!(*return_type, Type::I32);
+                        match &body.node {
                             AstNode::Program(statements) => {
                                 assert_eq!(statements.len(), 1);
-                                match &statements[0] {
-                                    AstNode::Return(expr) => {
-                                        match &**expr {
+                                match &statements[0].node {
+                                    AstNode::Return(Some(expr)) => {
+                                        match &expr.node {
                                             AstNode::Number(n) => assert_eq!(*n, 42),
                                             _ => panic!("Expected number in return statement"),
                                         }
@@ -336,4 +1553,974 @@ mod tests {
             _ => panic!("Expected program node"),
         }
     }
+
+    #[test]
+    fn test_parse_string_literal() {
+        let tokens = vec![
+            Token::Let,
+            Token::Identifier("s".to_string()),
+            Token::Equals,
+            Token::StringLiteral("hello".to_string()),
+            Token::Semicolon,
+        ];
+
+        let mut parser = Parser::new(spanned(tokens));
+        match parser.parse().unwrap() {
+            AstNode::Program(nodes) => match &nodes[0].node {
+                AstNode::Let { value, .. } => match &value.node {
+                    AstNode::StringLiteral(s) => assert_eq!(s, "hello"),
+                    _ => panic!("Expected string literal"),
+                },
+                _ => panic!("Expected let statement"),
+            },
+            _ => panic!("Expected program node"),
+        }
+    }
+
+    #[test]
+    fn test_parse_arrow_return_type() {
+        let tokens = vec![
+            Token::Function,
+            Token::Identifier("main".to_string()),
+            Token::LeftParen,
+            Token::RightParen,
+            Token::Arrow,
+            Token::TypeInt,
+            Token::LeftBrace,
+            Token::Return,
+            Token::Number(0),
+            Token::Semicolon,
+            Token::RightBrace,
+        ];
+
+        let mut parser = Parser::new(spanned(tokens));
+        assert!(parser.parse().is_ok());
+    }
+
+    #[test]
+    fn test_error_reports_position() {
+        let mut parser = Parser::new(tokenize("fn main(): i32 {\n    return 42\n}").unwrap());
+        assert_eq!(parser.parse().unwrap_err(), "Expected ';' after return statement at line 3, column 1");
+    }
+
+    #[test]
+    fn test_doc_comment_attached_to_function() {
+        let source = "/// The entry point.\n/// Returns zero.\nfn main(): i32 { return 0; }";
+        let mut parser = Parser::new(tokenize(source).unwrap());
+        match parser.parse().unwrap() {
+            AstNode::Program(nodes) => match &nodes[0].node {
+                AstNode::Function { doc, .. } => {
+                    assert_eq!(doc.as_deref(), Some("The entry point.\nReturns zero."));
+                },
+                _ => panic!("Expected function node"),
+            },
+            _ => panic!("Expected program node"),
+        }
+    }
+
+    #[test]
+    fn test_doc_comment_attached_to_declarations() {
+        let source = "/// A point.\nstruct P { x: i32 }\n/// Colors.\nenum C { Red }\n/// The limit.\nconst MAX: i32 = 1;\n\
+                      /// Meters.\ntype M = i32;\n/// Printable.\ntrait Show {\n/// Shows it.\nfn show(self): string;\n}\n\
+                      /// Points show.\nimpl Show for P {\n/// Shows a point.\nfn show(self): string { \"p\" } }";
+        let AstNode::Program(nodes) = Parser::new(tokenize(source).unwrap()).parse().unwrap() else {
+            panic!("Expected program node");
+        };
+        let docs: Vec<Option<&str>> = nodes.iter()
+            .map(|node| match &node.node {
+                AstNode::StructDef { doc, .. }
+                | AstNode::EnumDef { doc, .. }
+                | AstNode::Const { doc, .. }
+                | AstNode::TypeAlias { doc, .. }
+                | AstNode::TraitDef { doc, .. }
+                | AstNode::Impl { doc, .. } => doc.as_deref(),
+                other => panic!("Unexpected node {:?}", other),
+            })
+            .collect();
+        assert_eq!(docs, [
+            Some("A point."), Some("Colors."), Some("The limit."), Some("Meters."), Some("Printable."), Some("Points show."),
+        ]);
+        match (&nodes[4].node, &nodes[5].node) {
+            (AstNode::TraitDef { methods, .. }, AstNode::Impl { methods: impl_methods, .. }) => {
+                assert_eq!(methods[0].doc.as_deref(), Some("Shows it."));
+                assert!(matches!(&impl_methods[0].node, AstNode::Function { doc: Some(doc), .. } if doc == "Shows a point."));
+            },
+            _ => panic!("Expected a trait and an impl"),
+        }
+    }
+
+    #[test]
+    fn test_doc_comment_on_field_or_variant_is_ignored() {
+        let source = "struct P {\n /// Across.\n x: i32,\n /// Down.\n y: i32 }\nenum C {\n /// Warm.\n Red,\n Blue }";
+        let AstNode::Program(nodes) = Parser::new(tokenize(source).unwrap()).parse().unwrap() else {
+            panic!("Expected program node");
+        };
+        assert!(matches!(&nodes[0].node, AstNode::StructDef { fields, .. } if fields.len() == 2));
+        assert!(matches!(&nodes[1].node, AstNode::EnumDef { variants, .. } if variants.len() == 2));
+    }
+
+    #[test]
+    fn test_doc_comment_on_variable_or_import_is_rejected() {
+        for source in ["/// The limit.\nlet max = 1;", "/// Maths.\nimport math;"] {
+            assert_eq!(
+                Parser::new(tokenize(source).unwrap()).parse().unwrap_err(),
+                "Doc comments can only document a declaration, not a variable or an import at line 2, column 1"
+            );
+        }
+    }
+
+    #[test]
+    fn test_doc_comment_inside_body_is_ignored() {
+        let source = "fn f(): i32 {\n /// doc\n let x = 1;\n /// tail\n x }";
+        let AstNode::Program(nodes) = Parser::new(tokenize(source).unwrap()).parse().unwrap() else {
+            panic!("Expected program node");
+        };
+        match &nodes[0].node {
+            AstNode::Function { body, .. } => {
+                assert!(matches!(&body.node, AstNode::Program(statements) if statements.len() == 2));
+            },
+            _ => panic!("Expected function node"),
+        }
+    }
+
+    #[test]
+    fn test_question_mark_not_supported() {
+        let mut parser = Parser::new(tokenize("fn main(): i32 { return x?; }").unwrap());
+        assert_eq!(
+            parser.parse().unwrap_err(),
+            "The '?' operator is not yet supported at line 1, column 26"
+        );
+    }
+
+    #[test]
+    fn test_parse_module_paths() {
+        let mut parser = Parser::new(tokenize("module app.main; import std.io;").unwrap());
+        match parser.parse().unwrap() {
+            AstNode::Program(nodes) => {
+                assert!(matches!(&nodes[0].node, AstNode::Module(path) if path == &["app", "main"]));
+                assert!(matches!(&nodes[1].node, AstNode::Import(path) if path == &["std", "io"]));
+            },
+            _ => panic!("Expected program node"),
+        }
+
+        let mut parser = Parser::new(tokenize("import std.;").unwrap());
+        assert_eq!(parser.parse().unwrap_err(), "Expected module name after '.' at line 1, column 12");
+    }
+
+    #[test]
+    fn test_parse_bare_return() {
+        let mut parser = Parser::new(tokenize("fn f(): void { return; return 1; }").unwrap());
+        match parser.parse().unwrap() {
+            AstNode::Program(nodes) => match &nodes[0].node {
+                AstNode::Function { return_type, body, .. } => match &body.node {
+                    AstNode::Program(statements) => {
+                        assert_eq!(*return_type, Type::Void);
+                        assert!(matches!(statements[0].node, AstNode::Return(None)));
+                        assert!(matches!(statements[1].node, AstNode::Return(Some(_))));
+                    },
+                    _ => panic!("Expected function body"),
+                },
+                _ => panic!("Expected function"),
+            },
+            _ => panic!("Expected program node"),
+        }
+    }
+
+    #[test]
+    fn test_parse_conditional_expression() {
+        let mut parser = Parser::new(tokenize("let x = 1 + if a < b then a else b * 2;").unwrap());
+        match parser.parse().unwrap() {
+            AstNode::Program(nodes) => match &nodes[0].node {
+                AstNode::Let { value, .. } => match &value.node {
+                    AstNode::BinaryOp { op: BinaryOperator::Add, right, .. } => match &right.node {
+                        AstNode::Conditional { cond, then_value, else_value } => {
+                            assert!(matches!(cond.node, AstNode::BinaryOp { op: BinaryOperator::Lt, .. }));
+                            assert!(matches!(&then_value.node, AstNode::Identifier(name) if name == "a"));
+                            assert!(matches!(else_value.node, AstNode::BinaryOp { op: BinaryOperator::Multiply, .. }));
+                        },
+                        _ => panic!("Expected conditional expression"),
+                    },
+                    _ => panic!("Expected binary operation"),
+                },
+                _ => panic!("Expected let statement"),
+            },
+            _ => panic!("Expected program node"),
+        }
+
+        let mut parser = Parser::new(tokenize("let x = if a then 1;").unwrap());
+        assert_eq!(parser.parse().unwrap_err(), "Expected 'else' after the 'then' branch at line 1, column 20");
+    }
+
+    #[test]
+    fn test_trailing_commas() {
+        let source = "fn f<T,>(a: i32, b: fn(i32,) -> i32,): i32 { return g(a, |x: i32,| x,); }";
+        let mut parser = Parser::new(tokenize(source).unwrap());
+        match parser.parse().unwrap() {
+            AstNode::Program(nodes) => match &nodes[0].node {
+                AstNode::Function { type_params, params, .. } => {
+                    assert_eq!(type_params.len(), 1);
+                    assert_eq!(params.len(), 2);
+                    assert_eq!(params[1].1, Type::function(vec![Type::I32], Type::I32));
+                },
+                _ => panic!("Expected function"),
+            },
+            _ => panic!("Expected program node"),
+        }
+
+        let mut parser = Parser::new(tokenize("fn f(a: i32 b: i32): i32 { return 0; }").unwrap());
+        assert_eq!(parser.parse().unwrap_err(), "Expected ',' or ')' after parameter at line 1, column 13");
+        let mut parser = Parser::new(tokenize("fn f(): i32 { return g(1 2); }").unwrap());
+        assert_eq!(parser.parse().unwrap_err(), "Expected ',' or ')' after argument at line 1, column 26");
+    }
+
+    #[test]
+    fn test_from_source() {
+        let mut parser = Parser::from_source("let x = 1;").unwrap();
+        assert!(matches!(parser.parse().unwrap(), AstNode::Program(nodes) if nodes.len() == 1));
+
+        let errors = Parser::from_source("let x = @;").err().unwrap();
+        assert_eq!(errors[0].to_string(), "unexpected character '@' at line 1, column 9");
+    }
+
+    #[test]
+    fn test_parse_type_alias() {
+        let mut parser = Parser::from_source("type Grid = [[u8; 3]; 3];").unwrap();
+        match parser.parse().unwrap() {
+            AstNode::Program(nodes) => match &nodes[0].node {
+                AstNode::TypeAlias { name, target, .. } => {
+                    assert_eq!(name, "Grid");
+                    assert_eq!(*target, Type::array(Type::array(Type::U8, 3), 3));
+                },
+                _ => panic!("Expected type alias"),
+            },
+            _ => panic!("Expected program node"),
+        }
+    }
+
+    #[test]
+    fn test_block_tail_expressions() {
+        let source = "fn f(): i32 { let a = { let b = 1; b }; { a } }";
+        let mut parser = Parser::from_source(source).unwrap();
+        match parser.parse().unwrap() {
+            AstNode::Program(nodes) => match &nodes[0].node {
+                AstNode::Function { body, .. } => match &body.node {
+                    AstNode::Program(statements) => {
+                        match &statements[0].node {
+                            AstNode::Let { value, .. } => assert!(matches!(
+                                &value.node,
+                                AstNode::Program(inner) if matches!(inner[1].node, AstNode::Identifier(_))
+                            )),
+                            _ => panic!("Expected let statement"),
+                        }
+                        assert!(matches!(&statements[1].node, AstNode::Program(inner) if inner.len() == 1));
+                    },
+                    _ => panic!("Expected function body"),
+                },
+                _ => panic!("Expected function"),
+            },
+            _ => panic!("Expected program node"),
+        }
+    }
+
+    #[test]
+    fn test_parse_import() {
+        let mut parser = Parser::new(tokenize("import math;").unwrap());
+        match parser.parse().unwrap() {
+            AstNode::Program(nodes) => match &nodes[0].node {
+                AstNode::Import(path) => assert_eq!(path, &vec!["math".to_string()]),
+                _ => panic!("Expected import node"),
+            },
+            _ => panic!("Expected program node"),
+        }
+    }
+
+    #[test]
+    fn test_parse_negative_literals() {
+        let mut parser = Parser::new(tokenize("let x = -5 - -2;").unwrap());
+        match parser.parse().unwrap() {
+            AstNode::Program(nodes) => match &nodes[0].node {
+                AstNode::Let { value, .. } => match &value.node {
+                    AstNode::BinaryOp { op, left, right } => {
+                        assert_eq!(*op, BinaryOperator::Subtract);
+                        assert!(matches!(left.node, AstNode::Number(-5)));
+                        assert!(matches!(right.node, AstNode::Number(-2)));
+                    },
+                    _ => panic!("Expected binary operation"),
+                },
+                _ => panic!("Expected let statement"),
+            },
+            _ => panic!("Expected program node"),
+        }
+    }
+
+    #[test]
+    fn test_parse_format_string() {
+        let mut parser = Parser::new(tokenize("let s = f\"{name} is {age + 1}!\";").unwrap());
+        match parser.parse().unwrap() {
+            AstNode::Program(nodes) => match &nodes[0].node {
+                AstNode::Let { value, .. } => match &value.node {
+                    AstNode::FormatString { parts } => {
+                        assert_eq!(parts.len(), 4);
+                        assert!(matches!(&parts[0].node, AstNode::Identifier(name) if name == "name"));
+                        assert!(matches!(&parts[1].node, AstNode::StringLiteral(text) if text == " is "));
+                        assert!(matches!(parts[2].node, AstNode::BinaryOp { op: BinaryOperator::Add, .. }));
+                        assert_eq!(parts[2].span.column, 22);
+                        assert!(matches!(&parts[3].node, AstNode::StringLiteral(text) if text == "!"));
+                    },
+                    _ => panic!("Expected format string"),
+                },
+                _ => panic!("Expected let statement"),
+            },
+            _ => panic!("Expected program node"),
+        }
+
+        let mut parser = Parser::new(tokenize("let s = f\"{a b}\";").unwrap());
+        assert_eq!(
+            parser.parse().unwrap_err(),
+            "Expected '}' after interpolated expression at line 1, column 14"
+        );
+        let mut parser = Parser::new(tokenize("let s = f\"{}\";").unwrap());
+        assert!(parser.parse().is_err());
+
+        let source = "let s = f\"{Color.Red}\"; enum Color { Red }";
+        let AstNode::Program(nodes) = Parser::from_source(source).unwrap().parse().unwrap() else {
+            panic!("Expected program node")
+        };
+        assert!(matches!(&nodes[0].node, AstNode::Let { value, .. } if matches!(&value.node,
+            AstNode::FormatString { parts } if matches!(&parts[0].node,
+                AstNode::EnumVariant { enum_name, variant, .. } if enum_name == "Color" && variant == "Red"))));
+    }
+
+    #[test]
+    fn test_parse_primitive_type_names() {
+        let mut parser = Parser::from_source("let a: char = 'a'; let b: [u16; 2] = [1, 2]; let c: Chars = a;").unwrap();
+        match parser.parse().unwrap() {
+            AstNode::Program(nodes) => {
+                let annotations: Vec<_> = nodes.iter()
+                    .map(|node| match &node.node {
+                        AstNode::Let { type_annotation, .. } => type_annotation.clone(),
+                        _ => panic!("Expected let"),
+                    })
+                    .collect();
+                assert_eq!(annotations, [
+                    Some(Type::Char),
+                    Some(Type::array(Type::U16, 2)),
+                    Some(Type::Struct("Chars".to_string())),
+                ]);
+            },
+            _ => panic!("Expected program node"),
+        }
+        assert!(Parser::from_source("let a: 1 = 1;").unwrap().parse().is_err());
+    }
+
+    #[test]
+    fn test_parse_option_type() {
+        let annotation = |source: &str| match Parser::from_source(source).unwrap().parse()?.children()[0].node.clone() {
+            AstNode::Let { type_annotation, .. } => Ok(type_annotation),
+            _ => panic!("Expected let"),
+        };
+        assert_eq!(annotation("let a: Option<i32> = Option::None;"), Ok(Some(Type::option(Type::I32))));
+        // `>>` closes both lists
+        assert_eq!(
+            annotation("let a: Option<Option<[u8; 2]>> = Option::None;"),
+            Ok(Some(Type::option(Type::option(Type::array(Type::U8, 2)))))
+        );
+        assert_eq!(annotation("let a: Option = 1;"), Err("Expected '<' after 'Option' at line 1, column 15".to_string()));
+        assert_eq!(
+            annotation("let a: Option<i32 = 1;"),
+            Err("Expected '>' after the type of an Option at line 1, column 19".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_generic_function() {
+        let source = "fn id<T>(x: T): T { let y: T = x; return y; } fn other(x: T): i32 { return 0; }";
+        let mut parser = Parser::new(tokenize(source).unwrap());
+        match parser.parse().unwrap() {
+            AstNode::Program(nodes) => {
+                match &nodes[0].node {
+                    AstNode::Function { type_params, params, return_type, body, .. } => {
+                        assert_eq!(type_params, &vec![("T".to_string(), vec![])]);
+                        assert_eq!(params[0].1, Type::Param("T".to_string()));
+                        assert_eq!(*return_type, Type::Param("T".to_string()));
+                        match &body.node {
+                            AstNode::Program(statements) => assert!(matches!(
+                                &statements[0].node,
+                                AstNode::Let { type_annotation: Some(Type::Param(name)), .. } if name == "T"
+                            )),
+                            _ => panic!("Expected function body"),
+                        }
+                    },
+                    _ => panic!("Expected function"),
+                }
+                // `T` is out of scope after the generic function ends
+                match &nodes[1].node {
+                    AstNode::Function { type_params, params, .. } => {
+                        assert!(type_params.is_empty());
+                        assert_eq!(params[0].1, Type::Struct("T".to_string()));
+                    },
+                    _ => panic!("Expected function"),
+                }
+            },
+            _ => panic!("Expected program node"),
+        }
+
+        let mut parser = Parser::new(tokenize("fn f<T, T>(x: T): T { return x; }").unwrap());
+        assert!(parser.parse().unwrap_err().starts_with("Duplicate type parameter T"));
+    }
+
+    #[test]
+    fn test_operator_precedence() {
+        fn shape(node: &AstNode) -> String {
+            match node {
+                AstNode::BinaryOp { op, left, right } => {
+                    format!("({:?} {} {})", op, shape(left), shape(right))
+                },
+                AstNode::UnaryOp { operand, .. } => format!("(! {})", shape(operand)),
+                AstNode::Cast { value, target } => format!("(as {} {})", target, shape(value)),
+                AstNode::Identifier(name) => name.clone(),
+                other => format!("{:?}", other),
+            }
+        }
+
+        let mut parser = Parser::new(tokenize("let x = a || b && !c == d + e * f < g; let y = a - b - (c - d); let z = a ** b ** c * d; let w = -a as f64 * b as i64 as u8 < c; let v = -a ** b ** -c; let u = a ** b as f64 * c; let t = -2 ** 2;").unwrap());
+        match parser.parse().unwrap() {
+            AstNode::Program(nodes) => {
+                let values: Vec<String> = nodes.iter().map(|node| match &node.node {
+                    AstNode::Let { value, .. } => shape(value),
+                    _ => panic!("Expected let statement"),
+                }).collect();
+                assert_eq!(values[0], "(Or a (And b (Lt (Eq (! c) (Add d (Multiply e f))) g)))");
+                assert_eq!(values[1], "(Subtract (Subtract a b) (Subtract c d))");
+                assert_eq!(values[2], "(Multiply (Power a (Power b c)) d)");
+                assert_eq!(values[3], "(Lt (Multiply (as f64 (! a)) (as u8 (as i64 b))) c)");
+                // `**` binds tighter than a prefix operator on its base
+                assert_eq!(values[4], "(! (Power a (Power b (! c))))");
+                assert_eq!(values[5], "(Multiply (Power a (as f64 b)) c)");
+                assert_eq!(values[6], "(! (Power Number(2) Number(2)))");
+            },
+            _ => panic!("Expected program node"),
+        }
+    }
+
+    #[test]
+    fn test_parse_unary_operators() {
+        let mut parser = Parser::new(tokenize("let x = -y; let b = !!done;").unwrap());
+        match parser.parse().unwrap() {
+            AstNode::Program(nodes) => {
+                match &nodes[0].node {
+                    AstNode::Let { value, .. } => assert!(matches!(
+                        &value.node,
+                        AstNode::UnaryOp { op: UnaryOperator::Negate, operand } if matches!(operand.node, AstNode::Identifier(_))
+                    )),
+                    _ => panic!("Expected let statement"),
+                }
+                match &nodes[1].node {
+                    AstNode::Let { value, .. } => assert!(matches!(
+                        &value.node,
+                        AstNode::UnaryOp { op: UnaryOperator::Not, operand } if matches!(operand.node, AstNode::UnaryOp { .. })
+                    )),
+                    _ => panic!("Expected let statement"),
+                }
+            },
+            _ => panic!("Expected program node"),
+        }
+    }
+
+    #[test]
+    fn test_parse_if_else_chain() {
+        let source = "fn f(): i32 { if a { return 1; } else if b { return 2; } else { return 3; } }";
+        let mut parser = Parser::new(tokenize(source).unwrap());
+        match parser.parse().unwrap() {
+            AstNode::Program(nodes) => match &nodes[0].node {
+                AstNode::Function { body, .. } => match &body.node {
+                    AstNode::Program(statements) => match &statements[0].node {
+                        AstNode::If { else_block: Some(else_block), .. } => match &else_block.node {
+                            AstNode::If { else_block: Some(_), .. } => {},
+                            _ => panic!("Expected nested if in else branch"),
+                        },
+                        _ => panic!("Expected if statement with else branch"),
+                    },
+                    _ => panic!("Expected program node for function body"),
+                },
+                _ => panic!("Expected function node"),
+            },
+            _ => panic!("Expected program node"),
+        }
+    }
+
+    #[test]
+    fn test_parse_if_expression() {
+        let mut parser = Parser::new(tokenize("let x = if ready { 1 } else { 2 };").unwrap());
+        match parser.parse().unwrap() {
+            AstNode::Program(nodes) => match &nodes[0].node {
+                AstNode::Let { value, .. } => match &value.node {
+                    AstNode::If { then_block, else_block: Some(_), .. } => match &then_block.node {
+                        AstNode::Program(statements) => assert!(matches!(&statements[..], [s] if matches!(s.node, AstNode::Number(1)))),
+                        _ => panic!("Expected program node for if branch"),
+                    },
+                    _ => panic!("Expected if expression"),
+                },
+                _ => panic!("Expected let statement"),
+            },
+            _ => panic!("Expected program node"),
+        }
+    }
+
+    #[test]
+    fn test_parse_block_statements() {
+        let source = "fn main(): i32 {
+            let x = 1;
+            x;
+            { let y = 2; }
+            while running { break; continue; }
+            if done { return 0; }
+            return x;
+        }";
+        let mut parser = Parser::new(tokenize(source).unwrap());
+        match parser.parse().unwrap() {
+            AstNode::Program(nodes) => match &nodes[0].node {
+                AstNode::Function { body, .. } => match &body.node {
+                    AstNode::Program(statements) => {
+                        assert!(matches!(statements[0].node, AstNode::Let { .. }));
+                        assert!(matches!(statements[1].node, AstNode::ExpressionStatement(_)));
+                        assert!(matches!(statements[2].node, AstNode::Program(_)));
+                        match &statements[3].node {
+                            AstNode::While { body, .. } => {
+                                assert!(matches!(&body.node, AstNode::Program(b) if matches!(&b[..], [first, second] if matches!(first.node, AstNode::Break) && matches!(second.node, AstNode::Continue))));
+                            },
+                            _ => panic!("Expected while loop"),
+                        }
+                        assert!(matches!(statements[4].node, AstNode::If { .. }));
+                        assert!(matches!(statements[5].node, AstNode::Return(_)));
+                    },
+                    _ => panic!("Expected program node for function body"),
+                },
+                _ => panic!("Expected function node"),
+            },
+            _ => panic!("Expected program node"),
+        }
+    }
+
+    #[test]
+    fn test_parse_assignment() {
+        let mut parser = Parser::new(tokenize("fn main(): i32 { x = 1; x += 2; return x; }").unwrap());
+        match parser.parse().unwrap() {
+            AstNode::Program(nodes) => match &nodes[0].node {
+                AstNode::Function { body, .. } => match &body.node {
+                    AstNode::Program(statements) => {
+                        assert!(matches!(&statements[0].node, AstNode::Assign { name, value } if name == "x" && matches!(value.node, AstNode::Number(1))));
+                        match &statements[1].node {
+                            AstNode::Assign { value, .. } => assert!(matches!(
+                                &value.node,
+                                AstNode::BinaryOp { op: BinaryOperator::Add, .. }
+                            )),
+                            _ => panic!("Expected compound assignment"),
+                        }
+                    },
+                    _ => panic!("Expected program node for function body"),
+                },
+                _ => panic!("Expected function node"),
+            },
+            _ => panic!("Expected program node"),
+        }
+    }
+
+    #[test]
+    fn test_parse_typed_params() {
+        let mut parser = Parser::new(tokenize("fn f(x: i32, flag: bool) -> u8 { return 0; }").unwrap());
+        match parser.parse().unwrap() {
+            AstNode::Program(nodes) => match &nodes[0].node {
+                AstNode::Function { params, return_type, .. } => {
+                    assert_eq!(params, &vec![("x".to_string(), Type::I32), ("flag".to_string(), Type::Bool)]);
+                    assert_eq!(*return_type, Type::U8);
+                },
+                _ => panic!("Expected function node"),
+            },
+            _ => panic!("Expected program node"),
+        }
+    }
+
+    #[test]
+    fn test_parse_arrays() {
+        let mut parser = Parser::new(tokenize("fn f(a: [i32; 3]): i32 { let b = [1, 2, 3]; return b[a[0]]; }").unwrap());
+        match parser.parse().unwrap() {
+            AstNode::Program(nodes) => match &nodes[0].node {
+                AstNode::Function { params, body, .. } => {
+                    assert_eq!(params[0].1, Type::array(Type::I32, 3));
+                    match &body.node {
+                        AstNode::Program(statements) => {
+                            assert!(matches!(&statements[0].node, AstNode::Let { value, .. } if matches!(&value.node, AstNode::ArrayLiteral(e) if e.len() == 3)));
+                            assert!(matches!(&statements[1].node, AstNode::Return(Some(e)) if matches!(&e.node, AstNode::Index { index, .. } if matches!(index.node, AstNode::Index { .. }))));
+                        },
+                        _ => panic!("Expected program node for function body"),
+                    }
+                },
+                _ => panic!("Expected function node"),
+            },
+            _ => panic!("Expected program node"),
+        }
+    }
+
+    #[test]
+    fn test_parse_structs() {
+        let source = "struct Point { x: i32, y: i32 }
+            fn f(): i32 { let p = Point { x: 1, y: 2 }; if p.x { return p.y; } return 0; }";
+        let mut parser = Parser::new(tokenize(source).unwrap());
+        match parser.parse().unwrap() {
+            AstNode::Program(nodes) => {
+                match &nodes[0].node {
+                    AstNode::StructDef { name, fields, .. } => {
+                        assert_eq!(name, "Point");
+                        assert_eq!(fields, &vec![("x".to_string(), Type::I32), ("y".to_string(), Type::I32)]);
+                    },
+                    _ => panic!("Expected struct definition"),
+                }
+                match &nodes[1].node {
+                    AstNode::Function { body, .. } => match &body.node {
+                        AstNode::Program(statements) => {
+                            assert!(matches!(&statements[0].node, AstNode::Let { value, .. } if matches!(&value.node, AstNode::StructLiteral { fields, .. } if fields.len() == 2)));
+                            // `p.x {` is a condition and a body, not a struct literal
+                            assert!(matches!(&statements[1].node, AstNode::If { cond, .. } if matches!(&cond.node, AstNode::FieldAccess { field, .. } if field == "x")));
+                        },
+                        _ => panic!("Expected program node for function body"),
+                    },
+                    _ => panic!("Expected function node"),
+                }
+            },
+            _ => panic!("Expected program node"),
+        }
+    }
+
+    #[test]
+    fn test_parse_enums() {
+        let source = "enum Color { Red, Green, Blue, } fn f(): i32 { let c = Color::Green; return 0; }";
+        let mut parser = Parser::new(tokenize(source).unwrap());
+        match parser.parse().unwrap() {
+            AstNode::Program(nodes) => {
+                assert!(matches!(&nodes[0].node, AstNode::EnumDef { name, variants, .. } if name == "Color" && variants.len() == 3));
+                match &nodes[1].node {
+                    AstNode::Function { body, .. } => match &body.node {
+                        AstNode::Program(statements) => {
+                            assert!(matches!(&statements[0].node, AstNode::Let { value, .. }
+                                if matches!(&value.node, AstNode::EnumVariant { enum_name, variant, args } if enum_name == "Color" && variant == "Green" && args.is_empty())));
+                        },
+                        _ => panic!("Expected program node for function body"),
+                    },
+                    _ => panic!("Expected function node"),
+                }
+            },
+            _ => panic!("Expected program node"),
+        }
+    }
+
+    #[test]
+    fn test_parse_enum_payloads() {
+        let source = "enum Shape { Empty, Rect(f64, f64,) } let s = Shape::Rect(1.0, 2.0);";
+        let mut parser = Parser::from_source(source).unwrap();
+        match parser.parse().unwrap() {
+            AstNode::Program(nodes) => {
+                match &nodes[0].node {
+                    AstNode::EnumDef { variants, .. } => assert_eq!(
+                        variants,
+                        &vec![("Empty".to_string(), vec![]), ("Rect".to_string(), vec![Type::F64, Type::F64])]
+                    ),
+                    _ => panic!("Expected enum definition"),
+                }
+                assert!(matches!(&nodes[1].node, AstNode::Let { value, .. }
+                    if matches!(&value.node, AstNode::EnumVariant { variant, args, .. } if variant == "Rect" && args.len() == 2)));
+            },
+            _ => panic!("Expected program node"),
+        }
+        assert!(Parser::from_source("enum E { A(i32 i32) }").unwrap().parse().is_err());
+    }
+
+    #[test]
+    fn test_parse_dotted_enum_variants() {
+        // the enum may be declared after its use; any other name is a field
+        let source = "fn f(p: Point): i32 { let c = Color.Red; let o = Option.Some(3); let x = p.x; \
+                      match c { Color.Red => 1, _ => 0 } } enum Color { Red }";
+        let AstNode::Program(nodes) = Parser::from_source(source).unwrap().parse().unwrap() else {
+            panic!("Expected program node")
+        };
+        let AstNode::Function { body, .. } = &nodes[0].node else { panic!("Expected function") };
+        let AstNode::Program(statements) = &body.node else { panic!("Expected function body") };
+        let values: Vec<&AstNode> = statements[..3].iter().map(|statement| match &statement.node {
+            AstNode::Let { value, .. } => &value.node,
+            _ => panic!("Expected let statement"),
+        }).collect();
+        assert!(matches!(values[0], AstNode::EnumVariant { enum_name, variant, args }
+            if enum_name == "Color" && variant == "Red" && args.is_empty()));
+        assert!(matches!(values[1], AstNode::EnumVariant { enum_name, variant, args }
+            if enum_name == "Option" && variant == "Some" && args.len() == 1));
+        assert!(matches!(values[2], AstNode::FieldAccess { field, .. } if field == "x"));
+        assert!(matches!(&statements[3].node, AstNode::Match { arms, .. }
+            if matches!(&arms[0].0.node, Pattern::Variant { enum_name, variant, .. } if enum_name == "Color" && variant == "Red")));
+    }
+
+    #[test]
+    fn test_parse_match() {
+        let source = "fn f(s: Shape): i32 { match s { Shape::Rect(w, _h) => { w } Shape::Empty => 0, 'a' => 1, _ => 2, } }";
+        let mut parser = Parser::from_source(source).unwrap();
+        match parser.parse().unwrap() {
+            AstNode::Program(nodes) => match &nodes[0].node {
+                AstNode::Function { body, .. } => match &body.node {
+                    AstNode::Program(statements) => match &statements[0].node {
+                        AstNode::Match { scrutinee, arms } => {
+                            assert!(matches!(&scrutinee.node, AstNode::Identifier(name) if name == "s"));
+                            assert_eq!(arms.len(), 4);
+                            assert!(matches!(&arms[0].0.node, Pattern::Variant { variant, bindings, .. }
+                                if variant == "Rect" && bindings == &["w", "_h"]));
+                            assert!(matches!(&arms[0].1.node, AstNode::Program(_)));
+                            assert!(matches!(&arms[1].0.node, Pattern::Variant { bindings, .. } if bindings.is_empty()));
+                            assert!(matches!(&arms[2].0.node, Pattern::Literal(AstNode::Char('a'))));
+                            assert!(matches!(&arms[3].0.node, Pattern::Wildcard));
+                        },
+                        _ => panic!("Expected match expression"),
+                    },
+                    _ => panic!("Expected program node for function body"),
+                },
+                _ => panic!("Expected function node"),
+            },
+            _ => panic!("Expected program node"),
+        }
+        assert!(Parser::from_source("fn f(): i32 { match s { _ => 1 _ => 2 } }").unwrap().parse().is_err());
+        assert!(Parser::from_source("fn f(): i32 { match s { Shape => 1 } }").unwrap().parse().is_err());
+    }
+
+    #[test]
+    fn test_parse_traits_and_impls() {
+        let source = "trait Show { fn show(self): string; fn same(self, other: Self): bool; fn ping(self); }\n\
+                      impl Show for Point { fn show(self): string { \"p\" } }\n\
+                      fn print<T: Show + Eq, U>(x: T, y: U): void {}";
+        let mut parser = Parser::from_source(source).unwrap();
+        match parser.parse().unwrap() {
+            AstNode::Program(nodes) => {
+                match &nodes[0].node {
+                    AstNode::TraitDef { name, methods, .. } => {
+                        assert_eq!(name, "Show");
+                        let self_type = || Type::Param("Self".to_string());
+                        assert_eq!(methods[1].params, vec![("self".to_string(), self_type()), ("other".to_string(), self_type())]);
+                        assert_eq!(methods[1].return_type, Type::Bool);
+                        assert_eq!(methods[2].return_type, Type::Void);
+                    },
+                    _ => panic!("Expected trait definition"),
+                }
+                match &nodes[1].node {
+                    AstNode::Impl { trait_name, target, methods, .. } => {
+                        assert_eq!(trait_name, "Show");
+                        assert_eq!(*target, Type::Struct("Point".to_string()));
+                        assert!(matches!(&methods[0].node, AstNode::Function { params, .. }
+                            if params == &[("self".to_string(), Type::Struct("Point".to_string()))]));
+                    },
+                    _ => panic!("Expected impl"),
+                }
+                assert!(matches!(&nodes[2].node, AstNode::Function { type_params, .. } if type_params == &[
+                    ("T".to_string(), vec!["Show".to_string(), "Eq".to_string()]),
+                    ("U".to_string(), vec![]),
+                ]));
+            },
+            _ => panic!("Expected program node"),
+        }
+        assert!(Parser::from_source("trait T { fn f(x: i32); }").unwrap().parse().is_err());
+        assert!(Parser::from_source("fn f(self: i32): i32 { 0 }").unwrap().parse().is_err());
+        assert!(Parser::from_source("impl Show Point {}").unwrap().parse().is_err());
+    }
+
+    #[test]
+    fn test_parse_chained_field_access() {
+        let mut parser = Parser::new(tokenize("fn f(): i32 { return a.b[0].c; }").unwrap());
+        match parser.parse().unwrap() {
+            AstNode::Program(nodes) => match &nodes[0].node {
+                AstNode::Function { body, .. } => match &body.node {
+                    AstNode::Program(statements) => match &statements[0].node {
+                        AstNode::Return(Some(expr)) => match &expr.node {
+                            AstNode::FieldAccess { object, field } => {
+                                assert_eq!(field, "c");
+                                assert!(matches!(&object.node, AstNode::Index { array, .. } if matches!(&array.node, AstNode::FieldAccess { .. })));
+                            },
+                            _ => panic!("Expected field access"),
+                        },
+                        _ => panic!("Expected return statement"),
+                    },
+                    _ => panic!("Expected program node for function body"),
+                },
+                _ => panic!("Expected function node"),
+            },
+            _ => panic!("Expected program node"),
+        }
+    }
+
+    #[test]
+    fn test_parse_method_call() {
+        let mut parser = Parser::new(tokenize("fn f(): i32 { return s.len() + add(1, 2); }").unwrap());
+        match parser.parse().unwrap() {
+            AstNode::Program(nodes) => match &nodes[0].node {
+                AstNode::Function { body, .. } => match &body.node {
+                    AstNode::Program(statements) => match &statements[0].node {
+                        AstNode::Return(Some(expr)) => match &expr.node {
+                            AstNode::BinaryOp { left, right, .. } => {
+                                assert!(matches!(&left.node, AstNode::MethodCall { method, args, .. } if method == "len" && args.is_empty()));
+                                assert!(matches!(&right.node, AstNode::Call { callee, args } if callee == "add" && args.len() == 2));
+                            },
+                            _ => panic!("Expected binary operation"),
+                        },
+                        _ => panic!("Expected return statement"),
+                    },
+                    _ => panic!("Expected program node for function body"),
+                },
+                _ => panic!("Expected function node"),
+            },
+            _ => panic!("Expected program node"),
+        }
+    }
+
+    #[test]
+    fn test_parse_closures() {
+        let source = "fn f(g: fn(i32) -> i32): i32 { let inc = |x: i32| x + 1; let zero = || -> i32 0; return 0; }";
+        let mut parser = Parser::new(tokenize(source).unwrap());
+        match parser.parse().unwrap() {
+            AstNode::Program(nodes) => match &nodes[0].node {
+                AstNode::Function { params, body, .. } => {
+                    assert_eq!(params[0].1, Type::function(vec![Type::I32], Type::I32));
+                    match &body.node {
+                        AstNode::Program(statements) => {
+                            assert!(matches!(&statements[0].node, AstNode::Let { value, .. }
+                                if matches!(&value.node, AstNode::Closure { params, return_type: None, body }
+                                    if params.len() == 1 && matches!(body.node, AstNode::BinaryOp { .. }))));
+                            assert!(matches!(&statements[1].node, AstNode::Let { value, .. }
+                                if matches!(&value.node, AstNode::Closure { params, return_type: Some(Type::I32), .. } if params.is_empty())));
+                        },
+                        _ => panic!("Expected program node for function body"),
+                    }
+                },
+                _ => panic!("Expected function node"),
+            },
+            _ => panic!("Expected program node"),
+        }
+    }
+
+    #[test]
+    fn test_parse_let_mut() {
+        let mut parser = Parser::new(tokenize("fn main(): i32 { let mut x = 0; let y = 1; return x; }").unwrap());
+        match parser.parse().unwrap() {
+            AstNode::Program(nodes) => match &nodes[0].node {
+                AstNode::Function { body, .. } => match &body.node {
+                    AstNode::Program(statements) => {
+                        assert!(matches!(&statements[0].node, AstNode::Let { name, mutable: true, .. } if name == "x"));
+                        assert!(matches!(&statements[1].node, AstNode::Let { name, mutable: false, .. } if name == "y"));
+                    },
+                    _ => panic!("Expected program node for function body"),
+                },
+                _ => panic!("Expected function node"),
+            },
+            _ => panic!("Expected program node"),
+        }
+    }
+
+    #[test]
+    fn test_parse_const() {
+        let mut parser = Parser::new(tokenize("const MAX: i32 = 100; fn main(): i32 { return MAX; }").unwrap());
+        match parser.parse().unwrap() {
+            AstNode::Program(nodes) => {
+                assert!(matches!(&nodes[0].node, AstNode::Const { name, type_annotation: Type::I32, value, .. }
+                    if name == "MAX" && matches!(value.node, AstNode::Number(100))));
+            },
+            _ => panic!("Expected program node"),
+        }
+        assert!(Parser::new(tokenize("const MAX = 100;").unwrap()).parse().is_err());
+    }
+
+    #[test]
+    fn test_parse_let_type_keyword_annotation() {
+        let mut parser = Parser::new(tokenize("fn main(): i32 { let x: i32 = 42; let a: [u8; 2] = [1, 2]; return x; }").unwrap());
+        match parser.parse().unwrap() {
+            AstNode::Program(nodes) => match &nodes[0].node {
+                AstNode::Function { body, .. } => match &body.node {
+                    AstNode::Program(statements) => {
+                        assert!(matches!(&statements[0].node, AstNode::Let { type_annotation: Some(Type::I32), .. }));
+                        assert!(matches!(&statements[1].node, AstNode::Let { type_annotation: Some(t), .. } if *t == Type::array(Type::U8, 2)));
+                    },
+                    _ => panic!("Expected program node for function body"),
+                },
+                _ => panic!("Expected function node"),
+            },
+            _ => panic!("Expected program node"),
+        }
+    }
+
+    #[test]
+    fn test_nodes_carry_spans() {
+        let mut parser = Parser::new(tokenize("fn main(): i32 {\n    return 1 + x;\n}").unwrap());
+        match parser.parse().unwrap() {
+            AstNode::Program(nodes) => match &nodes[0].node {
+                AstNode::Function { body, .. } => match &body.node {
+                    AstNode::Program(statements) => {
+                        assert_eq!(statements[0].span, Span { start: 21, end: 34, line: 2, column: 5 });
+                        match &statements[0].node {
+                            AstNode::Return(Some(expr)) => {
+                                assert_eq!(expr.span, Span { start: 28, end: 33, line: 2, column: 12 });
+                            },
+                            _ => panic!("Expected return statement"),
+                        }
+                    },
+                    _ => panic!("Expected program node for function body"),
+                },
+                _ => panic!("Expected function node"),
+            },
+            _ => panic!("Expected program node"),
+        }
+    }
+
+    #[test]
+    fn test_truncated_input() {
+        let parse = |source: &str| Parser::new(tokenize(source).unwrap()).parse().map(|_| ());
+        assert_eq!(parse("fn main("), Err("unexpected end of input, expected parameter name".to_string()));
+        assert_eq!(parse("fn main()"), Err("unexpected end of input, expected ':' or '->' after parameters".to_string()));
+        assert_eq!(parse("fn main(): i32 { return 1"), Err("unexpected end of input, expected ';' after return statement".to_string()));
+        assert_eq!(parse("fn main(): i32 { let x = [1,"), Err("unexpected end of input, expected expression".to_string()));
+        assert_eq!(parse("/// docs"), Err("unexpected end of input, expected declaration after doc comment".to_string()));
+    }
+
+    #[test]
+    fn test_visitors() {
+        struct Identifiers(Vec<String>);
+        impl Visitor for Identifiers {
+            fn visit_node(&mut self, node: &Spanned<AstNode>) {
+                if let AstNode::Identifier(name) = &node.node {
+                    self.0.push(name.clone());
+                }
+                walk_node(self, node);
+            }
+        }
+
+        struct Rename;
+        impl MutVisitor for Rename {
+            fn visit_node_mut(&mut self, node: &mut Spanned<AstNode>) {
+                if let AstNode::Identifier(name) = &mut node.node {
+                    name.make_ascii_uppercase();
+                }
+                walk_node_mut(self, node);
+            }
+        }
+
+        let source = "fn f(): i32 { let a = [b, c.d]; if e { return g(h); } return i.j(k); }";
+        let mut ast = Parser::new(tokenize(source).unwrap()).parse().unwrap();
+        walk_node_mut(&mut Rename, &mut ast);
+
+        let mut identifiers = Identifiers(Vec::new());
+        walk_node(&mut identifiers, &ast);
+        assert_eq!(identifiers.0, vec!["B", "C", "E", "H", "I", "K"]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_ast_json_round_trip() {
+        let tokens = tokenize("fn main(): i32 { let xs: [i32; 2] = [1, 2]; return xs[0]; }").unwrap();
+        let ast = Parser::new(tokens).parse().unwrap();
+
+        let json = serde_json::to_string(&ast).unwrap();
+        let decoded: AstNode = serde_json::from_str(&json).unwrap();
+        assert_eq!(serde_json::to_string(&decoded).unwrap(), json);
+    }
 }
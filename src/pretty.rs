@@ -0,0 +1,582 @@
+use crate::parser::{AstNode, BinaryOperator, Pattern, Spanned, UnaryOperator};
+use crate::types::Type;
+
+const INDENT: &str = "    ";
+
+/// Renders an AST back into Nova source, one statement per line with
+/// four-space indentation. Spans are ignored, so the output is the
+/// canonical spelling of the tree rather than the text it was parsed from.
+pub fn to_source(node: &AstNode) -> String {
+    let mut printer = Printer::default();
+    match node {
+        AstNode::Program(items) => {
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    printer.out.push('\n');
+                }
+                printer.statement(item, false);
+            }
+        },
+        _ if node.is_expression() => printer.expression(node),
+        _ => printer.statement(node, false),
+    }
+    printer.out
+}
+
+/// Renders parameters as `name: type`, except the `self` of a method, whose
+/// type is implied by the trait or impl around it.
+fn params_list(params: &[(String, Type)]) -> String {
+    let params: Vec<String> = params.iter()
+        .map(|(name, ty)| match name.as_str() {
+            "self" => name.clone(),
+            _ => format!("{}: {}", name, ty),
+        })
+        .collect();
+    params.join(", ")
+}
+
+/// `as` binds tighter than every binary operator, and looser than the unary
+/// and postfix ones.
+const CAST_PRECEDENCE: u8 = 7;
+
+/// Whether `node` must be parenthesized as an operand of an operator with the
+/// given precedence. Conditionals and closures extend as far right as
+/// possible, so they are always grouped.
+fn binds_looser(node: &AstNode, precedence: u8) -> bool {
+    match node {
+        AstNode::BinaryOp { op, .. } => op.precedence() < precedence,
+        AstNode::Cast { .. } => CAST_PRECEDENCE < precedence,
+        AstNode::Conditional { .. } | AstNode::Closure { .. } => true,
+        _ => false,
+    }
+}
+
+#[derive(Default)]
+struct Printer {
+    out: String,
+    depth: usize,
+}
+
+impl Printer {
+    fn line_start(&mut self) {
+        for _ in 0..self.depth {
+            self.out.push_str(INDENT);
+        }
+    }
+
+    /// Prints the `///` lines of a declaration's doc comment, leaving the
+    /// declaration itself to start the next line at the same depth.
+    fn doc(&mut self, doc: &Option<String>) {
+        let Some(doc) = doc else {
+            return;
+        };
+        for line in doc.lines() {
+            self.out.push_str(&format!("/// {}\n", line));
+            self.line_start();
+        }
+    }
+
+    /// Prints `node` on its own line(s). `tail` marks the last statement of a
+    /// block, where an expression is the block's value and takes no `;`.
+    fn statement(&mut self, node: &AstNode, tail: bool) {
+        self.line_start();
+        match node {
+            AstNode::Function { name, type_params, params, return_type, body, doc } => {
+                self.doc(doc);
+                self.out.push_str(&format!("fn {}", name));
+                if !type_params.is_empty() {
+                    let type_params: Vec<String> = type_params.iter()
+                        .map(|(name, bounds)| if bounds.is_empty() {
+                            name.clone()
+                        } else {
+                            format!("{}: {}", name, bounds.join(" + "))
+                        })
+                        .collect();
+                    self.out.push_str(&format!("<{}>", type_params.join(", ")));
+                }
+                self.out.push_str(&format!("({}): {} ", params_list(params), return_type));
+                self.block(body);
+            },
+            AstNode::TraitDef { name, methods, doc } => {
+                self.doc(doc);
+                self.out.push_str(&format!("trait {} {{\n", name));
+                self.depth += 1;
+                for method in methods {
+                    self.line_start();
+                    self.doc(&method.doc);
+                    self.out.push_str(&format!("fn {}({})", method.name, params_list(&method.params)));
+                    if method.return_type != Type::Void {
+                        self.out.push_str(&format!(": {}", method.return_type));
+                    }
+                    self.out.push_str(";\n");
+                }
+                self.depth -= 1;
+                self.line_start();
+                self.out.push('}');
+            },
+            AstNode::Impl { trait_name, target, methods, doc } => {
+                self.doc(doc);
+                self.out.push_str(&format!("impl {} for {} {{\n", trait_name, target));
+                self.depth += 1;
+                for method in methods {
+                    self.statement(method, false);
+                }
+                self.depth -= 1;
+                self.line_start();
+                self.out.push('}');
+            },
+            AstNode::StructDef { name, fields, doc } => {
+                self.doc(doc);
+                self.out.push_str(&format!("struct {} {{\n", name));
+                self.depth += 1;
+                for (field, ty) in fields {
+                    self.line_start();
+                    self.out.push_str(&format!("{}: {},\n", field, ty));
+                }
+                self.depth -= 1;
+                self.line_start();
+                self.out.push('}');
+            },
+            AstNode::EnumDef { name, variants, doc } => {
+                self.doc(doc);
+                self.out.push_str(&format!("enum {} {{\n", name));
+                self.depth += 1;
+                for (variant, payload) in variants {
+                    self.line_start();
+                    self.out.push_str(variant);
+                    if !payload.is_empty() {
+                        let payload: Vec<String> = payload.iter().map(ToString::to_string).collect();
+                        self.out.push_str(&format!("({})", payload.join(", ")));
+                    }
+                    self.out.push_str(",\n");
+                }
+                self.depth -= 1;
+                self.line_start();
+                self.out.push('}');
+            },
+            AstNode::TypeAlias { name, target, doc } => {
+                self.doc(doc);
+                self.out.push_str(&format!("type {} = {};", name, target));
+            },
+            AstNode::Import(path) => self.out.push_str(&format!("import {};", path.join("."))),
+            AstNode::Module(path) => self.out.push_str(&format!("module {};", path.join("."))),
+            AstNode::Let { name, mutable, type_annotation, value } => {
+                self.out.push_str("let ");
+                if *mutable {
+                    self.out.push_str("mut ");
+                }
+                self.out.push_str(name);
+                if let Some(ty) = type_annotation {
+                    self.out.push_str(&format!(": {}", ty));
+                }
+                self.out.push_str(" = ");
+                self.expression(value);
+                self.out.push(';');
+            },
+            AstNode::Const { name, type_annotation, value, doc } => {
+                self.doc(doc);
+                self.out.push_str(&format!("const {}: {} = ", name, type_annotation));
+                self.expression(value);
+                self.out.push(';');
+            },
+            AstNode::Return(Some(value)) => {
+                self.out.push_str("return ");
+                self.expression(value);
+                self.out.push(';');
+            },
+            AstNode::Return(None) => self.out.push_str("return;"),
+            AstNode::Break => self.out.push_str("break;"),
+            AstNode::Continue => self.out.push_str("continue;"),
+            AstNode::Assign { name, value } => {
+                self.out.push_str(&format!("{} = ", name));
+                self.expression(value);
+                self.out.push(';');
+            },
+            AstNode::While { cond, body } => {
+                self.out.push_str("while ");
+                self.expression(cond);
+                self.out.push(' ');
+                self.block(body);
+            },
+            AstNode::If { .. } | AstNode::Match { .. } | AstNode::Program(_) => self.expression(node),
+            AstNode::ExpressionStatement(expr) => {
+                self.expression(expr);
+                self.out.push(';');
+            },
+            _ => {
+                self.expression(node);
+                if !tail {
+                    self.out.push(';');
+                }
+            },
+        }
+        self.out.push('\n');
+    }
+
+    fn block(&mut self, node: &AstNode) {
+        let AstNode::Program(statements) = node else {
+            return self.expression(node);
+        };
+        if statements.is_empty() {
+            self.out.push_str("{}");
+            return;
+        }
+        self.out.push_str("{\n");
+        self.depth += 1;
+        for (i, statement) in statements.iter().enumerate() {
+            self.statement(statement, i + 1 == statements.len());
+        }
+        self.depth -= 1;
+        self.line_start();
+        self.out.push('}');
+    }
+
+    fn pattern(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Wildcard => self.out.push('_'),
+            Pattern::Literal(literal) => self.expression(literal),
+            Pattern::Variant { enum_name, variant, bindings } => {
+                self.out.push_str(&format!("{}::{}", enum_name, variant));
+                if !bindings.is_empty() {
+                    self.out.push_str(&format!("({})", bindings.join(", ")));
+                }
+            },
+        }
+    }
+
+    fn expression(&mut self, node: &AstNode) {
+        match node {
+            AstNode::Number(n) => self.out.push_str(&n.to_string()),
+            AstNode::Float(n) => self.out.push_str(&format!("{:?}", n)),
+            AstNode::Char(c) => self.out.push_str(&format!("{:?}", c)),
+            AstNode::StringLiteral(s) => self.out.push_str(&format!("\"{}\"", s.escape_debug())),
+            AstNode::FormatString { parts } => {
+                self.out.push_str("f\"");
+                for part in parts {
+                    match &part.node {
+                        AstNode::StringLiteral(text) => {
+                            let text = text.escape_debug().to_string();
+                            self.out.push_str(&text.replace('{', "{{").replace('}', "}}"));
+                        },
+                        expr => {
+                            self.out.push('{');
+                            self.expression(expr);
+                            self.out.push('}');
+                        },
+                    }
+                }
+                self.out.push('"');
+            },
+            AstNode::Boolean(b) => self.out.push_str(&b.to_string()),
+            AstNode::Identifier(name) => self.out.push_str(name),
+            AstNode::BinaryOp { op, left, right } => {
+                // the operand on the side the operator does not fold towards
+                // needs grouping already at equal precedence
+                let (left_min, right_min) = if op.is_right_associative() {
+                    (op.precedence() + 1, op.precedence())
+                } else {
+                    (op.precedence(), op.precedence() + 1)
+                };
+                // a prefix operator on the base of a power applies to the
+                // whole power unless grouped
+                let prefixed = match &left.node {
+                    AstNode::UnaryOp { .. } => true,
+                    AstNode::Number(n) => *n < 0,
+                    AstNode::Float(n) => n.is_sign_negative(),
+                    _ => false,
+                };
+                self.operand(left, binds_looser(left, left_min) || (*op == BinaryOperator::Power && prefixed));
+                self.out.push_str(match op {
+                    BinaryOperator::Add => " + ",
+                    BinaryOperator::Subtract => " - ",
+                    BinaryOperator::Multiply => " * ",
+                    BinaryOperator::Divide => " / ",
+                    BinaryOperator::Eq => " == ",
+                    BinaryOperator::Ne => " != ",
+                    BinaryOperator::Lt => " < ",
+                    BinaryOperator::Le => " <= ",
+                    BinaryOperator::Gt => " > ",
+                    BinaryOperator::Ge => " >= ",
+                    BinaryOperator::And => " && ",
+                    BinaryOperator::Or => " || ",
+                    BinaryOperator::Power => " ** ",
+                });
+                self.operand(right, binds_looser(right, right_min));
+            },
+            AstNode::UnaryOp { op, operand } => {
+                self.out.push(match op {
+                    UnaryOperator::Negate => '-',
+                    UnaryOperator::Not => '!',
+                });
+                self.operand(operand, binds_looser(operand, u8::MAX));
+            },
+            AstNode::Cast { value, target } => {
+                self.operand(value, binds_looser(value, CAST_PRECEDENCE));
+                self.out.push_str(&format!(" as {}", target));
+            },
+            AstNode::ArrayLiteral(elements) => {
+                self.out.push('[');
+                self.list(elements);
+                self.out.push(']');
+            },
+            AstNode::Index { array, index } => {
+                self.postfix_receiver(array);
+                self.out.push('[');
+                self.expression(index);
+                self.out.push(']');
+            },
+            AstNode::StructLiteral { name, fields } => {
+                self.out.push_str(name);
+                if fields.is_empty() {
+                    self.out.push_str(" {}");
+                    return;
+                }
+                self.out.push_str(" { ");
+                for (i, (field, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        self.out.push_str(", ");
+                    }
+                    self.out.push_str(&format!("{}: ", field));
+                    self.expression(value);
+                }
+                self.out.push_str(" }");
+            },
+            AstNode::FieldAccess { object, field } => {
+                self.postfix_receiver(object);
+                self.out.push_str(&format!(".{}", field));
+            },
+            AstNode::EnumVariant { enum_name, variant, args } => {
+                self.out.push_str(&format!("{}::{}", enum_name, variant));
+                if !args.is_empty() {
+                    self.out.push('(');
+                    self.list(args);
+                    self.out.push(')');
+                }
+            },
+            AstNode::Call { callee, args } => {
+                self.out.push_str(&format!("{}(", callee));
+                self.list(args);
+                self.out.push(')');
+            },
+            AstNode::MethodCall { receiver, method, args } => {
+                self.postfix_receiver(receiver);
+                self.out.push_str(&format!(".{}(", method));
+                self.list(args);
+                self.out.push(')');
+            },
+            AstNode::Closure { params, return_type, body } => {
+                let params: Vec<String> = params.iter().map(|(name, ty)| format!("{}: {}", name, ty)).collect();
+                self.out.push_str(&format!("|{}| ", params.join(", ")));
+                if let Some(ty) = return_type {
+                    self.out.push_str(&format!("-> {} ", ty));
+                }
+                self.expression(body);
+            },
+            AstNode::If { cond, then_block, else_block } => {
+                self.out.push_str("if ");
+                self.expression(cond);
+                self.out.push(' ');
+                self.block(then_block);
+                if let Some(else_block) = else_block {
+                    self.out.push_str(" else ");
+                    match &else_block.node {
+                        AstNode::If { .. } => self.expression(else_block),
+                        _ => self.block(else_block),
+                    }
+                }
+            },
+            AstNode::Conditional { cond, then_value, else_value } => {
+                self.out.push_str("if ");
+                self.expression(cond);
+                self.out.push_str(" then ");
+                self.expression(then_value);
+                self.out.push_str(" else ");
+                self.expression(else_value);
+            },
+            AstNode::Match { scrutinee, arms } => {
+                self.out.push_str("match ");
+                self.expression(scrutinee);
+                self.out.push_str(" {\n");
+                self.depth += 1;
+                for (pattern, body) in arms {
+                    self.line_start();
+                    self.pattern(pattern);
+                    self.out.push_str(" => ");
+                    self.block(body);
+                    self.out.push_str(",\n");
+                }
+                self.depth -= 1;
+                self.line_start();
+                self.out.push('}');
+            },
+            AstNode::Program(_) => self.block(node),
+            // statements never appear in expression position in a parsed
+            // tree; print them verbatim so hand-built trees still render
+            _ => {
+                let mut nested = Printer::default();
+                nested.statement(node, false);
+                self.out.push_str(nested.out.trim_end());
+            },
+        }
+    }
+
+    fn operand(&mut self, node: &AstNode, grouped: bool) {
+        if grouped {
+            self.out.push('(');
+            self.expression(node);
+            self.out.push(')');
+        } else {
+            self.expression(node);
+        }
+    }
+
+    fn postfix_receiver(&mut self, node: &AstNode) {
+        let grouped = binds_looser(node, u8::MAX) || matches!(node, AstNode::UnaryOp { .. });
+        self.operand(node, grouped);
+    }
+
+    fn list(&mut self, nodes: &[Spanned<AstNode>]) {
+        for (i, node) in nodes.iter().enumerate() {
+            if i > 0 {
+                self.out.push_str(", ");
+            }
+            self.expression(node);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::tokenize;
+    use crate::parser::Parser;
+
+    fn parse(source: &str) -> AstNode {
+        Parser::new(tokenize(source).unwrap()).parse().unwrap()
+    }
+
+    #[test]
+    fn test_to_source() {
+        let source = "\
+module demo.shapes;
+
+import std.io;
+
+/// Adds one.
+fn inc(x: i32): i32 {
+    x + 1
+}
+
+fn pick<T, U>(a: T, b: U, f: fn(T) -> U): [T; 2] {
+    let c: T = a;
+    [a, c]
+}
+
+/// A point on the grid.
+struct Point {
+    x: i32,
+    y: i32,
+}
+
+/// What a shape is filled with.
+enum Color {
+    Red,
+    Green,
+    Mix(Color, f64),
+}
+
+/// Four colors.
+/// In order.
+type Palette = [Color; 4];
+
+trait Show {
+    /// Describes the value.
+    fn show(self): string;
+    fn same(self, other: Self): bool;
+    fn ping(self);
+}
+
+/// Points by their name.
+impl Show for Point {
+    /// Always `point`.
+    fn show(self): string {
+        \"point\"
+    }
+    fn same(self, other: Point): bool {
+        self.x == other.x
+    }
+    fn ping(self): void {}
+}
+
+fn describe<T: Show + Eq, U>(x: T, _y: U): string {
+    x.show()
+}
+
+fn main(): i32 {
+    let mut total: i32 = 0;
+    let p = Point { x: 1, y: -2 };
+    let xs: [i32; 3] = [1, 2, 3];
+    while total - 10 {
+        total = total + xs[0];
+        if p.x {
+            break;
+        } else if p.y {
+            continue;
+        } else {
+            total.abs();
+        }
+    }
+    if total {
+        return;
+    }
+    let f = |a: i32| -> i32 a * 2;
+    let c = Color::Red;
+    let d = Color::Mix(c, 0.5);
+    let m = if total < 3 then 1 else 2;
+    let n = (if m == 1 then 2 else 3) * 2 + 1;
+    match d {
+        Color::Mix(_, w) => {
+            total = total + 1;
+        },
+        _ => total.abs(),
+    }
+    let k = match m {
+        1 => true,
+        _ => false,
+    };
+    let o: Option<Option<i32>> = Option::Some(Option::None);
+    let r = (m + 1) as f64 * -(n as f32) as f64 as i64;
+    return inc(total);
+}
+";
+        assert_eq!(to_source(&parse(source)), source);
+    }
+
+    #[test]
+    fn test_compound_assignment_groups_right_operand() {
+        let printed = to_source(&parse("fn f(): i32 { x -= 1 + 2; }"));
+        assert!(printed.contains("x = x - (1 + 2);"), "{}", printed);
+    }
+
+    #[test]
+    fn test_parentheses_follow_precedence() {
+        let source = "let x = (a + b) * c == d - (e - f) || !(g && h) || (a ** b) ** c ** d;\n";
+        assert_eq!(to_source(&parse(source)), source);
+        let source = "let x = (-a) ** b + -(a ** b) + (-2) ** 2 + a ** -b;\n";
+        assert_eq!(to_source(&parse(source)), source);
+        assert_eq!(to_source(&parse("let x = -a ** b;")), "let x = -(a ** b);\n");
+        let printed = to_source(&parse("let x = ((a * b)) + (c);"));
+        assert_eq!(printed, "let x = a * b + c;\n");
+    }
+
+    #[test]
+    fn test_literals_are_escaped() {
+        let printed = to_source(&parse("let s = \"a\\n\\\"b\\\"\";"));
+        assert_eq!(printed, "let s = \"a\\n\\\"b\\\"\";\n");
+        assert_eq!(to_source(&AstNode::Char('\'')), "'\\''");
+        assert_eq!(to_source(&AstNode::Float(2.0)), "2.0");
+
+        let source = "let s = f\"{{{a}}} = {b + 1}\\n\";\n";
+        assert_eq!(to_source(&parse(source)), source);
+    }
+}
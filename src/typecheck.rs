@@ -1,81 +1,2874 @@
-use crate::parser::AstNode;
-use crate::types::{Type, TypeEnvironment};
+use crate::consteval::{self, ConstValue};
+use crate::lexer::Span;
+use crate::parser::{AstNode, BinaryOperator, Pattern, Spanned, UnaryOperator};
+use crate::types::{BindingKind, Type, TypeEnvironment};
+
+/// Functions every program can call without declaring them. A function
+/// the program declares under one of these names takes their place.
+#[allow(dead_code)]
+pub const BUILTINS: &[&str] = &["print", "println"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// Stable names for the kinds of problem the checker reports, so that
+/// tools can filter diagnostics without matching on their wording.
+pub mod code {
+    pub const TYPE_MISMATCH: &str = "type-mismatch";
+    pub const UNKNOWN_NAME: &str = "unknown-name";
+    pub const UNKNOWN_MEMBER: &str = "unknown-member";
+    pub const DUPLICATE_DEFINITION: &str = "duplicate-definition";
+    pub const INVALID_OPERAND: &str = "invalid-operand";
+    pub const WRONG_ARITY: &str = "wrong-arity";
+    pub const MISSING_ITEM: &str = "missing-item";
+    pub const CANNOT_INFER: &str = "cannot-infer";
+    pub const NOT_CONSTANT: &str = "not-constant";
+    pub const CONSTANT_EVALUATION: &str = "constant-evaluation";
+    pub const LITERAL_OUT_OF_RANGE: &str = "literal-out-of-range";
+    pub const OUT_OF_BOUNDS: &str = "out-of-bounds";
+    pub const IMMUTABLE_ASSIGNMENT: &str = "immutable-assignment";
+    pub const NON_EXHAUSTIVE_MATCH: &str = "non-exhaustive-match";
+    pub const INVALID_PATTERN: &str = "invalid-pattern";
+    pub const UNSATISFIED_BOUND: &str = "unsatisfied-bound";
+    pub const OUTSIDE_LOOP: &str = "outside-loop";
+    pub const ENTRY_POINT: &str = "entry-point";
+    pub const UNUSED: &str = "unused";
+    pub const UNUSED_RESULT: &str = "unused-result";
+    pub const UNREACHABLE_PATTERN: &str = "unreachable-pattern";
+    pub const MISSING_RETURN: &str = "missing-return";
+    pub const CAPTURE: &str = "capture";
+}
+
+/// A problem found while checking. The first error stops the checker and
+/// is returned from `check`; warnings are collected instead, and become
+/// errors when the checker was told to deny them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// Nodes built outside the parser have the default span, and no
+    /// position to report.
+    pub span: Span,
+    /// One of the names in `code`.
+    pub code: &'static str,
+    pub message: String,
+    /// Lines explaining the problem, each starting with `note:` or `help:`.
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn error(code: &'static str, message: impl Into<String>) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            span: Span::default(),
+            code,
+            message: message.into(),
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn warning(code: &'static str, message: impl Into<String>) -> Self {
+        Diagnostic { severity: Severity::Warning, ..Diagnostic::error(code, message) }
+    }
+
+    /// Points a diagnostic that does not name a position yet at `span`.
+    fn at(mut self, span: Span) -> Self {
+        if self.span == Span::default() {
+            self.span = span;
+        }
+        self
+    }
+
+    fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    /// The message with its position and notes, as printed after the
+    /// severity.
+    pub fn summary(&self) -> String {
+        let mut summary = self.message.clone();
+        if self.span != Span::default() {
+            summary += &format!(" at {}", self.span);
+        }
+        for note in &self.notes {
+            summary += &format!("\n{}", note);
+        }
+        summary
+    }
+
+    /// Renders the diagnostic against the source it was found in, quoting
+    /// the line it points at and underlining the span on that line.
+    pub fn render(&self, source: &str) -> String {
+        if self.span == Span::default() {
+            return self.to_string();
+        }
+        let Some(line) = source.lines().nth(self.span.line.wrapping_sub(1)) else {
+            return self.to_string();
+        };
+        let number = self.span.line.to_string();
+        let gutter = " ".repeat(number.len());
+        let indent: String = line.chars()
+            .take(self.span.column.saturating_sub(1))
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let width = source.get(self.span.start..self.span.end)
+            .and_then(|text| text.lines().next())
+            .map_or(0, |text| text.chars().count())
+            .max(1);
+        let mut rendered = format!(
+            "{}[{}]: {}\n{}--> line {}, column {}\n{} |\n{} | {}\n{} | {}{}",
+            self.severity, self.code, self.message, gutter, self.span.line, self.span.column,
+            gutter, number, line, gutter, indent, "^".repeat(width)
+        );
+        for note in &self.notes {
+            rendered += &format!("\n{} = {}", gutter, note);
+        }
+        rendered
+    }
+}
+
+impl From<consteval::ConstError> for Diagnostic {
+    fn from(error: consteval::ConstError) -> Self {
+        Diagnostic::error(code::CONSTANT_EVALUATION, error.message).at(error.span)
+    }
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.severity, self.summary())
+    }
+}
+
+/// A generic function applied to concrete type arguments, given in the
+/// order of the function's type parameters, which codegen has to emit a
+/// copy of.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instantiation {
+    pub function: String,
+    pub type_args: Vec<Type>,
+}
+
+impl std::fmt::Display for Instantiation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let type_args: Vec<String> = self.type_args.iter().map(Type::to_string).collect();
+        write!(f, "{}::<{}>", self.function, type_args.join(", "))
+    }
+}
 
 pub struct TypeChecker {
     env: TypeEnvironment,
+    loop_depth: usize,
+    /// The folded value of every constant declared so far.
+    constants: std::collections::HashMap<String, ConstValue>,
+    /// Problems that do not stop checking, in the order they were found.
+    diagnostics: Vec<Diagnostic>,
+    deny_warnings: bool,
+    /// Span of the node being checked.
+    span: Span,
+    /// Name and declared return type of the function being checked, as
+    /// written, so that errors can name the alias it was spelled as.
+    function: Option<(String, Type)>,
+    /// The type parameters of every generic function with their trait
+    /// bounds, in declaration order.
+    generics: std::collections::HashMap<String, Vec<(String, Vec<String>)>>,
+    /// The type parameters of the function being checked with their bounds,
+    /// which say what methods a value of a parameter type has.
+    bounds: Vec<(String, Vec<String>)>,
+    /// Every instantiation of a generic function, in the order first called.
+    instantiations: Vec<Instantiation>,
+    /// The type `check_expected` wants of the node about to be checked;
+    /// taken by that node, so it never leaks into the node's children.
+    expected: Option<Type>,
+    /// Scope depth outside the function being checked: bindings in deeper
+    /// scopes are its parameters and locals.
+    function_scope: usize,
+    /// Scope depth outside the innermost closure being checked, whose body
+    /// may not use the locals of the function around it.
+    closure_scope: Option<usize>,
+}
+
+impl Default for TypeChecker {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl TypeChecker {
     pub fn new() -> Self {
         Self {
             env: TypeEnvironment::new(),
+            loop_depth: 0,
+            constants: std::collections::HashMap::new(),
+            diagnostics: Vec::new(),
+            deny_warnings: false,
+            span: Span::default(),
+            function: None,
+            generics: std::collections::HashMap::new(),
+            bounds: Vec::new(),
+            instantiations: Vec::new(),
+            expected: None,
+            function_scope: usize::MAX,
+            closure_scope: None,
+        }
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// The generic functions called with concrete types, for codegen to
+    /// monomorphize. Calls from inside a generic body are not listed, as
+    /// their types are only known once that body is instantiated.
+    #[allow(dead_code)]
+    pub fn instantiations(&self) -> &[Instantiation] {
+        &self.instantiations
+    }
+
+    /// Lowers a program this checker has checked to the HIR.
+    #[allow(dead_code)]
+    pub fn lower(&self, program: &AstNode) -> Result<crate::hir::Program, String> {
+        crate::hir::lower(program, &self.env)
+    }
+
+    /// Reports every later warning as an error.
+    pub fn deny_warnings(&mut self) {
+        self.deny_warnings = true;
+    }
+
+    /// Whether a diagnostic was an error, so that compilation must stop.
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics.iter().any(|diagnostic| diagnostic.severity == Severity::Error)
+    }
+
+    /// Checks that a checked program has an entry point the runtime can
+    /// call: a non-generic `main` that takes no parameters and returns `i32`
+    /// or `void`.
+    pub fn check_entry_point(&self, program: &AstNode) -> Result<(), Diagnostic> {
+        let AstNode::Program(items) = program else {
+            return Err(Diagnostic::error(code::ENTRY_POINT, "Expected a program"));
+        };
+        let Some(main) = items.iter().find(|item| matches!(&item.node, AstNode::Function { name, .. } if name == "main")) else {
+            return Err(Diagnostic::error(code::ENTRY_POINT, "The program has no main function"));
+        };
+        let AstNode::Function { type_params, params, return_type, .. } = &main.node else {
+            unreachable!("main was found as a function");
+        };
+        let located = |message: String| Diagnostic::error(code::ENTRY_POINT, message).at(main.span);
+
+        if !type_params.is_empty() {
+            return Err(located("The main function cannot be generic".to_string()));
+        }
+        if !params.is_empty() {
+            let found: Vec<String> = params.iter().map(|(_, t)| self.env.resolve(t).to_string()).collect();
+            return Err(located(format!("The main function must take no parameters, found ({})", found.join(", "))));
+        }
+        let return_type = self.env.resolve(return_type);
+        if return_type != Type::I32 && return_type != Type::Void {
+            return Err(located(format!("The main function must return i32 or void, found {}", return_type)));
         }
+        Ok(())
     }
 
-    pub fn check(&mut self, node: &AstNode) -> Result<Type, String> {
+    pub fn check(&mut self, node: &AstNode) -> Result<Type, Diagnostic> {
+        let expected = self.expected.take();
         match node {
             AstNode::Program(nodes) => {
-                let mut last_type = Type::Void;
-                for node in nodes {
-                    last_type = self.check(node)?;
+                // a block is only as valuable as its trailing expression
+                self.env.push_scope();
+                let last_type = self.declare_items(nodes).and_then(|_| {
+                    nodes.iter().enumerate().try_fold(Type::Void, |_, (i, node)| {
+                        if Self::is_type_definition(node) {
+                            return Ok(Type::Void);
+                        }
+                        let tail = i + 1 == nodes.len() && node.is_expression();
+                        let node_type = self.check_hinted(node, expected.as_ref().filter(|_| tail))?;
+                        Ok(if node.is_expression() { node_type } else { Type::Void })
+                    })
+                });
+                self.pop_scope();
+                last_type
+            },
+            AstNode::Function { name, type_params, params, return_type, body, .. } => {
+                if let Some(bound) = type_params.iter()
+                    .flat_map(|(_, bounds)| bounds)
+                    .find(|bound| self.env.get_trait(bound).is_none())
+                {
+                    return Err(Diagnostic::error(code::UNKNOWN_NAME, format!("Unknown trait: {}", bound)));
+                }
+                self.generics.insert(name.clone(), type_params.clone());
+                let signature = self.signature(params, return_type);
+                // declared up front so that the body can call itself, unless
+                // `declare_items` already did so for the enclosing block
+                if self.env.declaration_span(name) != Some(self.span) || self.env.get(name) != Some(&signature) {
+                    self.declare_function(name, signature, self.span);
+                }
+
+                let outer = std::mem::replace(&mut self.bounds, type_params.clone());
+                let body_type = self.check_function(name, params, return_type, body);
+                self.bounds = outer;
+                body_type
+            },
+            AstNode::TraitDef { name, methods, .. } => {
+                if self.env.get_trait(name).is_some() {
+                    return Err(Diagnostic::error(
+                        code::DUPLICATE_DEFINITION,
+                        format!("Trait {} is already defined", name),
+                    ));
+                }
+                let mut signatures: Vec<(String, Type)> = Vec::new();
+                for method in methods {
+                    if signatures.iter().any(|(other, _)| *other == method.name) {
+                        return Err(Diagnostic::error(
+                            code::DUPLICATE_DEFINITION,
+                            format!("Duplicate method '{}' in trait {}", method.name, name),
+                        ));
+                    }
+                    let signature = self.signature(&method.params, &method.return_type);
+                    if let Some(unknown) = self.unknown_type_name(&signature) {
+                        return Err(Diagnostic::error(code::UNKNOWN_NAME, format!("Unknown type: {}", unknown)));
+                    }
+                    signatures.push((method.name.clone(), signature));
                 }
-                Ok(last_type)
+                self.env.define_trait(name.clone(), signatures);
+                Ok(Type::Void)
             },
-            AstNode::Function { name, params: _, body } => {
-                let body_type = self.check(body)?;
-                self.env.insert(name.clone(), Type::function(vec![], body_type.clone()));
-                Ok(body_type)
+            AstNode::Impl { trait_name, target, methods, .. } => {
+                // `declare_items` declares the impls of a block up front
+                if !self.env.implements(&self.env.resolve(target), trait_name) {
+                    self.declare_impl(trait_name, target, methods)?;
+                }
+                for method in methods {
+                    let AstNode::Function { name, params, return_type, body, .. } = &method.node else {
+                        continue;
+                    };
+                    let outer = std::mem::replace(&mut self.span, method.span);
+                    let result = self.check_function(name, params, return_type, body);
+                    self.span = outer;
+                    result.map_err(|error| error.at(method.span))?;
+                }
+                Ok(Type::Void)
+            },
+            AstNode::Const { name, type_annotation, value, .. } => {
+                if !self.is_constant(value) {
+                    return Err(Diagnostic::error(
+                        code::NOT_CONSTANT,
+                        format!("The value of constant {} must be a constant expression", name),
+                    ));
+                }
+                let declared = self.env.resolve(type_annotation);
+                let value_type = self.check_expected(value, &declared)?;
+                if value_type != declared {
+                    return Err(Diagnostic::error(
+                        code::TYPE_MISMATCH,
+                        format!("Type mismatch: expected {}, got {}", self.describe(type_annotation), value_type),
+                    ));
+                }
+                let folded = consteval::evaluate(value, &self.constants)?;
+                self.env.declare(name.clone(), declared, false, self.span);
+                self.constants.insert(name.clone(), folded);
+                Ok(Type::Void)
             },
-            AstNode::Number(_) => Ok(Type::Int),
+            // without an expected type a literal is an i32
+            AstNode::Number(n) => {
+                Self::check_literal_range(*n as i128, &Type::I32)?;
+                Ok(Type::I32)
+            },
+            AstNode::Float(_) => Ok(Type::F64),
+            AstNode::Char(_) => Ok(Type::Char),
             AstNode::StringLiteral(_) => Ok(Type::String),
+            AstNode::FormatString { parts } => {
+                for part in parts {
+                    let part_type = self.check_node(part)?;
+                    let printable = part_type.is_numeric()
+                        || matches!(part_type, Type::Bool | Type::Char | Type::String | Type::Enum(_));
+                    if !printable {
+                        return Err(Diagnostic::error(
+                            code::INVALID_OPERAND,
+                            format!("Cannot interpolate a value of type {} into a format string", part_type),
+                        ));
+                    }
+                }
+                Ok(Type::String)
+            },
             AstNode::Boolean(_) => Ok(Type::Bool),
-            AstNode::Let { name, type_annotation, value } => {
-                let value_type = self.check(value)?;
-                
-                // Convert type annotation if present
-                if let Some(type_name) = type_annotation {
-                    let expected_type = match type_name.as_str() {
-                        "int" => Type::Int,
-                        "float" => Type::Float,
-                        "string" => Type::String,
-                        "bool" => Type::Bool,
-                        _ => return Err(format!("Unknown type: {}", type_name)),
-                    };
+            AstNode::Identifier(name) => {
+                self.env.mark_used(name);
+                self.check_capture(name)?;
+                self.env.get(name)
+                    .cloned()
+                    .ok_or_else(|| Diagnostic::error(
+                        code::UNKNOWN_NAME,
+                        format!("Undefined variable: {}{}", name, self.did_you_mean(name)),
+                    ))
+            },
+            AstNode::Let { name, mutable, type_annotation, value } => {
+                let expected_type = type_annotation.as_ref().map(|annotation| self.env.resolve(annotation));
+                let value_type = match &expected_type {
+                    Some(expected_type) => self.check_expected(value, expected_type)?,
+                    None => self.check_node(value)?,
+                };
+
+                if let (Some(annotation), Some(expected_type)) = (type_annotation, expected_type) {
                     if value_type != expected_type {
-                        return Err(format!("Type mismatch: expected {:?}, got {:?}", expected_type, value_type));
+                        return Err(Diagnostic::error(
+                            code::TYPE_MISMATCH,
+                            format!("Type mismatch: expected {}, got {}", self.describe(annotation), value_type),
+                        ));
                     }
                 }
                 
-                self.env.insert(name.clone(), value_type.clone());
+                self.env.declare(name.clone(), value_type.clone(), *mutable, self.span);
+                self.track_usage(name, BindingKind::Variable, self.span);
+                Ok(value_type)
+            }
+            AstNode::UnaryOp { op, operand } => {
+                // `-2147483648` is an i32, though `2147483648` is not
+                if let Some(value) = Self::integer_literal(node) {
+                    Self::check_literal_range(value, &Type::I32)?;
+                    operand.set_ty(Type::I32);
+                    return Ok(Type::I32);
+                }
+                let operand_type = self.check_node(operand)?;
+                match op {
+                    UnaryOperator::Negate if operand_type.is_unsigned() => Err(Diagnostic::error(
+                        code::INVALID_OPERAND,
+                        format!("Cannot negate a value of type {}, which is unsigned", operand_type),
+                    )),
+                    UnaryOperator::Negate if operand_type.is_numeric() => Ok(operand_type),
+                    UnaryOperator::Negate => Err(Diagnostic::error(
+                        code::INVALID_OPERAND,
+                        format!("Cannot negate a value of type {}", operand_type),
+                    )),
+                    UnaryOperator::Not if operand_type == Type::Bool => Ok(Type::Bool),
+                    UnaryOperator::Not => Err(Diagnostic::error(
+                        code::INVALID_OPERAND,
+                        format!("Logical not requires bool, got {}", operand_type),
+                    )),
+                }
+            },
+            AstNode::Cast { value, target } => {
+                // numbers convert to any other numeric type, widening,
+                // narrowing or between integers and floats
+                let target = self.env.resolve(target);
+                let value_type = self.check_node(value)?;
+                if value_type != target && !(value_type.is_numeric() && target.is_numeric()) {
+                    return Err(Diagnostic::error(
+                        code::INVALID_OPERAND,
+                        format!("Cannot cast a value of type {} to {}", value_type, target),
+                    ));
+                }
+                Ok(target)
+            },
+            AstNode::BinaryOp { op, left, right } if op.is_comparison() || op.is_logical() => {
+                let (left_type, right_type) = self.check_operands(left, right)?;
+                if op.is_logical() && (left_type != Type::Bool || right_type != Type::Bool) {
+                    return Err(Diagnostic::error(code::INVALID_OPERAND, format!(
+                        "Logical operator {:?} requires bool operands, got {} and {}",
+                        op, left_type, right_type
+                    )));
+                }
+                if left_type != right_type {
+                    let error = Diagnostic::error(
+                        code::INVALID_OPERAND,
+                        format!("Cannot compare {} with {}", left_type, right_type),
+                    );
+                    return Err(Self::hint_string_conversion(error, &left_type, &right_type));
+                }
+                // strings are equal or not, but have no order
+                if left_type == Type::String && !matches!(op, BinaryOperator::Eq | BinaryOperator::Ne) {
+                    return Err(Diagnostic::error(
+                        code::INVALID_OPERAND,
+                        format!("Strings can only be compared with Eq and Ne, not {:?}", op),
+                    ));
+                }
+                Ok(Type::Bool)
+            },
+            AstNode::BinaryOp { op: BinaryOperator::Power, left, right } => {
+                let is_literal = |node: &AstNode| Self::integer_literal(node).is_some() || Self::is_float_literal(node);
+                let literal_base = is_literal(left);
+                let exponent = if literal_base && !is_literal(right) { Some(self.check_node(right)?) } else { None };
+                // a literal base takes the type of a float exponent, or else
+                // the one expected of the power
+                let context = match &exponent {
+                    Some(ty) if ty.is_float() => Some(ty.clone()),
+                    _ if literal_base => expected.clone().filter(Type::is_numeric),
+                    _ => None,
+                };
+                let base = self.check_hinted(left, context.as_ref())?;
+                // a float is raised to a float of its own width or to an
+                // integer
+                let exponent = match exponent {
+                    Some(ty) => ty,
+                    None if base.is_float() => self.check_expected(right, &base)?,
+                    None => self.check_node(right)?,
+                };
+                match (&base, &exponent) {
+                    (base, exponent) if base.is_integer() && exponent.is_integer() => Ok(base.clone()),
+                    (base, exponent) if base.is_float() && (exponent.is_integer() || exponent == base) => {
+                        Ok(base.clone())
+                    },
+                    _ => Err(Diagnostic::error(
+                        code::INVALID_OPERAND,
+                        format!("Cannot raise {} to the power of {}", base, exponent),
+                    )),
+                }
+            },
+            AstNode::BinaryOp { op, left, right } => {
+                let (left_type, right_type) = self.check_operands(left, right)?;
+                if *op == BinaryOperator::Add && left_type == Type::String && right_type == Type::String {
+                    return Ok(Type::String);
+                }
+                if !left_type.is_numeric() || !right_type.is_numeric() {
+                    let error = Diagnostic::error(code::INVALID_OPERAND, format!(
+                        "Arithmetic operator {:?} requires numeric operands, got {} and {}",
+                        op, left_type, right_type
+                    ));
+                    return Err(Self::hint_string_conversion(error, &left_type, &right_type));
+                }
+                // no implicit conversions, not even from integers to floats
+                if left_type != right_type {
+                    return Err(Diagnostic::error(code::INVALID_OPERAND, format!(
+                        "Arithmetic operator {:?} requires operands of the same type, got {} and {}",
+                        op, left_type, right_type
+                    )));
+                }
+                Ok(left_type)
+            },
+            AstNode::Return(value) => {
+                let function = self.function.clone();
+                let return_type = function.as_ref().map(|(_, written)| self.env.resolve(written));
+                let value_type = match (value, &return_type) {
+                    (Some(expr), Some(return_type)) => self.check_expected(expr, return_type)?,
+                    (Some(expr), None) => self.check_node(expr)?,
+                    (None, _) => Type::Void,
+                };
+                if let (Some((name, written)), Some(return_type)) = (&function, return_type) {
+                    if value_type != return_type {
+                        return Err(self.return_mismatch(name, written, &value_type));
+                    }
+                }
                 Ok(value_type)
+            },
+            AstNode::If { cond, then_block, else_block } => {
+                self.check_condition(cond)?;
+                let then_type = self.check_hinted(then_block, expected.as_ref())?;
+                match else_block {
+                    Some(else_block) => {
+                        let else_type = self.check_expected(else_block, &then_type)?;
+                        if then_type != else_type {
+                            return Err(Diagnostic::error(code::TYPE_MISMATCH, format!(
+                                "If branches have mismatched types: {} and {}",
+                                then_type, else_type
+                            )));
+                        }
+                        Ok(then_type)
+                    },
+                    None => Ok(Type::Void),
+                }
+            },
+            AstNode::Conditional { cond, then_value, else_value } => {
+                self.check_condition(cond)?;
+                let then_type = self.check_hinted(then_value, expected.as_ref())?;
+                let else_type = self.check_expected(else_value, &then_type)?;
+                if then_type != else_type {
+                    return Err(Diagnostic::error(code::TYPE_MISMATCH, format!(
+                        "Conditional branches have mismatched types: {} and {}",
+                        then_type, else_type
+                    )));
+                }
+                Ok(then_type)
+            },
+            AstNode::Match { scrutinee, arms } => self.check_match(scrutinee, arms, expected),
+            AstNode::While { cond, body } => {
+                self.check_condition(cond)?;
+                self.loop_depth += 1;
+                let result = self.check_node(body);
+                self.loop_depth -= 1;
+                result?;
+                Ok(Type::Void)
+            },
+            AstNode::Break | AstNode::Continue => {
+                if self.loop_depth == 0 {
+                    let keyword = if matches!(node, AstNode::Break) { "break" } else { "continue" };
+                    return Err(Diagnostic::error(code::OUTSIDE_LOOP, format!("'{}' outside of a loop", keyword)));
+                }
+                Ok(Type::Void)
+            },
+            AstNode::ArrayLiteral(elements) => {
+                // the elements are checked against the element type expected
+                // of the array, or else against the first of them
+                let mut element_type = match expected.map(|expected| self.env.resolve(&expected)) {
+                    Some(Type::Array(element, _)) => Some(*element),
+                    _ => None,
+                };
+                for element in elements {
+                    let t = match &element_type {
+                        Some(expected) => self.check_expected(element, expected)?,
+                        None => self.check_node(element)?,
+                    };
+                    match &element_type {
+                        Some(expected) if *expected != t => {
+                            return Err(Diagnostic::error(
+                                code::TYPE_MISMATCH,
+                                format!("Array elements must share one type: expected {}, got {}", expected, t),
+                            ));
+                        },
+                        Some(_) => {},
+                        None => element_type = Some(t),
+                    }
+                }
+                let element_type = element_type.ok_or_else(|| Diagnostic::error(
+                    code::CANNOT_INFER,
+                    "Cannot infer the element type of an empty array literal",
+                ))?;
+                Ok(Type::array(element_type, elements.len()))
+            },
+            AstNode::Index { array, index } => {
+                let array_type = self.check_node(array)?;
+                let index_type = self.check_node(index)?;
+                if !index_type.is_integer() {
+                    return Err(Diagnostic::error(
+                        code::INVALID_OPERAND,
+                        format!("Array index must be an integer, got {}", index_type),
+                    ));
+                }
+                match array_type {
+                    Type::Array(element, len) => {
+                        // the length is part of the type, so a constant index
+                        // can be checked against it without running the program
+                        if self.is_constant(index) {
+                            if let ConstValue::Int(i) = consteval::evaluate(index, &self.constants)? {
+                                if i < 0 || i >= len as i128 {
+                                    let error = Diagnostic::error(code::OUT_OF_BOUNDS, format!(
+                                        "Index {} is out of bounds for an array of type {}",
+                                        i,
+                                        Type::Array(element, len)
+                                    ));
+                                    return Err(error.at(index.span));
+                                }
+                            }
+                        }
+                        Ok(*element)
+                    },
+                    other => Err(Diagnostic::error(
+                        code::INVALID_OPERAND,
+                        format!("Cannot index into a value of type {}", other),
+                    )),
+                }
+            },
+            AstNode::StructDef { name, fields, .. } => {
+                if self.env.is_defined_type(name) {
+                    return Err(Diagnostic::error(
+                        code::DUPLICATE_DEFINITION,
+                        format!("Type {} is already defined", name),
+                    ));
+                }
+                for (i, (field, field_type)) in fields.iter().enumerate() {
+                    if fields[..i].iter().any(|(other, _)| other == field) {
+                        return Err(Diagnostic::error(
+                            code::DUPLICATE_DEFINITION,
+                            format!("Duplicate field '{}' in struct {}", field, name),
+                        ));
+                    }
+                    // a struct may refer to itself, e.g. through a function type
+                    match self.unknown_type_name(&self.env.resolve(field_type)) {
+                        Some(inner) if inner != name => return Err(Diagnostic::error(
+                            code::UNKNOWN_NAME,
+                            format!("Unknown type: {}", inner),
+                        )),
+                        _ => {},
+                    }
+                }
+                let fields = fields.iter()
+                    .map(|(field, field_type)| (field.clone(), self.env.resolve(field_type)))
+                    .collect();
+                self.env.define_struct(name.clone(), fields);
+                Ok(Type::Void)
+            },
+            AstNode::EnumDef { name, variants, .. } => {
+                if self.env.is_defined_type(name) {
+                    return Err(Diagnostic::error(
+                        code::DUPLICATE_DEFINITION,
+                        format!("Type {} is already defined", name),
+                    ));
+                }
+                let mut resolved = Vec::new();
+                for (i, (variant, payload)) in variants.iter().enumerate() {
+                    if variants[..i].iter().any(|(other, _)| other == variant) {
+                        return Err(Diagnostic::error(
+                            code::DUPLICATE_DEFINITION,
+                            format!("Duplicate variant '{}' in enum {}", variant, name),
+                        ));
+                    }
+                    let payload: Vec<Type> = payload.iter().map(|t| self.env.resolve(t)).collect();
+                    for payload_type in &payload {
+                        match self.unknown_type_name(payload_type) {
+                            Some(inner) if inner != name => return Err(Diagnostic::error(
+                                code::UNKNOWN_NAME,
+                                format!("Unknown type: {}", inner),
+                            )),
+                            _ => {},
+                        }
+                    }
+                    resolved.push((variant.clone(), payload));
+                }
+                self.env.define_enum(name.clone(), resolved);
+                Ok(Type::Void)
+            },
+            AstNode::TypeAlias { name, target, .. } => {
+                if self.env.is_defined_type(name) {
+                    return Err(Diagnostic::error(
+                        code::DUPLICATE_DEFINITION,
+                        format!("Type {} is already defined", name),
+                    ));
+                }
+                let target = self.env.resolve(target);
+                if let Some(unknown) = self.unknown_type_name(&target) {
+                    return Err(Diagnostic::error(code::UNKNOWN_NAME, format!("Unknown type: {}", unknown)));
+                }
+                self.env.define_alias(name.clone(), target);
+                Ok(Type::Void)
+            },
+            AstNode::EnumVariant { enum_name, variant, args } if enum_name == "Option" => {
+                self.check_option(variant, args, expected.as_ref())
+            },
+            AstNode::EnumVariant { enum_name, variant, args } => {
+                let payload = self.env.get_enum(enum_name)
+                    .ok_or_else(|| Diagnostic::error(code::UNKNOWN_NAME, format!("Unknown enum: {}", enum_name)))?
+                    .iter()
+                    .find(|(declared, _)| declared == variant)
+                    .map(|(_, payload)| payload.clone())
+                    .ok_or_else(|| Diagnostic::error(
+                        code::UNKNOWN_MEMBER,
+                        format!("Enum {} has no variant '{}'", enum_name, variant),
+                    ))?;
+                if payload.len() != args.len() {
+                    return Err(Diagnostic::error(code::WRONG_ARITY, format!(
+                        "Variant {}::{} takes {} payload values, got {}",
+                        enum_name, variant, payload.len(), args.len()
+                    )));
+                }
+                for (index, (expected, arg)) in payload.iter().zip(args).enumerate() {
+                    let arg_type = self.check_expected(arg, expected)?;
+                    if arg_type != *expected {
+                        let error = Diagnostic::error(code::TYPE_MISMATCH, format!(
+                            "Type mismatch in payload {} of {}::{}: expected {}, got {}",
+                            index + 1, enum_name, variant, expected, arg_type
+                        ));
+                        return Err(error.at(arg.span));
+                    }
+                }
+                Ok(Type::Enum(enum_name.clone()))
+            },
+            AstNode::StructLiteral { name, fields } => {
+                let declared = self.env.get_struct(name)
+                    .ok_or_else(|| Diagnostic::error(code::UNKNOWN_NAME, format!("Unknown struct: {}", name)))?
+                    .to_vec();
+                for (field, value) in fields {
+                    let expected = declared.iter()
+                        .find(|(declared_name, _)| declared_name == field)
+                        .map(|(_, t)| t)
+                        .ok_or_else(|| Diagnostic::error(
+                            code::UNKNOWN_MEMBER,
+                            format!("Struct {} has no field '{}'", name, field),
+                        ))?;
+                    let value_type = self.check_expected(value, expected)?;
+                    if value_type != *expected {
+                        let error = Diagnostic::error(code::TYPE_MISMATCH, format!(
+                            "Type mismatch in field '{}' of {}: expected {}, got {}",
+                            field, name, expected, value_type
+                        ));
+                        return Err(error.at(value.span));
+                    }
+                }
+                for (declared_name, _) in &declared {
+                    match fields.iter().filter(|(field, _)| field == declared_name).count() {
+                        0 => return Err(Diagnostic::error(
+                            code::MISSING_ITEM,
+                            format!("Missing field '{}' in {} literal", declared_name, name),
+                        )),
+                        1 => {},
+                        _ => return Err(Diagnostic::error(
+                            code::DUPLICATE_DEFINITION,
+                            format!("Field '{}' specified more than once", declared_name),
+                        )),
+                    }
+                }
+                Ok(Type::Struct(name.clone()))
+            },
+            AstNode::FieldAccess { object, field } => {
+                let object_type = self.check_node(object)?;
+                let name = match &object_type {
+                    Type::Struct(name) => name,
+                    _ => return Err(Diagnostic::error(
+                        code::INVALID_OPERAND,
+                        format!("Cannot access field '{}' on a value of type {}", field, object_type),
+                    )),
+                };
+                self.env.get_struct(name)
+                    .and_then(|fields| fields.iter().find(|(declared, _)| declared == field))
+                    .map(|(_, t)| t.clone())
+                    .ok_or_else(|| Diagnostic::error(
+                        code::UNKNOWN_MEMBER,
+                        format!("Struct {} has no field '{}'", name, field),
+                    ))
+            },
+            AstNode::Call { callee, args } => {
+                let params = match self.env.get(callee) {
+                    Some(Type::Function { params, .. }) if params.len() == args.len() => params.clone(),
+                    _ => vec![],
+                };
+                let mut arg_types = Vec::new();
+                for (index, arg) in args.iter().enumerate() {
+                    let arg_type = match params.get(index) {
+                        Some(param) => self.check_expected(arg, param)?,
+                        None => self.check_node(arg)?,
+                    };
+                    arg_types.push((arg_type, arg.span));
+                }
+                self.check_call(callee, &arg_types)
+            },
+            AstNode::MethodCall { receiver, method, args } => {
+                // UFCS: `x.f(a)` is `f(x, a)`
+                let mut arg_types = vec![(self.check_node(receiver)?, receiver.span)];
+                for arg in args {
+                    arg_types.push((self.check_node(arg)?, arg.span));
+                }
+                // besides a match, the one way to get at the value of an option
+                if let (Type::Option(inner), "unwrap", []) = (&arg_types[0].0, method.as_str(), args.as_slice()) {
+                    return Ok((**inner).clone());
+                }
+                // methods from traits are found by the receiver's type
+                if let Some(Type::Function { params, return_type }) = self.method_signature(&arg_types[0].0, method) {
+                    let bindings = self.check_arguments(method, &params, &arg_types)?;
+                    return Ok(return_type.substitute(&bindings));
+                }
+                match self.env.get(method) {
+                    Some(Type::Function { params, .. })
+                        if params.first().is_some_and(|param| param.unify(&arg_types[0].0, &mut Default::default())) => {},
+                    _ => return Err(Diagnostic::error(
+                        code::UNKNOWN_MEMBER,
+                        format!("No method '{}' found for {}", method, arg_types[0].0),
+                    )),
+                }
+                self.check_call(method, &arg_types)
+            },
+            AstNode::Closure { params, return_type, body } => {
+                let function = self.function.take();
+                let closure_scope = self.closure_scope.replace(self.env.depth());
+                let declared = return_type.as_ref().map(|declared| self.env.resolve(declared));
+                let body_type = self.check_body(params, body, declared.as_ref());
+                self.function = function;
+                self.closure_scope = closure_scope;
+
+                let body_type = body_type?;
+                if let (Some(written), Some(declared)) = (return_type, declared) {
+                    if declared != body_type {
+                        return Err(Diagnostic::error(
+                            code::TYPE_MISMATCH,
+                            format!("Type mismatch: expected {}, got {}", self.describe(written), body_type),
+                        ));
+                    }
+                }
+                let param_types = params.iter().map(|(_, t)| self.env.resolve(t)).collect();
+                Ok(Type::function(param_types, body_type))
+            },
+            AstNode::Assign { name, value } => {
+                self.check_capture(name)?;
+                let target_type = self.env.get(name)
+                    .cloned()
+                    .ok_or_else(|| Diagnostic::error(
+                        code::UNKNOWN_NAME,
+                        format!("Cannot assign to undefined variable: {}{}", name, self.did_you_mean(name)),
+                    ))?;
+                if !self.env.is_mutable(name) {
+                    let error = Diagnostic::error(
+                        code::IMMUTABLE_ASSIGNMENT,
+                        format!("Cannot assign twice to immutable variable {}", name),
+                    );
+                    let mut error = error.at(self.span);
+                    if let Some(span) = self.env.declaration_span(name).filter(|span| *span != Span::default()) {
+                        error = error.with_note(format!("note: {} was declared without `mut` at {}", name, span));
+                    }
+                    return Err(error.with_note(format!("help: declare it with `let mut {}`", name)));
+                }
+                let value_type = self.check_expected(value, &target_type)?;
+                if value_type != target_type {
+                    return Err(Diagnostic::error(code::TYPE_MISMATCH, format!(
+                        "Type mismatch in assignment to {}: expected {}, got {}",
+                        name, target_type, value_type
+                    )));
+                }
+                Ok(Type::Void)
+            },
+            AstNode::ExpressionStatement(expr) => {
+                let expr_type = self.check_node(expr)?;
+                if expr_type != Type::Void {
+                    let warning = format!("Unused result of type {}; use `let _ = ...` to discard it", expr_type);
+                    self.warn(code::UNUSED_RESULT, warning, expr.span);
+                }
+                Ok(Type::Void)
+            },
+            // imports have no effect until the module system lands
+            AstNode::Import(_) | AstNode::Module(_) => Ok(Type::Void),
+        }
+    }
+
+    /// Checks the body of a function or method against its declared return
+    /// type.
+    fn check_function(
+        &mut self,
+        name: &str,
+        params: &[(String, Type)],
+        written_return_type: &Type,
+        body: &Spanned<AstNode>,
+    ) -> Result<Type, Diagnostic> {
+        let return_type = self.env.resolve(written_return_type);
+        let outer = self.function.replace((name.to_string(), written_return_type.clone()));
+        let function_scope = std::mem::replace(&mut self.function_scope, self.env.depth());
+        let closure_scope = self.closure_scope.take();
+        let body_type = self.check_body(params, body, Some(&return_type));
+        self.function = outer;
+        self.function_scope = function_scope;
+        self.closure_scope = closure_scope;
+
+        // a tail expression is returned just like `return`
+        let body_type = body_type?;
+        if body_type != Type::Void && body_type != return_type {
+            return Err(self.return_mismatch(name, written_return_type, &body_type));
+        }
+        if body_type == Type::Void && return_type != Type::Void && !always_returns(body) {
+            let error = Diagnostic::error(code::MISSING_RETURN, format!(
+                "Function {} can reach the end of its body without returning a value of type {}",
+                name,
+                self.describe(written_return_type)
+            ));
+            return Err(error.with_note("help: end the body with a value or a `return`"));
+        }
+        Ok(body_type)
+    }
+
+    /// Rejects a use of `name` inside a closure that would capture a local
+    /// of the function around it, which closures cannot do yet.
+    fn check_capture(&self, name: &str) -> Result<(), Diagnostic> {
+        let (Some(closure_scope), Some(depth)) = (self.closure_scope, self.env.depth_of(name)) else {
+            return Ok(());
+        };
+        if depth < self.function_scope || depth >= closure_scope {
+            return Ok(());
+        }
+        let error = Diagnostic::error(
+            code::CAPTURE,
+            format!("Closures cannot capture variables yet, but this one uses `{}` from outside it", name),
+        );
+        Err(error.with_note(format!("help: pass `{}` to the closure as a parameter", name)))
+    }
+
+    /// Checks a function or closure body in a scope of its own, in which
+    /// the parameters shadow outer bindings of the same name. The body's
+    /// value is checked against the return type, if one was declared.
+    fn check_body(
+        &mut self,
+        params: &[(String, Type)],
+        body: &Spanned<AstNode>,
+        return_type: Option<&Type>,
+    ) -> Result<Type, Diagnostic> {
+        self.env.push_scope();
+        for (param, param_type) in params {
+            let param_type = self.env.resolve(param_type);
+            self.env.declare(param.clone(), param_type, false, self.span);
+            // a method need not look at its receiver
+            if param != "self" {
+                self.track_usage(param, BindingKind::Parameter, self.span);
             }
-            AstNode::Return(expr) => self.check(expr),
-            _ => Err("Unsupported node type for type checking".to_string()),
         }
+        let loop_depth = std::mem::replace(&mut self.loop_depth, 0);
+        let body_type = self.check_hinted(body, return_type);
+        self.loop_depth = loop_depth;
+        self.pop_scope();
+        body_type
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    
-    #[test]
-    fn test_type_checker() {
-        let mut checker = TypeChecker::new();
-        let node = AstNode::Let {
-            name: "x".to_string(),
-            type_annotation: Some("int".to_string()),
-            value: Box::new(AstNode::Number(42)),
+    /// Declares the items of a block before any statement in it is checked,
+    /// so that functions and constants can be used ahead of their
+    /// definitions. Type definitions are checked here, in order, because
+    /// function signatures refer to them; function bodies and constant
+    /// values are left for the statements' turn.
+    fn declare_items(&mut self, nodes: &[Spanned<AstNode>]) -> Result<(), Diagnostic> {
+        for node in nodes.iter().filter(|node| Self::is_type_definition(node)) {
+            self.check_node(node)?;
+        }
+        for node in nodes {
+            match &node.node {
+                AstNode::Function { name, type_params, params, return_type, .. } => {
+                    self.generics.insert(name.clone(), type_params.clone());
+                    let signature = self.signature(params, return_type);
+                    self.declare_function(name, signature, node.span);
+                },
+                AstNode::Const { name, type_annotation, .. } => {
+                    let declared = self.env.resolve(type_annotation);
+                    self.env.declare(name.clone(), declared, false, node.span);
+                },
+                AstNode::Impl { trait_name, target, methods, .. } => {
+                    self.declare_impl(trait_name, target, methods).map_err(|error| error.at(node.span))?;
+                },
+                _ => {},
+            }
+        }
+        Ok(())
+    }
+
+    fn is_type_definition(node: &AstNode) -> bool {
+        matches!(
+            node,
+            AstNode::StructDef { .. } | AstNode::EnumDef { .. } | AstNode::TypeAlias { .. } | AstNode::TraitDef { .. }
+        )
+    }
+
+    /// Checks that an impl provides exactly the methods of its trait, with
+    /// `Self` replaced by the target type, and records it.
+    fn declare_impl(&mut self, trait_name: &str, target: &Type, methods: &[Spanned<AstNode>]) -> Result<(), Diagnostic> {
+        let declared = self.env.get_trait(trait_name)
+            .ok_or_else(|| Diagnostic::error(code::UNKNOWN_NAME, format!("Unknown trait: {}", trait_name)))?
+            .to_vec();
+        let target = self.env.resolve(target);
+        if let Some(unknown) = self.unknown_type_name(&target) {
+            return Err(Diagnostic::error(code::UNKNOWN_NAME, format!("Unknown type: {}", unknown)));
+        }
+        if self.env.implements(&target, trait_name) {
+            return Err(Diagnostic::error(
+                code::DUPLICATE_DEFINITION,
+                format!("Type {} already implements {}", target, trait_name),
+            ));
+        }
+
+        let mut signatures = Vec::new();
+        for method in methods {
+            let AstNode::Function { name, params, return_type, .. } = &method.node else {
+                continue;
+            };
+            if !declared.iter().any(|(declared_name, _)| declared_name == name) {
+                return Err(Diagnostic::error(
+                    code::UNKNOWN_MEMBER,
+                    format!("Method {} is not a member of trait {}", name, trait_name),
+                ));
+            }
+            if self.env.find_method(&target, name).is_some() {
+                return Err(Diagnostic::error(
+                    code::DUPLICATE_DEFINITION,
+                    format!("Method {} is already defined for {} by another trait", name, target),
+                ));
+            }
+            signatures.push((name.clone(), self.signature(params, return_type)));
+        }
+        let self_type = std::collections::HashMap::from([("Self".to_string(), target.clone())]);
+        for (name, signature) in &declared {
+            let expected = signature.substitute(&self_type);
+            match signatures.iter().find(|(implemented, _)| implemented == name) {
+                None => return Err(Diagnostic::error(
+                    code::MISSING_ITEM,
+                    format!("Impl of {} for {} is missing method {}", trait_name, target, name),
+                )),
+                Some((_, found)) if *found != expected => {
+                    return Err(Diagnostic::error(code::TYPE_MISMATCH, format!(
+                        "Method {} of {} for {} has type {}, but the trait declares {}",
+                        name, trait_name, target, found, expected
+                    )));
+                },
+                Some(_) => {},
+            }
+        }
+        self.env.define_impl(trait_name.to_string(), target, signatures);
+        Ok(())
+    }
+
+    /// The signature of `method` on a value of type `receiver`, from the
+    /// trait bounds of a type parameter or from an impl.
+    fn method_signature(&self, receiver: &Type, method: &str) -> Option<Type> {
+        let Type::Param(param) = receiver else {
+            return self.env.find_method(receiver, method).cloned();
         };
-        
-        assert_eq!(checker.check(&node), Ok(Type::Int));
-        
-        let node_error = AstNode::Let {
-            name: "y".to_string(),
-            type_annotation: Some("string".to_string()),
-            value: Box::new(AstNode::Number(42)),
+        let bounds = self.bounds.iter().find(|(name, _)| name == param).map(|(_, bounds)| bounds)?;
+        let self_type = std::collections::HashMap::from([("Self".to_string(), receiver.clone())]);
+        bounds.iter()
+            .filter_map(|bound| self.env.get_trait(bound))
+            .flatten()
+            .find(|(name, _)| name == method)
+            .map(|(_, signature)| signature.substitute(&self_type))
+    }
+
+    /// Whether `type_` implements `trait_name`; a type parameter does if it
+    /// is bound by it.
+    fn satisfies(&self, type_: &Type, trait_name: &str) -> bool {
+        match type_ {
+            Type::Param(param) => self.bounds.iter().any(|(name, bounds)| name == param && bounds.iter().any(|b| b == trait_name)),
+            _ => self.env.implements(type_, trait_name),
+        }
+    }
+
+    fn signature(&self, params: &[(String, Type)], return_type: &Type) -> Type {
+        let param_types = params.iter().map(|(_, t)| self.env.resolve(t)).collect();
+        Type::function(param_types, self.env.resolve(return_type))
+    }
+
+    fn declare_function(&mut self, name: &str, signature: Type, span: Span) {
+        self.env.declare(name.to_string(), signature, false, span);
+        if name != "main" {
+            self.track_usage(name, BindingKind::Function, span);
+        }
+    }
+
+    /// Checks a match: every arm must be reachable, the arms must agree on
+    /// a type, and together they must cover every value of the scrutinee.
+    /// Only enums, options and `bool` can be covered without a `_` arm.
+    fn check_match(
+        &mut self,
+        scrutinee: &Spanned<AstNode>,
+        arms: &[(Spanned<Pattern>, Spanned<AstNode>)],
+        expected: Option<Type>,
+    ) -> Result<Type, Diagnostic> {
+        let scrutinee_type = self.check_node(scrutinee)?;
+        let mut covered: Vec<String> = Vec::new();
+        let mut has_wildcard = false;
+        let mut match_type: Option<Type> = None;
+        for (pattern, body) in arms {
+            let key = match &pattern.node {
+                Pattern::Wildcard => None,
+                Pattern::Variant { variant, .. } => Some(variant.clone()),
+                Pattern::Literal(literal) => Some(format!("{:?}", literal)),
+            };
+            if has_wildcard || key.as_ref().is_some_and(|key| covered.contains(key)) {
+                self.warn(code::UNREACHABLE_PATTERN, "Unreachable match arm".to_string(), pattern.span);
+            }
+            match key {
+                Some(key) => covered.push(key),
+                None => has_wildcard = true,
+            }
+
+            self.env.push_scope();
+            // later arms are checked against the first, so that literals agree with it
+            let arm_type = self.check_pattern(pattern, &scrutinee_type)
+                .and_then(|_| self.check_hinted(body, match_type.as_ref().or(expected.as_ref())));
+            self.pop_scope();
+            let arm_type = arm_type?;
+            match &match_type {
+                Some(first) if *first != arm_type => {
+                    let error = Diagnostic::error(
+                        code::TYPE_MISMATCH,
+                        format!("Match arms have mismatched types: {} and {}", first, arm_type),
+                    );
+                    return Err(error.at(body.span));
+                },
+                Some(_) => {},
+                None => match_type = Some(arm_type),
+            }
+        }
+
+        if !has_wildcard {
+            let missing: Vec<String> = match &scrutinee_type {
+                Type::Enum(_) | Type::Option(_) => {
+                    let name = match &scrutinee_type {
+                        Type::Enum(name) => name.as_str(),
+                        _ => "Option",
+                    };
+                    self.env.variants(&scrutinee_type)
+                        .unwrap_or_default()
+                        .iter()
+                        .filter(|(variant, _)| !covered.contains(variant))
+                        .map(|(variant, _)| format!("{}::{}", name, variant))
+                        .collect()
+                },
+                Type::Bool => [true, false]
+                    .into_iter()
+                    .filter(|value| !covered.contains(&format!("{:?}", AstNode::Boolean(*value))))
+                    .map(|value| value.to_string())
+                    .collect(),
+                _ => return Err(Diagnostic::error(
+                    code::NON_EXHAUSTIVE_MATCH,
+                    format!("Non-exhaustive match on {}; add a `_` arm", scrutinee_type),
+                )),
+            };
+            if !missing.is_empty() {
+                return Err(Diagnostic::error(
+                    code::NON_EXHAUSTIVE_MATCH,
+                    format!("Non-exhaustive match on {}: missing {}", scrutinee_type, missing.join(", ")),
+                ));
+            }
+        }
+        Ok(match_type.unwrap_or(Type::Void))
+    }
+
+    /// Checks that `pattern` can match a value of `scrutinee_type`, and
+    /// declares the payload values it binds in the current scope.
+    fn check_pattern(&mut self, pattern: &Spanned<Pattern>, scrutinee_type: &Type) -> Result<(), Diagnostic> {
+        let error = match &pattern.node {
+            Pattern::Wildcard => return Ok(()),
+            Pattern::Literal(literal) => {
+                let fits = match literal {
+                    AstNode::Number(_) => scrutinee_type.integer_range().is_some(),
+                    AstNode::Boolean(_) => *scrutinee_type == Type::Bool,
+                    AstNode::Char(_) => *scrutinee_type == Type::Char,
+                    AstNode::StringLiteral(_) => *scrutinee_type == Type::String,
+                    _ => scrutinee_type.is_float(),
+                };
+                if fits {
+                    return Ok(());
+                }
+                format!("Literal pattern cannot match a value of type {}", scrutinee_type)
+            },
+            Pattern::Variant { enum_name, variant, bindings } => {
+                let of_enum = match scrutinee_type {
+                    Type::Enum(name) => name == enum_name,
+                    Type::Option(_) => enum_name == "Option",
+                    _ => false,
+                };
+                if !of_enum {
+                    format!("Pattern {}::{} cannot match a value of type {}", enum_name, variant, scrutinee_type)
+                } else {
+                    let payload = self.env.variants(scrutinee_type)
+                        .and_then(|variants| variants.into_iter().find(|(declared, _)| declared == variant))
+                        .map(|(_, payload)| payload);
+                    match payload {
+                        None => format!("Enum {} has no variant '{}'", enum_name, variant),
+                        Some(payload) if payload.len() != bindings.len() => format!(
+                            "Variant {}::{} takes {} payload values, but the pattern binds {}",
+                            enum_name, variant, payload.len(), bindings.len()
+                        ),
+                        Some(payload) => {
+                            for (binding, binding_type) in bindings.iter().zip(payload) {
+                                self.env.insert(binding.clone(), binding_type);
+                                self.track_usage(binding, BindingKind::Variable, pattern.span);
+                            }
+                            return Ok(());
+                        },
+                    }
+                }
+            },
         };
-        
-        assert!(checker.check(&node_error).is_err());
+        Err(Diagnostic::error(code::INVALID_PATTERN, error).at(pattern.span))
+    }
+
+    /// Watches a binding declared at `span`, unless its name starts with
+    /// `_` to say that it is unused on purpose.
+    fn track_usage(&mut self, name: &str, kind: BindingKind, span: Span) {
+        if !name.starts_with('_') {
+            self.env.track_usage(name.to_string(), kind, span);
+        }
+    }
+
+    /// Closes a scope, warning about the bindings in it that were never used.
+    fn pop_scope(&mut self) {
+        for (name, kind, span) in self.env.pop_scope() {
+            let warning = match kind {
+                BindingKind::Variable | BindingKind::Parameter => format!(
+                    "Unused {} `{}`; prefix it with an underscore to silence this warning",
+                    if kind == BindingKind::Variable { "variable" } else { "parameter" },
+                    name
+                ),
+                BindingKind::Function => format!("Function `{}` is never called", name),
+            };
+            self.warn(code::UNUSED, warning, span);
+        }
+    }
+
+    fn warn(&mut self, code: &'static str, message: String, span: Span) {
+        let mut diagnostic = Diagnostic::warning(code, message).at(span);
+        if self.deny_warnings {
+            diagnostic.severity = Severity::Error;
+        }
+        self.diagnostics.push(diagnostic);
+    }
+
+    /// Checks `node` where a value of type `expected` is wanted. An integer
+    /// literal takes on an expected integer type, if it fits, rather than
+    /// defaulting to `i32`, and a float literal an expected `f32` rather
+    /// than `f64`; anything else is checked as usual.
+    fn check_expected(&mut self, node: &Spanned<AstNode>, expected: &Type) -> Result<Type, Diagnostic> {
+        match (Self::integer_literal(node), expected.integer_range()) {
+            (Some(value), Some(_)) => {
+                Self::check_literal_range(value, expected).map_err(|error| error.at(node.span))?;
+            },
+            _ if expected.is_float() && Self::is_float_literal(node) => {},
+            _ => {
+                self.expected = Some(expected.clone());
+                return self.check_node(node);
+            },
+        }
+        node.set_ty(expected.clone());
+        if let AstNode::UnaryOp { operand, .. } = &node.node {
+            operand.set_ty(expected.clone());
+        }
+        Ok(expected.clone())
+    }
+
+    /// Fails unless the integer literal `value` is one of the values of the
+    /// integer type `ty`.
+    fn check_literal_range(value: i128, ty: &Type) -> Result<(), Diagnostic> {
+        match ty.integer_range() {
+            Some((min, max)) if value < min || value > max => Err(Diagnostic::error(code::LITERAL_OUT_OF_RANGE, format!(
+                "Integer literal {} does not fit in {}, whose values range from {} to {}",
+                value, ty, min, max
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks the condition of an `if`, `while` or conditional, which must be
+    /// a `bool`: no other value counts as true or false.
+    fn check_condition(&mut self, cond: &Spanned<AstNode>) -> Result<(), Diagnostic> {
+        let cond_type = self.check_node(cond)?;
+        if cond_type == Type::Bool {
+            return Ok(());
+        }
+        let error = Diagnostic::error(
+            code::TYPE_MISMATCH,
+            format!("Expected bool, found {} — Nova has no truthiness", cond_type),
+        );
+        let error = error.at(cond.span);
+        if cond_type.is_numeric() {
+            return Err(error.with_note("help: compare it against zero, as in `n != 0`"));
+        } else if let Type::Option(_) = cond_type {
+            return Err(error.with_note("help: match on it to check whether it holds a value"));
+        }
+        Err(error)
+    }
+
+    /// Checks `node` against `expected` if a type is wanted of it.
+    fn check_hinted(&mut self, node: &Spanned<AstNode>, expected: Option<&Type>) -> Result<Type, Diagnostic> {
+        match expected {
+            Some(expected) => self.check_expected(node, expected),
+            None => self.check_node(node),
+        }
+    }
+
+    /// Checks `Option::Some(value)` or `Option::None`. Which option `None`
+    /// is can only be told from the type expected of it.
+    fn check_option(&mut self, variant: &str, args: &[Spanned<AstNode>], expected: Option<&Type>) -> Result<Type, Diagnostic> {
+        let inner = match expected {
+            Some(Type::Option(inner)) => Some(&**inner),
+            _ => None,
+        };
+        match (variant, args) {
+            ("Some", [value]) => {
+                let value_type = self.check_hinted(value, inner)?;
+                if value_type == Type::Void {
+                    return Err(Diagnostic::error(
+                        code::INVALID_OPERAND,
+                        "Option::Some needs a value, got Void",
+                    ).at(value.span));
+                }
+                Ok(Type::option(value_type))
+            },
+            ("None", []) => inner.cloned().map(Type::option).ok_or_else(|| {
+                Diagnostic::error(
+                    code::CANNOT_INFER,
+                    "Cannot infer the type of Option::None; annotate it, as in `let x: Option<i32> = Option::None;`",
+                )
+            }),
+            ("Some" | "None", _) => Err(Diagnostic::error(code::WRONG_ARITY, format!(
+                "Variant Option::{} takes {} payload values, got {}",
+                variant,
+                if variant == "Some" { 1 } else { 0 },
+                args.len()
+            ))),
+            _ => Err(Diagnostic::error(code::UNKNOWN_MEMBER, format!("Enum Option has no variant '{}'", variant))),
+        }
+    }
+
+    /// Checks the operands of a binary operator. An integer literal on one
+    /// side takes its type from the other side.
+    fn check_operands(&mut self, left: &Spanned<AstNode>, right: &Spanned<AstNode>) -> Result<(Type, Type), Diagnostic> {
+        let is_literal = |node: &AstNode| Self::integer_literal(node).is_some() || Self::is_float_literal(node);
+        if is_literal(left) && !is_literal(right) {
+            let right_type = self.check_node(right)?;
+            return Ok((self.check_expected(left, &right_type)?, right_type));
+        }
+        let left_type = self.check_node(left)?;
+        Ok((left_type.clone(), self.check_expected(right, &left_type)?))
+    }
+
+    /// The value of `1` or `-1`, the only forms an integer literal takes.
+    fn integer_literal(node: &AstNode) -> Option<i128> {
+        match node {
+            AstNode::Number(n) => Some(*n as i128),
+            AstNode::UnaryOp { op: UnaryOperator::Negate, operand } => match operand.node {
+                AstNode::Number(n) => Some(-(n as i128)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn is_float_literal(node: &AstNode) -> bool {
+        match node {
+            AstNode::Float(_) => true,
+            AstNode::UnaryOp { op: UnaryOperator::Negate, operand } => matches!(operand.node, AstNode::Float(_)),
+            _ => false,
+        }
+    }
+
+    /// Checks a child node and annotates it with its type if it is an
+    /// expression. An error raised by the node itself, rather than by one of
+    /// its own children, is pointed at the node's span; nodes built outside
+    /// the parser have no position to report.
+    fn check_node(&mut self, node: &Spanned<AstNode>) -> Result<Type, Diagnostic> {
+        let outer = std::mem::replace(&mut self.span, node.span);
+        let node_type = self.check(node);
+        self.span = outer;
+        let node_type = node_type.map_err(|error| error.at(node.span))?;
+        if node.is_expression() {
+            node.set_ty(node_type.clone());
+        }
+        // operations on constants are folded, so that division by zero or
+        // overflow in them is caught now rather than when the program runs,
+        // and references to constants are replaced by their values
+        let foldable = matches!(
+            node.node,
+            AstNode::BinaryOp { .. } | AstNode::UnaryOp { .. } | AstNode::Cast { .. } | AstNode::Identifier(_)
+        );
+        if foldable && self.is_constant(node) {
+            let folded = consteval::evaluate(node, &self.constants)
+                .map_err(|error| Diagnostic::from(error).at(node.span))?;
+            node.set_folded(folded);
+        }
+        Ok(node_type)
+    }
+
+    /// The first struct name inside `type_` that was never declared.
+    fn unknown_type_name<'t>(&self, type_: &'t Type) -> Option<&'t str> {
+        match type_ {
+            Type::Struct(name) if self.env.get_struct(name).is_none() => Some(name),
+            Type::Array(element, _) => self.unknown_type_name(element),
+            Type::Function { params, return_type } => params.iter()
+                .chain(std::iter::once(&**return_type))
+                .find_map(|t| self.unknown_type_name(t)),
+            _ => None,
+        }
+    }
+
+    /// Literals, operators over constant operands and references to other
+    /// constants; anything that could need a value at run time is rejected.
+    fn is_constant(&self, node: &AstNode) -> bool {
+        match node {
+            AstNode::Number(_)
+            | AstNode::Float(_)
+            | AstNode::Char(_)
+            | AstNode::Boolean(_)
+            | AstNode::StringLiteral(_) => true,
+            AstNode::Identifier(name) => self.constants.contains_key(name),
+            AstNode::UnaryOp { operand, .. } => self.is_constant(operand),
+            AstNode::BinaryOp { left, right, .. } => self.is_constant(left) && self.is_constant(right),
+            AstNode::Cast { value, .. } => self.is_constant(value),
+            _ => false,
+        }
+    }
+
+    /// A hint naming the bound name closest to the misspelt `name`, if any
+    /// is close enough to be what was meant.
+    fn did_you_mean(&self, name: &str) -> String {
+        let len = name.chars().count();
+        // short names differ in a large share of their letters too easily
+        let max_distance = (len / 3).max(1).min(len.saturating_sub(1));
+        let closest = self.env.names()
+            .map(|candidate| (edit_distance(name, candidate), candidate))
+            .filter(|&(distance, _)| distance <= max_distance)
+            .min();
+        match closest {
+            Some((_, candidate)) => format!("; did you mean `{}`?", candidate),
+            None => String::new(),
+        }
+    }
+
+    /// Checks `args`, each with the span it was written at, against the
+    /// signature of `name`. A bad argument is reported at its own position.
+    fn check_call(&mut self, name: &str, args: &[(Type, Span)]) -> Result<Type, Diagnostic> {
+        self.env.mark_used(name);
+        let (params, return_type) = match self.env.get(name) {
+            Some(Type::Function { params, return_type }) => (params.clone(), (**return_type).clone()),
+            Some(other) => return Err(Diagnostic::error(
+                code::INVALID_OPERAND,
+                format!("'{}' is not a function, it has type {}", name, other),
+            )),
+            None if BUILTINS.contains(&name) => return Self::check_print(name, args),
+            None => return Err(Diagnostic::error(
+                code::UNKNOWN_NAME,
+                format!("Undefined function: {}{}", name, self.did_you_mean(name)),
+            )),
+        };
+        let bindings = self.check_arguments(name, &params, args)?;
+
+        let type_params = match self.generics.get(name) {
+            Some(type_params) if !type_params.is_empty() => type_params.clone(),
+            _ => return Ok(return_type),
+        };
+        let mut type_args = Vec::new();
+        for (type_param, bounds) in &type_params {
+            let type_arg = bindings.get(type_param)
+                .ok_or_else(|| Diagnostic::error(
+                    code::CANNOT_INFER,
+                    format!("Cannot infer type parameter {} of {} from its arguments", type_param, name),
+                ))?;
+            if let Some(bound) = bounds.iter().find(|bound| !self.satisfies(type_arg, bound)) {
+                return Err(Diagnostic::error(code::UNSATISFIED_BOUND, format!(
+                    "Type {} does not implement {}, required by type parameter {} of {}",
+                    type_arg, bound, type_param, name
+                )));
+            }
+            type_args.push(type_arg.clone());
+        }
+        let instantiation = Instantiation { function: name.to_string(), type_args };
+        if !instantiation.type_args.iter().any(Type::is_generic) && !self.instantiations.contains(&instantiation) {
+            self.instantiations.push(instantiation);
+        }
+        Ok(return_type.substitute(&bindings))
+    }
+
+    /// Checks a call of `print` or `println`, which take any number of
+    /// numbers, booleans, characters and strings.
+    fn check_print(name: &str, args: &[(Type, Span)]) -> Result<Type, Diagnostic> {
+        for (index, (arg, span)) in args.iter().enumerate() {
+            if !(arg.is_numeric() || matches!(arg, Type::Bool | Type::Char | Type::String)) {
+                let error = Diagnostic::error(code::INVALID_OPERAND, format!(
+                    "Cannot print argument {} of {}, a value of type {}",
+                    index + 1,
+                    name,
+                    arg
+                ));
+                return Err(error.at(*span));
+            }
+        }
+        Ok(Type::Void)
+    }
+
+    /// Checks the number and types of `args` against `params`, binding the
+    /// type parameters they mention.
+    fn check_arguments(
+        &mut self,
+        name: &str,
+        params: &[Type],
+        args: &[(Type, Span)],
+    ) -> Result<std::collections::HashMap<String, Type>, Diagnostic> {
+        if params.len() != args.len() {
+            return Err(Diagnostic::error(
+                code::WRONG_ARITY,
+                format!("Function {} expects {} arguments, got {}", name, params.len(), args.len()),
+            ));
+        }
+        // a generic function's type parameters are bound by its arguments
+        let mut bindings = std::collections::HashMap::new();
+        for (index, (param, (arg, span))) in params.iter().zip(args).enumerate() {
+            if !param.unify(arg, &mut bindings) {
+                let error = Diagnostic::error(code::TYPE_MISMATCH, format!(
+                    "Type mismatch in argument {} of {}: expected {}, got {}",
+                    index + 1,
+                    name,
+                    param.substitute(&bindings),
+                    arg
+                ));
+                return Err(error.at(*span));
+            }
+        }
+        Ok(bindings)
+    }
+
+    /// Adds a hint to an error about an operator applied to a string and a
+    /// number, which Nova never converts into one another on its own.
+    fn hint_string_conversion(error: Diagnostic, left: &Type, right: &Type) -> Diagnostic {
+        match (left, right) {
+            (Type::String, other) | (other, Type::String) if other.is_numeric() => {
+                error.with_note("help: convert the number to a string first, as in `f\"{n}\"`")
+            },
+            _ => error,
+        }
+    }
+
+    fn return_mismatch(&self, function: &str, declared: &Type, found: &Type) -> Diagnostic {
+        Diagnostic::error(
+            code::TYPE_MISMATCH,
+            format!("Function {} is declared to return {}, but returns {}", function, self.describe(declared), found),
+        )
+    }
+
+    /// How a type written in the source is named in errors: resolved, with
+    /// the alias it was spelled as, if any, so that a mismatch on `Meters`
+    /// does not just say `i32`.
+    fn describe(&self, written: &Type) -> String {
+        let resolved = self.env.resolve(written);
+        match written {
+            Type::Struct(name) if self.env.get_alias(name).is_some() => format!("{} (alias of {})", name, resolved),
+            _ => resolved.to_string(),
+        }
+    }
+}
+
+/// Whether every path through `node` ends in a `return`. A `while true`
+/// that nothing breaks out of counts too, as it can only be left by
+/// returning.
+fn always_returns(node: &AstNode) -> bool {
+    match node {
+        AstNode::Return(_) => true,
+        AstNode::Program(statements) => statements.iter().any(|statement| always_returns(statement)),
+        AstNode::ExpressionStatement(expr) => always_returns(expr),
+        AstNode::If { then_block, else_block: Some(else_block), .. } => {
+            always_returns(then_block) && always_returns(else_block)
+        },
+        AstNode::Conditional { then_value, else_value, .. } => always_returns(then_value) && always_returns(else_value),
+        AstNode::Match { arms, .. } => !arms.is_empty() && arms.iter().all(|(_, body)| always_returns(body)),
+        AstNode::While { cond, body } => matches!(cond.node, AstNode::Boolean(true)) && !breaks_out(body),
+        _ => false,
+    }
+}
+
+/// Whether a loop body contains a `break` that leaves that loop rather
+/// than one nested in it.
+fn breaks_out(node: &AstNode) -> bool {
+    match node {
+        AstNode::Break => true,
+        AstNode::While { .. } | AstNode::Closure { .. } => false,
+        _ => node.children().into_iter().any(|child| breaks_out(child)),
+    }
+}
+
+/// The number of single-character insertions, deletions and substitutions
+/// that turn `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses and checks `source`, reporting an error by its summary.
+    fn check_source(source: &str) -> Result<Type, String> {
+        let ast = crate::parser::Parser::from_source(source).unwrap().parse().unwrap();
+        TypeChecker::new().check(&ast).map_err(|error| error.summary())
+    }
+
+    #[test]
+    fn test_type_checker() {
+        let mut checker = TypeChecker::new();
+        let node = AstNode::Let {
+            name: "x".to_string(),
+            mutable: false,
+            type_annotation: Some(Type::I32),
+            value: Box::new(AstNode::Number(42).into()),
+        };
+        
+        assert_eq!(checker.check(&node), Ok(Type::I32));
+        
+        let node_error = AstNode::Let {
+            name: "y".to_string(),
+            mutable: false,
+            type_annotation: Some(Type::String),
+            value: Box::new(AstNode::Number(42).into()),
+        };
+        
+        assert!(checker.check(&node_error).is_err());
+    }
+
+    #[test]
+    fn test_unary_operators() {
+        let mut checker = TypeChecker::new();
+        let negate = AstNode::UnaryOp {
+            op: UnaryOperator::Negate,
+            operand: Box::new(AstNode::Float(1.5).into()),
+        };
+        assert_eq!(checker.check(&negate), Ok(Type::F64));
+
+        let not = AstNode::UnaryOp {
+            op: UnaryOperator::Not,
+            operand: Box::new(AstNode::Number(1).into()),
+        };
+        assert!(checker.check(&not).is_err());
+
+        assert!(check_source("fn f(a: i8, b: f32): f32 { -(a as f32) - -b }").is_ok());
+        assert_eq!(
+            check_source("fn f(a: u32): u32 { -a }"),
+            Err("Cannot negate a value of type u32, which is unsigned at line 1, column 21".to_string())
+        );
+    }
+
+    #[test]
+    fn test_casts() {
+        assert!(check_source("type Meters = i64;\nfn f(a: i8, b: f32): f64 { let m = a as Meters + 1; (m as u8) as f64 * b as f64 }").is_ok());
+        assert!(check_source("fn f(c: char): char { c as char }").is_ok());
+        assert_eq!(
+            check_source("fn f(a: i32): i64 { a as i64 + a }"),
+            Err("Arithmetic operator Add requires operands of the same type, got i64 and i32 at line 1, column 21".to_string())
+        );
+        assert_eq!(
+            check_source("fn f(b: bool): i32 { b as i32 }"),
+            Err("Cannot cast a value of type bool to i32 at line 1, column 22".to_string())
+        );
+        assert_eq!(
+            check_source("fn f(c: char): u32 { c as u32 }"),
+            Err("Cannot cast a value of type char to u32 at line 1, column 22".to_string())
+        );
+        assert_eq!(
+            check_source("fn f(a: i32): string { a as string }"),
+            Err("Cannot cast a value of type i32 to string at line 1, column 24".to_string())
+        );
+    }
+
+    #[test]
+    fn test_comparison_and_logical_operators() {
+        let mut checker = TypeChecker::new();
+        let binary = |op, left: AstNode, right: AstNode| AstNode::BinaryOp {
+            op,
+            left: Box::new(left.into()),
+            right: Box::new(right.into()),
+        };
+
+        let less = binary(BinaryOperator::Lt, AstNode::Number(1), AstNode::Number(2));
+        assert_eq!(checker.check(&less), Ok(Type::Bool));
+        let both = binary(BinaryOperator::And, less.clone(), AstNode::Boolean(true));
+        assert_eq!(checker.check(&both), Ok(Type::Bool));
+
+        let mixed = binary(BinaryOperator::Eq, AstNode::Number(1), AstNode::Boolean(true));
+        assert_eq!(
+            checker.check(&mixed).map_err(|error| error.summary()),
+            Err("Cannot compare i32 with bool".to_string())
+        );
+        let not_bool = binary(BinaryOperator::Or, AstNode::Number(1), AstNode::Number(2));
+        assert!(checker.check(&not_bool).is_err());
+    }
+
+    #[test]
+    fn test_format_string() {
+        let mut checker = TypeChecker::new();
+        checker.env.insert("xs".to_string(), Type::array(Type::I32, 2));
+        let node = AstNode::FormatString {
+            parts: vec![AstNode::StringLiteral("n = ".to_string()).into(), AstNode::Number(1).into()],
+        };
+        assert_eq!(checker.check(&node), Ok(Type::String));
+
+        let node_error = AstNode::FormatString {
+            parts: vec![AstNode::Identifier("xs".to_string()).into()],
+        };
+        assert!(checker.check(&node_error).unwrap_err().message.starts_with("Cannot interpolate"));
+        let undefined = AstNode::FormatString {
+            parts: vec![AstNode::Identifier("missing".to_string()).into()],
+        };
+        assert!(checker.check(&undefined).is_err());
+    }
+
+    #[test]
+    fn test_arithmetic_operators() {
+        let mut checker = TypeChecker::new();
+        let binary = |op, left: AstNode, right: AstNode| AstNode::BinaryOp {
+            op,
+            left: Box::new(left.into()),
+            right: Box::new(right.into()),
+        };
+        let sum = binary(BinaryOperator::Add, AstNode::Number(1), AstNode::Number(2));
+        assert_eq!(checker.check(&binary(BinaryOperator::Multiply, sum, AstNode::Number(3))), Ok(Type::I32));
+        assert_eq!(
+            checker.check(&binary(BinaryOperator::Divide, AstNode::Float(1.0), AstNode::Float(2.0))),
+            Ok(Type::F64)
+        );
+        assert_eq!(
+            checker
+                .check(&binary(BinaryOperator::Subtract, AstNode::Number(1), AstNode::Float(2.0)))
+                .map_err(|error| error.summary()),
+            Err("Arithmetic operator Subtract requires operands of the same type, got i32 and f64".to_string())
+        );
+        assert_eq!(
+            checker.check(&binary(
+                BinaryOperator::Multiply,
+                AstNode::StringLiteral("a".to_string()),
+                AstNode::Boolean(true)
+            )).map_err(|error| error.summary()),
+            Err("Arithmetic operator Multiply requires numeric operands, got string and bool".to_string())
+        );
+    }
+
+    #[test]
+    fn test_string_operations() {
+        assert!(check_source("fn f(a: string, b: string): string { a + b + \"!\" }").is_ok());
+        assert!(check_source("fn f(a: string): bool { a == \"x\" || a != \"y\" }").is_ok());
+        assert!(check_source("const GREETING: string = \"hello, \" + \"world\";").is_ok());
+        assert_eq!(
+            check_source("fn f(a: string): bool { a < \"b\" }"),
+            Err("Strings can only be compared with Eq and Ne, not Lt at line 1, column 25".to_string())
+        );
+        assert_eq!(
+            check_source("fn f(a: string, n: i32): string { a + n }"),
+            Err("Arithmetic operator Add requires numeric operands, got string and i32 at line 1, column 35\n\
+                 help: convert the number to a string first, as in `f\"{n}\"`"
+                .to_string())
+        );
+        assert_eq!(
+            check_source("fn f(a: string): bool { 1.5 == a }"),
+            Err("Cannot compare f64 with string at line 1, column 25\n\
+                 help: convert the number to a string first, as in `f\"{n}\"`"
+                .to_string())
+        );
+        assert_eq!(
+            check_source("fn f(a: string): string { a - a }"),
+            Err("Arithmetic operator Subtract requires numeric operands, got string and string at line 1, column 27"
+                .to_string())
+        );
+    }
+
+    #[test]
+    fn test_integer_literal_widths() {
+        assert!(check_source("let x: i8 = -128; let y: u32 = 4294967295;").is_ok());
+        assert_eq!(
+            check_source("let x: i8 = 300;"),
+            Err("Integer literal 300 does not fit in i8, whose values range from -128 to 127 at line 1, column 13".to_string())
+        );
+        assert_eq!(
+            check_source("let x: i16 = 32768;"),
+            Err("Integer literal 32768 does not fit in i16, whose values range from -32768 to 32767 at line 1, column 14"
+                .to_string())
+        );
+        assert_eq!(
+            check_source("let x: u8 = -1;"),
+            Err("Integer literal -1 does not fit in u8, whose values range from 0 to 255 at line 1, column 13".to_string())
+        );
+        assert!(check_source("fn f(a: i64): i64 { let mut b: u16 = 1; b = 2; a * 2 + f(3) } fn g(): u8 { return 255; }").is_ok());
+        assert_eq!(
+            check_source("fn f(a: i8): bool { a < 1000 }"),
+            Err("Integer literal 1000 does not fit in i8, whose values range from -128 to 127 at line 1, column 25".to_string())
+        );
+        // without an expected type a literal is an i32
+        assert_eq!(
+            check_source("fn f(a: i64): i64 { let b = 1; a + b }"),
+            Err("Arithmetic operator Add requires operands of the same type, got i64 and i32 at line 1, column 32".to_string())
+        );
+        assert!(check_source("let x = 2147483647; let y = -2147483648; let z: i64 = 5000000000;").is_ok());
+        assert_eq!(
+            check_source("let x = 5000000000;"),
+            Err("Integer literal 5000000000 does not fit in i32, whose values range from -2147483648 to 2147483647 \
+                 at line 1, column 9".to_string())
+        );
+        assert_eq!(
+            check_source("let x = 2147483648;"),
+            Err("Integer literal 2147483648 does not fit in i32, whose values range from -2147483648 to 2147483647 \
+                 at line 1, column 9".to_string())
+        );
+        assert_eq!(
+            check_source("let x = -2147483649;"),
+            Err("Integer literal -2147483649 does not fit in i32, whose values range from -2147483648 to 2147483647 \
+                 at line 1, column 9".to_string())
+        );
+    }
+
+    #[test]
+    fn test_float_typing() {
+        assert!(check_source("fn f(a: f32): f32 { let b: f32 = -0.5; a * b + 2.0 }").is_ok());
+        assert!(check_source("fn f(a: f32, b: f32): bool { a < b || a == 1.0 }").is_ok());
+        assert_eq!(
+            check_source("fn f(): f64 { let a = 1.0; a + 1 }"),
+            Err("Arithmetic operator Add requires operands of the same type, got f64 and i32 at line 1, column 28".to_string())
+        );
+        assert_eq!(
+            check_source("fn f(a: f32): f32 { let b = 2.0; a * b }"),
+            Err("Arithmetic operator Multiply requires operands of the same type, got f32 and f64 at line 1, column 34".to_string())
+        );
+        assert_eq!(
+            check_source("fn f(a: f64): bool { a > 1 }"),
+            Err("Cannot compare f64 with i32 at line 1, column 22".to_string())
+        );
+        assert_eq!(check_source("let x: i32 = 1.5;"), Err("Type mismatch: expected i32, got f64 at line 1, column 1".to_string()));
+    }
+
+    #[test]
+    fn test_power_operator() {
+        let mut checker = TypeChecker::new();
+        let power = |left: AstNode, right: AstNode| AstNode::BinaryOp {
+            op: BinaryOperator::Power,
+            left: Box::new(left.into()),
+            right: Box::new(right.into()),
+        };
+        assert_eq!(checker.check(&power(AstNode::Number(2), AstNode::Number(3))), Ok(Type::I32));
+        assert_eq!(checker.check(&power(AstNode::Float(2.0), AstNode::Number(3))), Ok(Type::F64));
+        assert_eq!(checker.check(&power(AstNode::Float(2.0), AstNode::Float(0.5))), Ok(Type::F64));
+        assert_eq!(
+            checker.check(&power(AstNode::Number(2), AstNode::Float(0.5))).map_err(|error| error.summary()),
+            Err("Cannot raise i32 to the power of f64".to_string())
+        );
+
+        // literals take the types around them
+        assert_eq!(check_source("fn f(x: f32): f32 { x ** 0.5 }"), Ok(Type::Void));
+        assert_eq!(check_source("fn f(x: f32): f32 { 2.0 ** x }"), Ok(Type::Void));
+        assert_eq!(check_source("fn f(n: u8): u8 { 2 ** n }"), Ok(Type::Void));
+        assert_eq!(check_source("let x: u64 = 2 ** 40;"), Ok(Type::Void));
+        assert_eq!(check_source("fn f(x: f32, n: i32): f32 { x ** n }"), Ok(Type::Void));
+    }
+
+    #[test]
+    fn test_type_alias() {
+        let mut checker = TypeChecker::new();
+        let alias = |name: &str, target: Type| AstNode::TypeAlias { name: name.to_string(), target, doc: None };
+        checker.check(&alias("Meters", Type::I32)).unwrap();
+        checker.check(&alias("Path", Type::array(Type::Struct("Meters".to_string()), 2))).unwrap();
+
+        let node = AstNode::Let {
+            name: "p".to_string(),
+            mutable: false,
+            type_annotation: Some(Type::Struct("Path".to_string())),
+            value: Box::new(AstNode::ArrayLiteral(vec![AstNode::Number(1).into(), AstNode::Number(2).into()]).into()),
+        };
+        assert_eq!(checker.check(&node), Ok(Type::array(Type::I32, 2)));
+        // the alias is equal to its target, but errors still name it
+        let meters = |value: AstNode| AstNode::Let {
+            name: "m".to_string(),
+            mutable: false,
+            type_annotation: Some(Type::Struct("Meters".to_string())),
+            value: Box::new(value.into()),
+        };
+        assert_eq!(checker.check(&meters(AstNode::Number(3))), Ok(Type::I32));
+        assert_eq!(
+            checker.check(&meters(AstNode::Boolean(true))).map_err(|error| error.summary()),
+            Err("Type mismatch: expected Meters (alias of i32), got bool".to_string())
+        );
+
+        assert_eq!(
+            checker.check(&alias("Meters", Type::F64)).map_err(|error| error.summary()),
+            Err("Type Meters is already defined".to_string())
+        );
+        assert_eq!(
+            checker.check(&alias("Loop", Type::Struct("Loop".to_string()))).map_err(|error| error.summary()),
+            Err("Unknown type: Loop".to_string())
+        );
+    }
+
+    #[test]
+    fn test_if_condition_must_be_bool() {
+        let mut checker = TypeChecker::new();
+        let node = AstNode::If {
+            cond: Box::new(AstNode::Boolean(true).into()),
+            then_block: Box::new(AstNode::Program(vec![]).into()),
+            else_block: Some(Box::new(AstNode::Program(vec![]).into())),
+        };
+        assert_eq!(checker.check(&node), Ok(Type::Void));
+
+        let node_error = AstNode::If {
+            cond: Box::new(AstNode::Number(1).into()),
+            then_block: Box::new(AstNode::Program(vec![]).into()),
+            else_block: None,
+        };
+        assert_eq!(
+            checker.check(&node_error).map_err(|error| error.summary()),
+            Err("Expected bool, found i32 — Nova has no truthiness\nhelp: compare it against zero, as in `n != 0`".to_string())
+        );
+
+        assert_eq!(
+            check_source("fn f(s: string): i32 { while s { } 0 }"),
+            Err("Expected bool, found string — Nova has no truthiness at line 1, column 30".to_string())
+        );
+        assert_eq!(
+            check_source("fn f(x: Option<u8>): u8 { if x then 1 else 0 }"),
+            Err("Expected bool, found Option<u8> — Nova has no truthiness at line 1, column 30\n\
+                 help: match on it to check whether it holds a value"
+                .to_string())
+        );
+    }
+
+    #[test]
+    fn test_if_expression_branch_types() {
+        let mut checker = TypeChecker::new();
+        let node = AstNode::If {
+            cond: Box::new(AstNode::Boolean(true).into()),
+            then_block: Box::new(AstNode::Program(vec![AstNode::Number(1).into()]).into()),
+            else_block: Some(Box::new(AstNode::Program(vec![AstNode::Number(2).into()]).into())),
+        };
+        assert_eq!(checker.check(&node), Ok(Type::I32));
+
+        let node_error = AstNode::If {
+            cond: Box::new(AstNode::Boolean(true).into()),
+            then_block: Box::new(AstNode::Program(vec![AstNode::Number(1).into()]).into()),
+            else_block: Some(Box::new(AstNode::Program(vec![AstNode::Boolean(false).into()]).into())),
+        };
+        assert!(checker.check(&node_error).is_err());
+    }
+
+    #[test]
+    fn test_conditional_expression() {
+        let mut checker = TypeChecker::new();
+        let conditional = |then_value: AstNode, else_value: AstNode| AstNode::Conditional {
+            cond: Box::new(AstNode::Boolean(true).into()),
+            then_value: Box::new(then_value.into()),
+            else_value: Box::new(else_value.into()),
+        };
+        assert_eq!(checker.check(&conditional(AstNode::Number(1), AstNode::Number(2))), Ok(Type::I32));
+        assert_eq!(
+            checker.check(&conditional(AstNode::Number(1), AstNode::Float(2.0))).map_err(|error| error.summary()),
+            Err("Conditional branches have mismatched types: i32 and f64".to_string())
+        );
+    }
+
+    #[test]
+    fn test_discarded_result_warns() {
+        let mut checker = TypeChecker::new();
+        let statement = |expr: AstNode| AstNode::ExpressionStatement(Box::new(Spanned::new(expr, Span {
+            start: 4,
+            end: 9,
+            line: 2,
+            column: 5,
+        })));
+        checker.check(&statement(AstNode::Number(1))).unwrap();
+        checker.check(&statement(AstNode::Program(vec![]))).unwrap();
+        assert_eq!(
+            checker.diagnostics().iter().map(Diagnostic::summary).collect::<Vec<_>>(),
+            ["Unused result of type i32; use `let _ = ...` to discard it at line 2, column 5"]
+        );
+    }
+
+    #[test]
+    fn test_unused_bindings_warn() {
+        let source = "fn helper(a: i32, _b: i32): i32 { let x = 1; let _y = 2; let z = a; z }\nfn main(): i32 { 0 }";
+        let ast = crate::parser::Parser::from_source(source).unwrap().parse().unwrap();
+        let mut checker = TypeChecker::new();
+        checker.check(&ast).unwrap();
+        assert_eq!(
+            checker.diagnostics().iter().map(Diagnostic::summary).collect::<Vec<_>>(),
+            [
+                "Unused variable `x`; prefix it with an underscore to silence this warning at line 1, column 35",
+                "Function `helper` is never called at line 1, column 1",
+            ]
+        );
+        assert!(!checker.has_errors());
+
+        let mut checker = TypeChecker::new();
+        checker.deny_warnings();
+        let ast = crate::parser::Parser::from_source("fn main(): i32 { let f = |n: i32| 0; f(1) }").unwrap().parse().unwrap();
+        checker.check(&ast).unwrap();
+        assert_eq!(
+            checker.diagnostics().iter().map(ToString::to_string).collect::<Vec<_>>(),
+            ["error: Unused parameter `n`; prefix it with an underscore to silence this warning at line 1, column 26"]
+        );
+        assert!(checker.has_errors());
+    }
+
+    #[test]
+    fn test_block_tail_types() {
+        let mut checker = TypeChecker::new();
+        let tail = AstNode::Program(vec![
+            AstNode::Let {
+                name: "a".to_string(),
+                mutable: false,
+                type_annotation: None,
+                value: Box::new(AstNode::Float(1.0).into()),
+            }.into(),
+            AstNode::Program(vec![AstNode::Identifier("a".to_string()).into()]).into(),
+        ]);
+        assert_eq!(checker.check(&tail), Ok(Type::F64));
+
+        let statement = AstNode::Program(vec![AstNode::ExpressionStatement(Box::new(AstNode::Number(1).into())).into()]);
+        assert_eq!(checker.check(&statement), Ok(Type::Void));
+    }
+
+    #[test]
+    fn test_break_outside_loop() {
+        let mut checker = TypeChecker::new();
+        let node = AstNode::While {
+            cond: Box::new(AstNode::Boolean(true).into()),
+            body: Box::new(AstNode::Program(vec![AstNode::Break.into()]).into()),
+        };
+        assert_eq!(checker.check(&node), Ok(Type::Void));
+        assert_eq!(
+            checker.check(&AstNode::Continue).map_err(|error| error.summary()),
+            Err("'continue' outside of a loop".to_string())
+        );
+    }
+
+    #[test]
+    fn test_assignment() {
+        let mut checker = TypeChecker::new();
+        let declare = AstNode::Let {
+            name: "x".to_string(),
+            mutable: true,
+            type_annotation: None,
+            value: Box::new(AstNode::Number(1).into()),
+        };
+        assert!(checker.check(&declare).is_ok());
+
+        let assign = AstNode::Assign {
+            name: "x".to_string(),
+            value: Box::new(AstNode::Number(2).into()),
+        };
+        assert_eq!(checker.check(&assign), Ok(Type::Void));
+
+        let wrong_type = AstNode::Assign {
+            name: "x".to_string(),
+            value: Box::new(AstNode::Boolean(true).into()),
+        };
+        assert!(checker.check(&wrong_type).is_err());
+
+        let undefined = AstNode::Assign {
+            name: "y".to_string(),
+            value: Box::new(AstNode::Number(2).into()),
+        };
+        assert_eq!(
+            checker.check(&undefined).map_err(|error| error.summary()),
+            Err("Cannot assign to undefined variable: y".to_string())
+        );
+    }
+
+    #[test]
+    fn test_array_typing() {
+        let mut checker = TypeChecker::new();
+        let literal = AstNode::ArrayLiteral(vec![AstNode::Number(1).into(), AstNode::Number(2).into()]);
+        assert_eq!(checker.check(&literal), Ok(Type::array(Type::I32, 2)));
+
+        let index = AstNode::Index {
+            array: Box::new(literal.into()),
+            index: Box::new(AstNode::Number(0).into()),
+        };
+        assert_eq!(checker.check(&index), Ok(Type::I32));
+
+        let mixed = AstNode::ArrayLiteral(vec![AstNode::Number(1).into(), AstNode::Boolean(true).into()]);
+        assert!(checker.check(&mixed).is_err());
+
+        // elements take the element type expected of the array, or that of
+        // the first element
+        assert!(check_source("let a: [i64; 2] = [1, 2];").is_ok());
+        assert!(check_source("let a: [u8; 0] = [];").is_ok());
+        assert!(check_source("fn f(x: u8): u8 { let a = [x, 2]; a[1] }").is_ok());
+        assert_eq!(
+            check_source("let a: [u8; 2] = [1, 300];"),
+            Err("Integer literal 300 does not fit in u8, whose values range from 0 to 255 at line 1, column 22".to_string())
+        );
+    }
+
+    #[test]
+    fn test_constant_index_bounds() {
+        let check = |body: &str| check_source(&format!("const LAST: i32 = 3;\nfn f(i: i32): i32 {{ let a: [i32; 4] = [1, 2, 3, 4]; {} }}", body));
+        assert!(check("a[0] + a[LAST]").is_ok());
+        // an index only known at run time is left to the program
+        assert!(check("a[i + 10]").is_ok());
+        assert_eq!(
+            check("a[10]"),
+            Err("Index 10 is out of bounds for an array of type [i32; 4] at line 2, column 55".to_string())
+        );
+        assert_eq!(
+            check("a[LAST + 1]"),
+            Err("Index 4 is out of bounds for an array of type [i32; 4] at line 2, column 55".to_string())
+        );
+        assert_eq!(
+            check("a[-1]"),
+            Err("Index -1 is out of bounds for an array of type [i32; 4] at line 2, column 55".to_string())
+        );
+        assert_eq!(
+            check("let b: [i32; 2] = a; 0"),
+            Err("Type mismatch: expected [i32; 2], got [i32; 4] at line 2, column 53".to_string())
+        );
+    }
+
+    #[test]
+    fn test_struct_typing() {
+        let mut checker = TypeChecker::new();
+        let def = AstNode::StructDef {
+            name: "Point".to_string(),
+            fields: vec![("x".to_string(), Type::I32), ("y".to_string(), Type::Bool)],
+            doc: None,
+        };
+        assert_eq!(checker.check(&def), Ok(Type::Void));
+
+        let literal = AstNode::StructLiteral {
+            name: "Point".to_string(),
+            fields: vec![("y".to_string(), AstNode::Boolean(true).into()), ("x".to_string(), AstNode::Number(1).into())],
+        };
+        let access = AstNode::FieldAccess { object: Box::new(literal.into()), field: "y".to_string() };
+        assert_eq!(checker.check(&access), Ok(Type::Bool));
+
+        let missing = AstNode::StructLiteral {
+            name: "Point".to_string(),
+            fields: vec![("x".to_string(), AstNode::Number(1).into())],
+        };
+        assert_eq!(
+            checker.check(&missing).map_err(|error| error.summary()),
+            Err("Missing field 'y' in Point literal".to_string())
+        );
+    }
+
+    #[test]
+    fn test_struct_declarations_and_literals() {
+        let point = "struct Point { x: i8, y: f32 }\n";
+        assert!(check_source(&format!("{}fn f(): f32 {{ let p = Point {{ y: 1.5, x: 2 }}; p.y }}", point)).is_ok());
+        assert_eq!(
+            check_source(&format!("{}fn f(): i8 {{ let p = Point {{ x: 2, y: true }}; p.x }}", point)),
+            Err("Type mismatch in field 'y' of Point: expected f32, got bool at line 2, column 39".to_string())
+        );
+        assert_eq!(
+            check_source(&format!("{}fn f(): i8 {{ Point {{ x: 300, y: 1.0 }}.x }}", point)),
+            Err("Integer literal 300 does not fit in i8, whose values range from -128 to 127 at line 2, column 25".to_string())
+        );
+        assert_eq!(
+            check_source(&format!("{}fn f(): i8 {{ Point {{ x: 1, y: 1.0 }}.z }}", point)),
+            Err("Struct Point has no field 'z' at line 2, column 14".to_string())
+        );
+        assert_eq!(check_source(&format!("{}struct Point {{ x: i8 }}", point)), Err("Type Point is already defined at line 2, column 1".to_string()));
+        assert_eq!(check_source("struct Path { points: [Point; 2] }"), Err("Unknown type: Point at line 1, column 1".to_string()));
+    }
+
+    #[test]
+    fn test_field_access_checks_definition() {
+        let mut checker = TypeChecker::new();
+        checker.check(&AstNode::StructDef {
+            name: "Point".to_string(),
+            fields: vec![("x".to_string(), Type::F64)],
+            doc: None,
+        }).unwrap();
+        checker.check(&AstNode::Let {
+            name: "p".to_string(),
+            mutable: false,
+            type_annotation: None,
+            value: Box::new(AstNode::StructLiteral {
+                name: "Point".to_string(),
+                fields: vec![("x".to_string(), AstNode::Float(1.5).into())],
+            }.into()),
+        }).unwrap();
+
+        let access = |object: AstNode, field: &str| AstNode::FieldAccess { object: Box::new(object.into()), field: field.to_string() };
+        let p = || AstNode::Identifier("p".to_string());
+        assert_eq!(checker.check(&access(p(), "x")), Ok(Type::F64));
+        assert_eq!(
+            checker.check(&access(p(), "z")).map_err(|error| error.summary()),
+            Err("Struct Point has no field 'z'".to_string())
+        );
+        assert!(checker.check(&access(AstNode::Number(1), "x")).is_err());
+    }
+
+    #[test]
+    fn test_enum_typing() {
+        let mut checker = TypeChecker::new();
+        let def = AstNode::EnumDef {
+            name: "Color".to_string(),
+            variants: vec![("Red".to_string(), vec![]), ("Green".to_string(), vec![])],
+            doc: None,
+        };
+        assert_eq!(checker.check(&def), Ok(Type::Void));
+
+        let variant = |name: &str| AstNode::EnumVariant {
+            enum_name: "Color".to_string(),
+            variant: name.to_string(),
+            args: vec![],
+        };
+        assert_eq!(checker.check(&variant("Green")), Ok(Type::Enum("Color".to_string())));
+        assert_eq!(
+            checker.check(&variant("Blue")).map_err(|error| error.summary()),
+            Err("Enum Color has no variant 'Blue'".to_string())
+        );
+    }
+
+    #[test]
+    fn test_match_exhaustiveness() {
+        let shape = "enum Shape { Empty, Circle(f32), Rect(i8, i8) }\n";
+        assert!(check_source(&format!(
+            "{}fn area(s: Shape): f32 {{ match s {{ Shape::Circle(r) => r * r, Shape::Empty => 0.0, Shape::Rect(_, _) => 1.0 }} }}",
+            shape
+        ))
+        .is_ok());
+        assert_eq!(
+            check_source(&format!("{}fn f(s: Shape): i32 {{ match s {{ Shape::Circle(_) => 1 }} }}", shape)),
+            Err("Non-exhaustive match on Shape: missing Shape::Empty, Shape::Rect at line 2, column 23".to_string())
+        );
+        assert!(check_source("fn f(b: bool): i32 { match b { true => 1, false => 0 } }").is_ok());
+        assert_eq!(
+            check_source("fn f(b: bool): i32 { match b { true => 1 } }"),
+            Err("Non-exhaustive match on bool: missing false at line 1, column 22".to_string())
+        );
+        assert_eq!(
+            check_source("fn f(n: i32): i32 { match n { 0 => 1, 1 => 2 } }"),
+            Err("Non-exhaustive match on i32; add a `_` arm at line 1, column 21".to_string())
+        );
+        assert!(check_source("fn f(n: i32): i32 { match n { 0 => 1, _ => n } }").is_ok());
+    }
+
+    #[test]
+    fn test_match_arms_and_patterns() {
+        let shape = "enum Shape { Empty, Rect(i8, i8) }\n";
+        assert_eq!(
+            check_source(&format!("{}fn f(s: Shape): i8 {{ match s {{ Shape::Rect(w, h) => w * h, _ => true }} }}", shape)),
+            Err("Match arms have mismatched types: i8 and bool at line 2, column 65".to_string())
+        );
+        assert_eq!(
+            check_source(&format!("{}fn f(s: Shape): i8 {{ match s {{ Shape::Rect(w) => w, _ => 0 }} }}", shape)),
+            Err("Variant Shape::Rect takes 2 payload values, but the pattern binds 1 at line 2, column 32".to_string())
+        );
+        assert_eq!(
+            check_source("fn f(n: i32): i32 { match n { 'a' => 1, _ => 0 } }"),
+            Err("Literal pattern cannot match a value of type i32 at line 1, column 31".to_string())
+        );
+
+        let ast = crate::parser::Parser::from_source(&format!(
+            "{}fn main(): i32 {{ let s = Shape::Empty; match s {{ Shape::Empty => 0, Shape::Empty => 1, _ => 2, _ => 3 }} }}",
+            shape
+        ))
+        .unwrap()
+        .parse()
+        .unwrap();
+        let mut checker = TypeChecker::new();
+        checker.check(&ast).unwrap();
+        assert_eq!(
+            checker.diagnostics().iter().map(Diagnostic::summary).collect::<Vec<_>>(),
+            [
+                "Unreachable match arm at line 2, column 69",
+                "Unreachable match arm at line 2, column 96",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_option_checking() {
+        let find = "fn find(xs: [i32; 3], x: i32): Option<i32> { \
+                    if xs[0] == x { return Option::Some(0); } \
+                    if xs[1] == x then Option::Some(1) else Option::None }\n";
+        assert!(check_source(&format!(
+            "{}fn f(): i32 {{ match find([1, 2, 3], 2) {{ Option::Some(i) => i, Option::None => -1 }} }}",
+            find
+        ))
+        .is_ok());
+        assert!(check_source(&format!("{}fn f(): i32 {{ find([1, 2, 3], 2).unwrap() + 1 }}", find)).is_ok());
+        // the value cannot be used without checking for it first
+        assert_eq!(
+            check_source(&format!("{}fn f(): i32 {{ let i: i32 = find([1, 2, 3], 2); i }}", find)),
+            Err("Type mismatch: expected i32, got Option<i32> at line 2, column 15".to_string())
+        );
+        assert_eq!(
+            check_source(&format!("{}fn f(): i32 {{ match find([1, 2, 3], 2) {{ Option::Some(i) => i }} }}", find)),
+            Err("Non-exhaustive match on Option<i32>: missing Option::None at line 2, column 15".to_string())
+        );
+        assert_eq!(
+            check_source("fn f(): i32 { let x = Option::None; 0 }"),
+            Err("Cannot infer the type of Option::None; annotate it, as in `let x: Option<i32> = Option::None;` \
+                 at line 1, column 23"
+                .to_string())
+        );
+        assert_eq!(
+            check_source("fn f(x: Option<bool>): i32 { match x { Option::Some(_, _) => 1, _ => 0 } }"),
+            Err("Variant Option::Some takes 1 payload values, but the pattern binds 2 at line 1, column 40".to_string())
+        );
+        assert_eq!(
+            check_source("fn f(): i32 { let x: Option<u8> = Option::Some(300); 0 }"),
+            Err("Integer literal 300 does not fit in u8, whose values range from 0 to 255 at line 1, column 48".to_string())
+        );
+        assert_eq!(check_source("enum Option { A }"), Err("Type Option is already defined at line 1, column 1".to_string()));
+    }
+
+    #[test]
+    fn test_enum_payloads() {
+        let shape = "enum Shape { Empty, Circle(f32), Rect(i8, i8) }\n";
+        assert!(check_source(&format!("{}fn f(): Shape {{ let e = Shape::Empty; Shape::Rect(1, -2) }}", shape)).is_ok());
+        // `.` names a variant as well as `::` does
+        assert!(check_source(&format!("{}fn f(): Shape {{ let _e = Shape.Empty; Shape.Rect(1, -2) }}", shape)).is_ok());
+        assert_eq!(check_source("fn f(): Option<u8> { Option.Some(3) }"), Ok(Type::Void));
+        assert_eq!(
+            check_source(&format!("{}fn f(): Shape {{ Shape.Circle(true) }}", shape)),
+            Err("Type mismatch in payload 1 of Shape::Circle: expected f32, got bool at line 2, column 30".to_string())
+        );
+        assert_eq!(
+            check_source(&format!("{}fn f(): Shape {{ Shape::Circle(1) }}", shape)),
+            Err("Type mismatch in payload 1 of Shape::Circle: expected f32, got i32 at line 2, column 31".to_string())
+        );
+        assert_eq!(
+            check_source(&format!("{}fn f(): Shape {{ Shape::Rect(1) }}", shape)),
+            Err("Variant Shape::Rect takes 2 payload values, got 1 at line 2, column 17".to_string())
+        );
+        assert_eq!(
+            check_source(&format!("{}fn f(): Shape {{ Shape::Empty(1) }}", shape)),
+            Err("Variant Shape::Empty takes 0 payload values, got 1 at line 2, column 17".to_string())
+        );
+        assert_eq!(check_source("enum E { A(Missing) }"), Err("Unknown type: Missing at line 1, column 1".to_string()));
+    }
+
+    #[test]
+    fn test_method_call_resolves_to_function() {
+        let mut checker = TypeChecker::new();
+        checker.check(&AstNode::Function {
+            name: "len".to_string(),
+            type_params: vec![],
+            params: vec![("s".to_string(), Type::String)],
+            return_type: Type::I32,
+            body: Box::new(AstNode::Program(vec![AstNode::Return(Some(Box::new(AstNode::Number(0).into()))).into()]).into()),
+            doc: None,
+        }).unwrap();
+
+        let call = |receiver: AstNode| AstNode::MethodCall {
+            receiver: Box::new(receiver.into()),
+            method: "len".to_string(),
+            args: vec![],
+        };
+        assert_eq!(checker.check(&call(AstNode::StringLiteral("hi".to_string()))), Ok(Type::I32));
+        assert_eq!(
+            checker.check(&call(AstNode::Number(1))).map_err(|error| error.summary()),
+            Err("No method 'len' found for i32".to_string())
+        );
+    }
+
+    #[test]
+    fn test_function_calls() {
+        // `body` is the tail of a second function, on line 2 from column 18
+        let check = |body: &str| check_source(&format!("fn add(a: i32, b: f64): f64 {{ b }}\nfn main(): f64 {{ {} }}", body));
+        assert_eq!(check("add(1, 2.0)"), Ok(Type::Void));
+        assert_eq!(check("1.5.add(2.0)"), Err("No method 'add' found for f64 at line 2, column 18".to_string()));
+        assert_eq!(
+            check("add(1, true)"),
+            Err("Type mismatch in argument 2 of add: expected f64, got bool at line 2, column 25".to_string())
+        );
+        assert_eq!(check("add(1)"), Err("Function add expects 2 arguments, got 1 at line 2, column 18".to_string()));
+        assert_eq!(
+            check("let x = 1; x()"),
+            Err("'x' is not a function, it has type i32 at line 2, column 29".to_string())
+        );
+        assert_eq!(check("nope()"), Err("Undefined function: nope at line 2, column 18".to_string()));
+    }
+
+    #[test]
+    fn test_print_builtins() {
+        assert_eq!(check_source("fn main(): void { println(\"n = \", 1, 2.5, true, 'c'); print(); }"), Ok(Type::Void));
+        assert_eq!(
+            check_source("fn main(): void { let a = [1]; println(1, a) }"),
+            Err("Cannot print argument 2 of println, a value of type [i32; 1] at line 1, column 43".to_string())
+        );
+        // a declared function takes the place of the built-in
+        assert_eq!(
+            check_source("fn print(n: i32): void {}\nfn main(): void { print(\"hi\") }"),
+            Err("Type mismatch in argument 1 of print: expected i32, got string at line 2, column 25".to_string())
+        );
+    }
+
+    #[test]
+    fn test_closure_typing() {
+        let mut checker = TypeChecker::new();
+        checker.env.insert("x".to_string(), Type::Bool);
+        let closure = AstNode::Closure {
+            params: vec![("x".to_string(), Type::I32)],
+            return_type: None,
+            body: Box::new(AstNode::UnaryOp {
+                op: UnaryOperator::Negate,
+                operand: Box::new(AstNode::Identifier("x".to_string()).into()),
+            }.into()),
+        };
+        assert_eq!(checker.check(&closure), Ok(Type::function(vec![Type::I32], Type::I32)));
+        // the outer `x` is visible again once the closure ends
+        assert_eq!(checker.check(&AstNode::Identifier("x".to_string())), Ok(Type::Bool));
+
+        // a declared return type is compared once its aliases are resolved
+        let check = |body: &str| check_source(&format!("type M = i32;\nfn main(): i32 {{ {}; 0 }}", body));
+        assert_eq!(check("let f = |x: i32| -> M x"), Ok(Type::Void));
+        assert_eq!(
+            check("let f = |x: bool| -> M x"),
+            Err("Type mismatch: expected M (alias of i32), got bool at line 2, column 26".to_string())
+        );
+
+        // a closure sees its own bindings and the program's items, but not
+        // the locals of the function around it
+        let check = |body: &str| check_source(&format!(
+            "const STEP: i32 = 2;\nfn double(x: i32): i32 {{ x * 2 }}\nfn main(): i32 {{ let n = 1; {}; n }}",
+            body
+        ));
+        assert_eq!(check("let _f = |x: i32| { let y = x; y + STEP + double(y) }"), Ok(Type::Void));
+        assert_eq!(check("let _f = |n: i32| n + 1"), Ok(Type::Void));
+        let captured = |name: &str, column: usize| Err(format!(
+            "Closures cannot capture variables yet, but this one uses `{}` from outside it at line 3, \
+             column {}\nhelp: pass `{}` to the closure as a parameter",
+            name, column, name
+        ));
+        assert_eq!(check("let _f = |x: i32| x + n"), captured("n", 51));
+        // nor may a closure use the parameters of the closure around it
+        assert_eq!(check("let _f = |x: i32| |y: i32| y + x"), captured("x", 60));
+    }
+
+    #[test]
+    fn test_assign_to_immutable_binding() {
+        let mut checker = TypeChecker::new();
+        checker.check(&AstNode::Let {
+            name: "x".to_string(),
+            mutable: false,
+            type_annotation: None,
+            value: Box::new(AstNode::Number(1).into()),
+        }).unwrap();
+
+        let assign = AstNode::Assign {
+            name: "x".to_string(),
+            value: Box::new(AstNode::Number(2).into()),
+        };
+        assert_eq!(
+            checker.check(&assign).map_err(|error| error.summary()),
+            Err("Cannot assign twice to immutable variable x\nhelp: declare it with `let mut x`".to_string())
+        );
+
+        assert_eq!(
+            check_source("fn main(): i32 {\n    let x = 1;\n    x = 2;\n    x\n}"),
+            Err("Cannot assign twice to immutable variable x at line 3, column 5\n\
+                 note: x was declared without `mut` at line 2, column 5\n\
+                 help: declare it with `let mut x`"
+                .to_string())
+        );
+    }
+
+    #[test]
+    fn test_const_requires_constant_expression() {
+        let mut checker = TypeChecker::new();
+        let constant = |name: &str, value: AstNode| AstNode::Const {
+            name: name.to_string(),
+            type_annotation: Type::I32,
+            value: Box::new(value.into()),
+            doc: None,
+        };
+        assert_eq!(checker.check(&constant("MAX", AstNode::Number(100))), Ok(Type::Void));
+        assert_eq!(checker.check(&constant("MIN", AstNode::UnaryOp {
+            op: UnaryOperator::Negate,
+            operand: Box::new(AstNode::Identifier("MAX".to_string()).into()),
+        })), Ok(Type::Void));
+
+        let call = AstNode::Call { callee: "f".to_string(), args: vec![] };
+        assert_eq!(
+            checker.check(&constant("BAD", call)).map_err(|error| error.summary()),
+            Err("The value of constant BAD must be a constant expression".to_string())
+        );
+    }
+
+    #[test]
+    fn test_constant_operations_are_folded() {
+        assert!(check_source("const N: i32 = 6 * 7; const M: i32 = N / 2;").is_ok());
+        assert_eq!(
+            check_source("const N: i32 = 0; fn f(): i32 { 1 / N }"),
+            Err("Division by zero in constant expression at line 1, column 33".to_string())
+        );
+        assert_eq!(
+            check_source("const N: i8 = 100; fn f(): i8 { N * 2 }"),
+            Err("Constant expression overflows i8: 200 at line 1, column 33".to_string())
+        );
+        assert_eq!(
+            check_source("fn f(): i32 { 65536 * 65536 }"),
+            Err("Constant expression overflows i32: 4294967296 at line 1, column 15".to_string())
+        );
+        assert!(check_source("type Byte = u8; const X: f64 = 1 as f64; const B: Byte = 300 as Byte; const C: u8 = B + 1;").is_ok());
+        assert_eq!(
+            check_source("const T: f64 = 2.5; fn f(): i32 { 10 / (T as i32 - 2) }"),
+            Err("Division by zero in constant expression at line 1, column 35".to_string())
+        );
+        // values only known at run time are left alone
+        assert!(check_source("fn f(n: i32): i32 { 1 / n }").is_ok());
+    }
+
+    #[test]
+    fn test_suggests_similar_names() {
+        assert_eq!(edit_distance("cout", "count"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+
+        assert_eq!(
+            check_source("fn f(): i32 { let count = 1; cout }"),
+            Err("Undefined variable: cout; did you mean `count`? at line 1, column 30".to_string())
+        );
+        assert_eq!(
+            check_source("fn length(): i32 { lenght() }"),
+            Err("Undefined function: lenght; did you mean `length`? at line 1, column 20".to_string())
+        );
+        assert_eq!(
+            check_source("fn f(): i32 { let total = 1; totl = 2; 0 }"),
+            Err("Cannot assign to undefined variable: totl; did you mean `total`? at line 1, column 30".to_string())
+        );
+        assert_eq!(
+            check_source("fn f(): i32 { let count = 1; width }"),
+            Err("Undefined variable: width at line 1, column 30".to_string())
+        );
+    }
+
+    #[test]
+    fn test_errors_point_at_source() {
+        let source = "fn main(): i32 {\n    let x = 1;\n    return -y;\n}";
+        let ast = crate::parser::Parser::new(crate::lexer::tokenize(source).unwrap()).parse().unwrap();
+        let mut checker = TypeChecker::new();
+        assert_eq!(
+            checker.check(&ast).map_err(|error| error.summary()),
+            Err("Undefined variable: y at line 3, column 13".to_string())
+        );
+    }
+
+    #[test]
+    fn test_return_matches_declared_type() {
+        assert!(check_source("fn main(): i32 { return 1; }").is_ok());
+        assert!(check_source("fn main(): i32 { 1 }").is_ok());
+        assert!(check_source("fn main(): void { return; }").is_ok());
+        assert_eq!(
+            check_source("fn main(): i32 { return \"hi\"; }"),
+            Err("Function main is declared to return i32, but returns string at line 1, column 18".to_string())
+        );
+        assert_eq!(
+            check_source("fn f(): i32 { if true { return; } 0 }"),
+            Err("Function f is declared to return i32, but returns void at line 1, column 25".to_string())
+        );
+        assert!(check_source("fn f(): f64 { 1 }").unwrap_err().starts_with("Function f is declared to return f64, but returns i32"));
+        assert_eq!(
+            check_source("type Name = string; fn f(): Name { return 1; }"),
+            Err("Function f is declared to return Name (alias of string), but returns i32 at line 1, column 36".to_string())
+        );
+        assert!(check_source("type Meters = i32; fn f(m: Meters): i32 { m } fn main(): i32 { f(2) }").is_ok());
+        // a closure's `return` belongs to the closure, not the function around it
+        assert!(check_source("fn f(): i32 { let g = |x: bool| { return x; }; 0 }").is_ok());
+
+        // every path through the body must end in a value or a `return`
+        assert!(check_source("fn f(b: bool): i32 { if b { return 1; } else { return 2; } }").is_ok());
+        assert!(check_source("fn f(n: i32): i32 { while true { if n > 0 { return n; } } }").is_ok());
+        assert!(check_source("fn f(): void { }").is_ok());
+        let missing = "Function f can reach the end of its body without returning a value of type i32 at line 1, column 1\n\
+                       help: end the body with a value or a `return`";
+        assert_eq!(check_source("fn f(): i32 { }"), Err(missing.to_string()));
+        assert_eq!(check_source("fn f(b: bool): i32 { if b { return 1; } }"), Err(missing.to_string()));
+        assert_eq!(check_source("fn f(): i32 { while true { break; } }"), Err(missing.to_string()));
+    }
+
+    #[test]
+    fn test_recursive_functions() {
+        assert!(check_source("fn fact(n: i32): i32 { if n <= 1 { return 1; } n * fact(n - 1) } fn main(): i32 { fact(5) }").is_ok());
+        // the body sees the declared signature, not one inferred from the body
+        assert_eq!(
+            check_source("fn fact(n: i32): i32 { if n <= 1 { return 1; } fact(true) }"),
+            Err("Type mismatch in argument 1 of fact: expected i32, got bool at line 1, column 53".to_string())
+        );
+    }
+
+    #[test]
+    fn test_items_are_declared_before_use() {
+        let source = "fn main(): i32 { later(LIMIT).x }\nfn later(n: i32): Point { Point { x: n } }\n\
+                      fn unused(): i32 { 0 }\nconst LIMIT: i32 = 3;\nstruct Point { x: i32 }";
+        let ast = crate::parser::Parser::from_source(source).unwrap().parse().unwrap();
+        let mut checker = TypeChecker::new();
+        assert_eq!(checker.check(&ast).map_err(|error| error.summary()), Ok(Type::Void));
+        // a function is only watched once, even though it is declared twice
+        assert_eq!(
+            checker.diagnostics().iter().map(Diagnostic::summary).collect::<Vec<_>>(),
+            ["Function `unused` is never called at line 3, column 1"]
+        );
+
+        assert_eq!(
+            check_source("fn main(): i32 { later(true) } fn later(n: i32): i32 { n }"),
+            Err("Type mismatch in argument 1 of later: expected i32, got bool at line 1, column 24".to_string())
+        );
+    }
+
+    #[test]
+    fn test_generic_calls_are_instantiated() {
+        let source = "fn id<T>(x: T): T { x }\nfn first<T, U>(a: T, b: U): T { id(a) }\n\
+                      fn main(): i32 { let b: bool = id(true); let c = first(1, 'c'); id(2) + first(c, b) }";
+        let ast = crate::parser::Parser::from_source(source).unwrap().parse().unwrap();
+        let mut checker = TypeChecker::new();
+        assert_eq!(checker.check(&ast), Ok(Type::Void));
+        let instantiations: Vec<String> = checker.instantiations().iter().map(ToString::to_string).collect();
+        assert_eq!(instantiations, ["id::<bool>", "first::<i32, char>", "id::<i32>", "first::<i32, bool>"]);
+
+        assert_eq!(
+            check_source("fn same<T>(a: T, b: T): bool { true } fn main(): i32 { same(1, true); 0 }"),
+            Err("Type mismatch in argument 2 of same: expected i32, got bool at line 1, column 64".to_string())
+        );
+        assert_eq!(
+            check_source("fn none<T>(): i32 { 0 } fn main(): i32 { none() }"),
+            Err("Cannot infer type parameter T of none from its arguments at line 1, column 42".to_string())
+        );
+    }
+
+    #[test]
+    fn test_traits_and_bounds() {
+        let show = "trait Show { fn show(self): string; fn same(self, other: Self): bool; }\nstruct Point { x: i32 }\n";
+        let point = "impl Show for Point { fn show(self): string { \"p\" } fn same(self, other: Point): bool { self.x == other.x } }\n";
+        assert!(check_source(&format!(
+            "{}{}fn display<T: Show>(x: T): string {{ if x.same(x) {{ x.show() }} else {{ \"\" }} }}\n\
+             fn main(): i32 {{ let p = Point {{ x: 1 }}; let s: string = display(p); let t = p.show(); 0 }}",
+            show, point
+        ))
+        .is_ok());
+        assert_eq!(
+            check_source(&format!("{}{}fn display<T: Show>(x: T): string {{ x.show() }}\nfn main(): i32 {{ display(1); 0 }}", show, point)),
+            Err("Type i32 does not implement Show, required by type parameter T of display at line 5, column 18".to_string())
+        );
+        assert_eq!(
+            check_source(&format!("{}fn display<T>(x: T): string {{ x.show() }}", show)),
+            Err("No method 'show' found for T at line 3, column 31".to_string())
+        );
+        assert_eq!(
+            check_source(&format!("{}fn display<T: Shw>(x: T): string {{ \"\" }}", show)),
+            Err("Unknown trait: Shw at line 3, column 1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_impls_match_their_trait() {
+        let show = "trait Show { fn show(self): string; }\nstruct Point { x: i32 }\n";
+        assert_eq!(
+            check_source(&format!("{}impl Show for Point {{}}", show)),
+            Err("Impl of Show for Point is missing method show at line 3, column 1".to_string())
+        );
+        assert_eq!(
+            check_source(&format!("{}impl Show for Point {{ fn show(self): i32 {{ 0 }} }}", show)),
+            Err("Method show of Show for Point has type fn(Point) -> i32, but the trait declares fn(Point) -> string \
+                 at line 3, column 1"
+                .to_string())
+        );
+        assert_eq!(
+            check_source(&format!("{}impl Show for Point {{ fn show(self): string {{ \"\" }} fn hide(self): void {{}} }}", show)),
+            Err("Method hide is not a member of trait Show at line 3, column 1".to_string())
+        );
+        assert_eq!(
+            check_source(&format!("{}impl Show for Point {{ fn show(self): string {{ 1 }} }}", show)),
+            Err("Function show is declared to return string, but returns i32 at line 3, column 23".to_string())
+        );
+        let twice = format!("{}impl Show for Point {{ fn show(self): string {{ \"\" }} }}\n", show);
+        assert_eq!(
+            check_source(&format!("{}{}", twice, twice.lines().last().unwrap())),
+            Err("Type Point already implements Show at line 4, column 1".to_string())
+        );
+        assert_eq!(check_source("impl Show for i32 {}"), Err("Unknown trait: Show at line 1, column 1".to_string()));
+    }
+
+    #[test]
+    fn test_entry_point_signature() {
+        let check = |source: &str| {
+            let ast = crate::parser::Parser::from_source(source).unwrap().parse().unwrap();
+            let mut checker = TypeChecker::new();
+            checker.check(&ast).and_then(|_| checker.check_entry_point(&ast)).map_err(|error| error.summary())
+        };
+        assert!(check("fn main(): i32 { 0 }").is_ok());
+        assert!(check("fn main(): void {}").is_ok());
+        assert_eq!(check("fn start(): i32 { 0 }"), Err("The program has no main function".to_string()));
+        assert_eq!(
+            check("fn main(x: i32): string { \"\" }"),
+            Err("The main function must take no parameters, found (i32) at line 1, column 1".to_string())
+        );
+        assert_eq!(
+            check("fn main(argc: i32, argv: [string; 2]): i32 { argc }"),
+            Err("The main function must take no parameters, found (i32, [string; 2]) at line 1, column 1".to_string())
+        );
+        assert_eq!(
+            check("\nfn main(): string { \"\" }"),
+            Err("The main function must return i32 or void, found string at line 2, column 1".to_string())
+        );
+        assert_eq!(
+            check("fn main<T>(): i32 { 0 }"),
+            Err("The main function cannot be generic at line 1, column 1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_function_parameters_in_scope() {
+        let mut checker = TypeChecker::new();
+        let source = "let n = true; fn fact(n: i32): i32 { fact(n) } fn twice(n: i32, s: string): i32 { let t = s; fact(n) }";
+        let AstNode::Program(items) = crate::parser::Parser::from_source(source).unwrap().parse().unwrap() else {
+            panic!("Expected program node");
+        };
+        // checked one by one, so that the items stay in the global scope
+        for item in &items {
+            checker.check_node(item).unwrap();
+        }
+        assert_eq!(
+            checker.env.get("twice"),
+            Some(&Type::function(vec![Type::I32, Type::String], Type::I32))
+        );
+        // parameters do not outlive the body
+        assert_eq!(checker.env.get("n"), Some(&Type::Bool));
+        assert_eq!(checker.env.get("s"), None);
+    }
+
+    #[test]
+    fn test_expressions_are_annotated() {
+        let source = "fn f(): f64 { let x = 1; let y = 2.0 ** x; y }";
+        let ast = crate::parser::Parser::from_source(source).unwrap().parse().unwrap();
+        TypeChecker::new().check(&ast).unwrap();
+
+        let AstNode::Program(items) = &ast else { panic!("Expected program node") };
+        let AstNode::Function { body, .. } = &items[0].node else { panic!("Expected function") };
+        let AstNode::Program(statements) = &body.node else { panic!("Expected function body") };
+        let AstNode::Let { value, .. } = &statements[1].node else { panic!("Expected let statement") };
+        assert_eq!(value.ty(), Some(Type::F64));
+        let AstNode::BinaryOp { right, .. } = &value.node else { panic!("Expected binary operation") };
+        assert_eq!(right.ty(), Some(Type::I32));
+        // statements carry no type of their own
+        assert_eq!(statements[1].ty(), None);
+        assert_eq!(body.ty(), Some(Type::F64));
+    }
+
+    #[test]
+    fn test_constant_expressions_are_folded() {
+        let source = "const N: i32 = 4;\nfn f(m: i32): i32 { let x = N * 2 + 1; let y = m + N; x + y }";
+        let ast = crate::parser::Parser::from_source(source).unwrap().parse().unwrap();
+        TypeChecker::new().check(&ast).unwrap();
+
+        let AstNode::Program(items) = &ast else { panic!("Expected program node") };
+        let AstNode::Function { body, .. } = &items[1].node else { panic!("Expected function") };
+        let AstNode::Program(statements) = &body.node else { panic!("Expected function body") };
+        let AstNode::Let { value, .. } = &statements[0].node else { panic!("Expected let statement") };
+        assert_eq!(value.folded(), Some(ConstValue::Int(9)));
+        // only the constant side of an operation on a variable is known
+        let AstNode::Let { value, .. } = &statements[1].node else { panic!("Expected let statement") };
+        let AstNode::BinaryOp { left, right, .. } = &value.node else { panic!("Expected binary operation") };
+        assert_eq!((value.folded(), left.folded(), right.folded()), (None, None, Some(ConstValue::Int(4))));
+    }
+
+    #[test]
+    fn test_block_scopes() {
+        assert_eq!(
+            check_source("fn f(): i32 { { let a = 1; } a }"),
+            Err("Undefined variable: a at line 1, column 30".to_string())
+        );
+        assert_eq!(check_source("fn f(): i32 { let a = true; { let a = 1; a } }"), Ok(Type::Void));
+        assert!(check_source("fn f(): bool { let a = true; { let a = 1; } a }").is_ok());
+        assert_eq!(
+            check_source("fn f(): i32 { let x = 1; 0 } fn g(): i32 { x }"),
+            Err("Undefined variable: x at line 1, column 44".to_string())
+        );
+    }
+
+    #[test]
+    fn test_structured_diagnostics() {
+        let source = "fn main(): i32 {\n    let x = 1;\n    x = 2;\n    x\n}";
+        let ast = crate::parser::Parser::from_source(source).unwrap().parse().unwrap();
+        let error = TypeChecker::new().check(&ast).unwrap_err();
+        assert_eq!(error.severity, Severity::Error);
+        assert_eq!(error.code, code::IMMUTABLE_ASSIGNMENT);
+        assert_eq!(error.message, "Cannot assign twice to immutable variable x");
+        assert_eq!((error.span.line, error.span.column), (3, 5));
+        assert_eq!(error.notes.len(), 2);
+        assert_eq!(
+            error.render(source),
+            "error[immutable-assignment]: Cannot assign twice to immutable variable x\n\
+             \x20--> line 3, column 5\n  |\n3 |     x = 2;\n  |     ^^^^^^\n\
+             \x20 = note: x was declared without `mut` at line 2, column 5\n\
+             \x20 = help: declare it with `let mut x`"
+        );
+
+        let ast = crate::parser::Parser::from_source("fn f(): i32 { 1 + 2; 0 }").unwrap().parse().unwrap();
+        let mut checker = TypeChecker::new();
+        assert_eq!(checker.check(&ast).map_err(|error| error.summary()), Ok(Type::Void));
+        let codes: Vec<&str> = checker.diagnostics().iter().map(|diagnostic| diagnostic.code).collect();
+        assert_eq!(codes, [code::UNUSED_RESULT, code::UNUSED]);
+
+        // a constant folding error points at the operation that failed
+        let ast = crate::parser::Parser::from_source("const X: i32 = 1 / 0;").unwrap().parse().unwrap();
+        let error = TypeChecker::new().check(&ast);
+        assert_eq!(error.map_err(|error| (error.code, error.span.column)), Err((code::CONSTANT_EVALUATION, 16)));
+        // nodes built outside the parser have no position to quote
+        assert_eq!(
+            Diagnostic::error(code::UNKNOWN_NAME, "Undefined variable: y").render(""),
+            "error: Undefined variable: y"
+        );
     }
 }
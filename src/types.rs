@@ -1,19 +1,63 @@
+use crate::lexer::Span;
+
 #[allow(dead_code)]
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Type {
-    Int,
-    Float,
+    I8,
+    I16,
+    I32,
+    I64,
+    U8,
+    U16,
+    U32,
+    U64,
+    F32,
+    F64,
     Bool,
+    Char,
     String,
     Void,
+    Array(Box<Type>, usize),
+    Struct(String),
+    /// A type parameter such as `T` in `fn id<T>(x: T): T`.
+    Param(String),
+    Enum(String),
+    /// `Option<T>`: a `T`, or no value at all. It is the only type that can
+    /// be empty, and its value is reached only by a match or `unwrap()`.
+    Option(Box<Type>),
     Function {
         params: Vec<Type>,
         return_type: Box<Type>,
     },
 }
 
+/// Every primitive type with its name in Nova source, the one place that
+/// spelling is decided. Each name but `char` is also a lexer keyword.
+const PRIMITIVES: [(&str, Type); 14] = [
+    ("i8", Type::I8),
+    ("i16", Type::I16),
+    ("i32", Type::I32),
+    ("i64", Type::I64),
+    ("u8", Type::U8),
+    ("u16", Type::U16),
+    ("u32", Type::U32),
+    ("u64", Type::U64),
+    ("f32", Type::F32),
+    ("f64", Type::F64),
+    ("bool", Type::Bool),
+    ("char", Type::Char),
+    ("string", Type::String),
+    ("void", Type::Void),
+];
+
 #[allow(dead_code)]
 impl Type {
+    /// The primitive type spelled `name` in source, if any.
+    pub fn from_name(name: &str) -> Option<Type> {
+        PRIMITIVES.iter().find(|(spelling, _)| *spelling == name).map(|(_, type_)| type_.clone())
+    }
+
     pub fn function(params: Vec<Type>, return_type: Type) -> Self {
         Type::Function {
             params,
@@ -24,28 +68,360 @@ impl Type {
     pub fn void() -> Self {
         Type::Void
     }
+
+    pub fn array(element: Type, len: usize) -> Self {
+        Type::Array(Box::new(element), len)
+    }
+
+    pub fn option(inner: Type) -> Self {
+        Type::Option(Box::new(inner))
+    }
+
+    pub fn is_integer(&self) -> bool {
+        matches!(
+            self,
+            Type::I8 | Type::I16 | Type::I32 | Type::I64 | Type::U8 | Type::U16 | Type::U32 | Type::U64
+        )
+    }
+
+    pub fn is_unsigned(&self) -> bool {
+        matches!(self, Type::U8 | Type::U16 | Type::U32 | Type::U64)
+    }
+
+    /// The smallest and largest value of an integer type.
+    pub fn integer_range(&self) -> Option<(i128, i128)> {
+        let (bits, signed) = match self {
+            Type::I8 => (8, true),
+            Type::I16 => (16, true),
+            Type::I32 => (32, true),
+            Type::I64 => (64, true),
+            Type::U8 => (8, false),
+            Type::U16 => (16, false),
+            Type::U32 => (32, false),
+            Type::U64 => (64, false),
+            _ => return None,
+        };
+        Some(if signed { (-(1i128 << (bits - 1)), (1i128 << (bits - 1)) - 1) } else { (0, (1i128 << bits) - 1) })
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(self, Type::F32 | Type::F64)
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        self.is_integer() || self.is_float()
+    }
+
+    /// Matches `self`, which may mention type parameters, against the
+    /// concrete type `other`, binding each parameter the first time it is
+    /// met. Fails if the shapes differ or a parameter would need two types.
+    pub fn unify(&self, other: &Type, bindings: &mut std::collections::HashMap<String, Type>) -> bool {
+        match (self, other) {
+            (Type::Param(name), _) => match bindings.get(name) {
+                Some(bound) => bound == other,
+                None => {
+                    bindings.insert(name.clone(), other.clone());
+                    true
+                },
+            },
+            (Type::Array(element, len), Type::Array(other_element, other_len)) => {
+                len == other_len && element.unify(other_element, bindings)
+            },
+            (Type::Option(inner), Type::Option(other_inner)) => inner.unify(other_inner, bindings),
+            (
+                Type::Function { params, return_type },
+                Type::Function { params: other_params, return_type: other_return },
+            ) => {
+                params.len() == other_params.len()
+                    && params.iter().zip(other_params).all(|(param, other)| param.unify(other, bindings))
+                    && return_type.unify(other_return, bindings)
+            },
+            _ => self == other,
+        }
+    }
+
+    /// Replaces the bound type parameters in `self` by their types.
+    pub fn substitute(&self, bindings: &std::collections::HashMap<String, Type>) -> Type {
+        match self {
+            Type::Param(name) => bindings.get(name).cloned().unwrap_or_else(|| self.clone()),
+            Type::Array(element, len) => Type::array(element.substitute(bindings), *len),
+            Type::Option(inner) => Type::option(inner.substitute(bindings)),
+            Type::Function { params, return_type } => Type::function(
+                params.iter().map(|param| param.substitute(bindings)).collect(),
+                return_type.substitute(bindings),
+            ),
+            _ => self.clone(),
+        }
+    }
+
+    /// Whether `self` mentions a type parameter anywhere.
+    pub fn is_generic(&self) -> bool {
+        match self {
+            Type::Param(_) => true,
+            Type::Array(element, _) | Type::Option(element) => element.is_generic(),
+            Type::Function { params, return_type } => params.iter().any(Type::is_generic) || return_type.is_generic(),
+            _ => false,
+        }
+    }
+}
+
+/// Formats the type the way it is spelled in Nova source.
+impl std::fmt::Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Type::Array(element, len) => write!(f, "[{}; {}]", element, len),
+            Type::Option(inner) => write!(f, "Option<{}>", inner),
+            Type::Struct(name) | Type::Enum(name) | Type::Param(name) => write!(f, "{}", name),
+            Type::Function { params, return_type } => {
+                let params: Vec<String> = params.iter().map(Type::to_string).collect();
+                write!(f, "fn({}) -> {}", params.join(", "), return_type)
+            },
+            primitive => {
+                let (name, _) = PRIMITIVES.iter()
+                    .find(|(_, type_)| type_ == primitive)
+                    .expect("every other type is primitive");
+                write!(f, "{}", name)
+            },
+        }
+    }
+}
+
+/// What a binding watched by `TypeEnvironment::track_usage` names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BindingKind {
+    Variable,
+    Parameter,
+    Function,
+}
+
+/// The variables declared in one block, function body or closure body.
+#[derive(Debug, Default)]
+struct Scope {
+    symbols: std::collections::HashMap<String, Type>,
+    mutable: std::collections::HashSet<String>,
+    /// Where each binding was declared, for bindings from the parser.
+    spans: std::collections::HashMap<String, Span>,
+    /// Tracked bindings that have not been used yet, in declaration order.
+    unused: Vec<(String, BindingKind, Span)>,
+}
+
+/// An `impl Trait for Type` block, with the signatures of its methods.
+#[derive(Debug)]
+struct Impl {
+    trait_name: String,
+    target: Type,
+    methods: Vec<(String, Type)>,
 }
 
 #[allow(dead_code)]
 #[derive(Debug)]
 pub struct TypeEnvironment {
-    symbols: std::collections::HashMap<String, Type>,
+    /// Innermost last; the first scope holds the globals and is never popped.
+    scopes: Vec<Scope>,
+    structs: std::collections::HashMap<String, Vec<(String, Type)>>,
+    enums: std::collections::HashMap<String, Vec<(String, Vec<Type>)>>,
+    aliases: std::collections::HashMap<String, Type>,
+    /// Each trait's method signatures, with `self` as `Type::Param("Self")`.
+    traits: std::collections::HashMap<String, Vec<(String, Type)>>,
+    impls: Vec<Impl>,
+}
+
+impl Default for TypeEnvironment {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 #[allow(dead_code)]
 impl TypeEnvironment {
     pub fn new() -> Self {
         Self {
-            symbols: std::collections::HashMap::new(),
+            scopes: vec![Scope::default()],
+            structs: std::collections::HashMap::new(),
+            enums: std::collections::HashMap::new(),
+            aliases: std::collections::HashMap::new(),
+            traits: std::collections::HashMap::new(),
+            impls: Vec::new(),
         }
     }
 
+    /// Opens a scope whose bindings shadow those of the enclosing scopes.
+    pub fn push_scope(&mut self) {
+        self.scopes.push(Scope::default());
+    }
+
+    /// Drops every binding declared since the matching `push_scope`, and
+    /// returns the tracked ones among them that were never used.
+    pub fn pop_scope(&mut self) -> Vec<(String, BindingKind, Span)> {
+        assert!(self.scopes.len() > 1, "Cannot pop the global scope");
+        self.scopes.pop().map(|scope| scope.unused).unwrap_or_default()
+    }
+
     pub fn insert(&mut self, name: String, type_: Type) {
-        self.symbols.insert(name, type_);
+        self.declare(name, type_, false, Span::default());
     }
 
+    /// The type of the innermost binding of `name`.
     pub fn get(&self, name: &str) -> Option<&Type> {
-        self.symbols.get(name)
+        self.scope_of(name).map(|scope| &scope.symbols[name])
+    }
+
+    /// Declares a variable binding at `span` in the innermost scope,
+    /// replacing any earlier one of that name there and shadowing those
+    /// further out.
+    pub fn declare(&mut self, name: String, type_: Type, mutable: bool, span: Span) {
+        let scope = self.scopes.last_mut().expect("the global scope is never popped");
+        if mutable {
+            scope.mutable.insert(name.clone());
+        } else {
+            scope.mutable.remove(&name);
+        }
+        scope.spans.insert(name.clone(), span);
+        scope.symbols.insert(name, type_);
+    }
+
+    /// Watches the binding just declared as `name`, so that `pop_scope`
+    /// reports it unless `mark_used` is called for it first.
+    pub fn track_usage(&mut self, name: String, kind: BindingKind, span: Span) {
+        let scope = self.scopes.last_mut().expect("the global scope is never popped");
+        scope.unused.push((name, kind, span));
+    }
+
+    /// Every name bound in any open scope, for suggestions.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.scopes.iter().flat_map(|scope| scope.symbols.keys().map(String::as_str))
+    }
+
+    /// Records a use of the innermost binding of `name`.
+    pub fn mark_used(&mut self, name: &str) {
+        let Some(scope) = self.scopes.iter_mut().rev().find(|scope| scope.symbols.contains_key(name)) else {
+            return;
+        };
+        // a name declared twice in one scope refers to the later binding
+        if let Some(index) = scope.unused.iter().rposition(|(unused, ..)| unused == name) {
+            scope.unused.remove(index);
+        }
+    }
+
+    pub fn is_mutable(&self, name: &str) -> bool {
+        self.scope_of(name).is_some_and(|scope| scope.mutable.contains(name))
+    }
+
+    /// Where the innermost binding of `name` was declared.
+    pub fn declaration_span(&self, name: &str) -> Option<Span> {
+        self.scope_of(name).map(|scope| scope.spans[name])
+    }
+
+    /// How many scopes are open, the global one included.
+    pub fn depth(&self) -> usize {
+        self.scopes.len()
+    }
+
+    /// The position of the scope holding the innermost binding of `name`,
+    /// counting the global scope as 0.
+    pub fn depth_of(&self, name: &str) -> Option<usize> {
+        self.scopes.iter().rposition(|scope| scope.symbols.contains_key(name))
+    }
+
+    fn scope_of(&self, name: &str) -> Option<&Scope> {
+        self.scopes.iter().rev().find(|scope| scope.symbols.contains_key(name))
+    }
+
+    /// Registers a struct's fields, in declaration order.
+    pub fn define_struct(&mut self, name: String, fields: Vec<(String, Type)>) {
+        self.structs.insert(name, fields);
+    }
+
+    pub fn get_struct(&self, name: &str) -> Option<&[(String, Type)]> {
+        self.structs.get(name).map(Vec::as_slice)
+    }
+
+    /// Registers an enum's variants with their payload types; a variant's
+    /// tag is its position.
+    pub fn define_enum(&mut self, name: String, variants: Vec<(String, Vec<Type>)>) {
+        self.enums.insert(name, variants);
+    }
+
+    pub fn get_enum(&self, name: &str) -> Option<&[(String, Vec<Type>)]> {
+        self.enums.get(name).map(Vec::as_slice)
+    }
+
+    /// The variants of an enum type with their payload types; `Option<T>`
+    /// has `Some(T)` and `None`.
+    pub fn variants(&self, type_: &Type) -> Option<Vec<(String, Vec<Type>)>> {
+        match type_ {
+            Type::Enum(name) => self.get_enum(name).map(<[_]>::to_vec),
+            Type::Option(inner) => Some(vec![("Some".to_string(), vec![(**inner).clone()]), ("None".to_string(), vec![])]),
+            _ => None,
+        }
+    }
+
+    /// Registers `type name = target;`. The target should already be
+    /// resolved, so looking an alias up never has to chase further aliases.
+    pub fn define_alias(&mut self, name: String, target: Type) {
+        self.aliases.insert(name, target);
+    }
+
+    pub fn get_alias(&self, name: &str) -> Option<&Type> {
+        self.aliases.get(name)
+    }
+
+    /// Registers a trait's method signatures.
+    pub fn define_trait(&mut self, name: String, methods: Vec<(String, Type)>) {
+        self.traits.insert(name, methods);
+    }
+
+    pub fn get_trait(&self, name: &str) -> Option<&[(String, Type)]> {
+        self.traits.get(name).map(Vec::as_slice)
+    }
+
+    /// Records that `target` implements `trait_name` with the given method
+    /// signatures, in which `self` has the type `target`.
+    pub fn define_impl(&mut self, trait_name: String, target: Type, methods: Vec<(String, Type)>) {
+        self.impls.push(Impl { trait_name, target, methods });
+    }
+
+    pub fn implements(&self, target: &Type, trait_name: &str) -> bool {
+        self.impls.iter().any(|impl_| impl_.trait_name == trait_name && impl_.target == *target)
+    }
+
+    /// The signature of `method` from a trait that `target` implements. The
+    /// checker lets only one impl for a type define a given method, so a
+    /// call can be dispatched statically.
+    pub fn find_method(&self, target: &Type, method: &str) -> Option<&Type> {
+        self.impls.iter()
+            .filter(|impl_| impl_.target == *target)
+            .flat_map(|impl_| &impl_.methods)
+            .find(|(name, _)| name == method)
+            .map(|(_, signature)| signature)
+    }
+
+    /// Whether `name` is a primitive type or was declared as a struct, enum
+    /// or type alias.
+    pub fn is_defined_type(&self, name: &str) -> bool {
+        Type::from_name(name).is_some()
+            || name == "Option"
+            || self.structs.contains_key(name)
+            || self.enums.contains_key(name)
+            || self.aliases.contains_key(name)
+    }
+
+    /// The parser reads every named type as `Type::Struct`; this rewrites
+    /// the names that refer to enums, replaces aliases by their targets and
+    /// turns primitive names, as in trees not built by the parser, into the
+    /// primitive types.
+    pub fn resolve(&self, type_: &Type) -> Type {
+        match type_ {
+            Type::Struct(name) if self.enums.contains_key(name) => Type::Enum(name.clone()),
+            Type::Struct(name) if self.aliases.contains_key(name) => self.aliases[name].clone(),
+            Type::Struct(name) => Type::from_name(name).unwrap_or_else(|| type_.clone()),
+            Type::Function { params, return_type } => {
+                Type::function(params.iter().map(|param| self.resolve(param)).collect(), self.resolve(return_type))
+            },
+            Type::Array(element, len) => Type::Array(Box::new(self.resolve(element)), *len),
+            Type::Option(inner) => Type::option(self.resolve(inner)),
+            _ => type_.clone(),
+        }
     }
 }
 
@@ -56,19 +432,148 @@ mod tests {
     #[test]
     fn test_type_environment() {
         let mut env = TypeEnvironment::new();
-        env.insert("x".to_string(), Type::Int);
-        assert_eq!(env.get("x"), Some(&Type::Int));
+        env.insert("x".to_string(), Type::I32);
+        assert_eq!(env.get("x"), Some(&Type::I32));
+    }
+
+    #[test]
+    fn test_mutable_bindings() {
+        let mut env = TypeEnvironment::new();
+        env.declare("x".to_string(), Type::I32, true, Span::default());
+        assert!(env.is_mutable("x"));
+        let span = Span { line: 3, column: 5, ..Span::default() };
+        env.declare("x".to_string(), Type::I32, false, span);
+        assert!(!env.is_mutable("x"));
+        assert_eq!(env.declaration_span("x"), Some(span));
+    }
+
+    #[test]
+    fn test_scopes_shadow_outer_bindings() {
+        let mut env = TypeEnvironment::new();
+        env.declare("x".to_string(), Type::I32, true, Span::default());
+        env.push_scope();
+        assert_eq!(env.get("x"), Some(&Type::I32));
+        env.declare("x".to_string(), Type::Bool, false, Span::default());
+        env.insert("y".to_string(), Type::F64);
+        assert_eq!(env.get("x"), Some(&Type::Bool));
+        assert!(!env.is_mutable("x"));
+        env.pop_scope();
+        assert_eq!(env.get("x"), Some(&Type::I32));
+        assert!(env.is_mutable("x"));
+        assert_eq!(env.get("y"), None);
+    }
+
+    #[test]
+    fn test_unused_bindings() {
+        let mut env = TypeEnvironment::new();
+        env.push_scope();
+        for name in ["a", "b", "a"] {
+            env.insert(name.to_string(), Type::I32);
+            env.track_usage(name.to_string(), BindingKind::Variable, Span::default());
+        }
+        env.mark_used("a");
+        env.mark_used("b");
+        // the first `a` was shadowed before it was ever read
+        assert_eq!(env.pop_scope(), vec![("a".to_string(), BindingKind::Variable, Span::default())]);
+    }
+
+    #[test]
+    fn test_struct_registration() {
+        let mut env = TypeEnvironment::new();
+        env.define_struct("Point".to_string(), vec![("x".to_string(), Type::I32), ("y".to_string(), Type::I32)]);
+        assert_eq!(env.get_struct("Point").map(|fields| fields.len()), Some(2));
+        assert_eq!(env.get_struct("Line"), None);
+    }
+
+    #[test]
+    fn test_enum_resolution() {
+        let mut env = TypeEnvironment::new();
+        env.define_enum("Color".to_string(), vec![("Red".to_string(), vec![]), ("Green".to_string(), vec![])]);
+        assert_eq!(env.resolve(&Type::Struct("Color".to_string())), Type::Enum("Color".to_string()));
+        assert_eq!(env.resolve(&Type::Struct("Point".to_string())), Type::Struct("Point".to_string()));
+    }
+
+    #[test]
+    fn test_alias_resolution() {
+        let mut env = TypeEnvironment::new();
+        env.define_alias("Meters".to_string(), Type::I32);
+        assert!(env.is_defined_type("Meters"));
+        let meters = Type::Struct("Meters".to_string());
+        assert_eq!(env.resolve(&Type::array(meters.clone(), 2)), Type::array(Type::I32, 2));
+        assert_eq!(env.resolve(&Type::function(vec![meters], Type::Void)), Type::function(vec![Type::I32], Type::Void));
+    }
+
+    #[test]
+    fn test_integer_ranges() {
+        assert_eq!(Type::I8.integer_range(), Some((-128, 127)));
+        assert_eq!(Type::U16.integer_range(), Some((0, 65535)));
+        assert_eq!(Type::I32.integer_range(), Some((i32::MIN as i128, i32::MAX as i128)));
+        assert_eq!(Type::U64.integer_range(), Some((0, u64::MAX as i128)));
+        assert_eq!(Type::F64.integer_range(), None);
+    }
+
+    #[test]
+    fn test_type_display() {
+        assert_eq!(Type::I32.to_string(), "i32");
+        assert_eq!(Type::array(Type::U8, 4).to_string(), "[u8; 4]");
+        assert_eq!(Type::function(vec![Type::I32, Type::Bool], Type::F64).to_string(), "fn(i32, bool) -> f64");
+    }
+
+    #[test]
+    fn test_primitive_names_round_trip() {
+        for (name, primitive) in PRIMITIVES {
+            assert_eq!(Type::from_name(name), Some(primitive.clone()));
+            assert_eq!(primitive.to_string(), name);
+        }
+        assert_eq!(Type::from_name("int"), None);
+        let env = TypeEnvironment::new();
+        assert_eq!(env.resolve(&Type::array(Type::Struct("char".to_string()), 2)), Type::array(Type::Char, 2));
+        assert!(env.is_defined_type("bool"));
+    }
+
+    #[test]
+    fn test_option_type() {
+        let mut env = TypeEnvironment::new();
+        env.define_alias("Meters".to_string(), Type::I64);
+        let meters = env.resolve(&Type::option(Type::Struct("Meters".to_string())));
+        assert_eq!(meters, Type::option(Type::I64));
+        assert_eq!(meters.to_string(), "Option<i64>");
+        assert_eq!(
+            env.variants(&meters),
+            Some(vec![("Some".to_string(), vec![Type::I64]), ("None".to_string(), vec![])])
+        );
+        assert_eq!(env.variants(&Type::I64), None);
+        assert!(env.is_defined_type("Option"));
+
+        let mut bindings = std::collections::HashMap::new();
+        assert!(Type::option(Type::Param("T".to_string())).unify(&meters, &mut bindings));
+        assert_eq!(bindings["T"], Type::I64);
+        assert!(!Type::option(Type::I32).unify(&Type::I32, &mut bindings));
     }
 
     #[test]
     fn test_function_type() {
-        let fn_type = Type::function(vec![Type::Int, Type::Bool], Type::void());
+        let fn_type = Type::function(vec![Type::I32, Type::Bool], Type::void());
         match fn_type {
             Type::Function { params, return_type } => {
-                assert_eq!(params, vec![Type::Int, Type::Bool]);
+                assert_eq!(params, vec![Type::I32, Type::Bool]);
                 assert_eq!(*return_type, Type::Void);
             },
             _ => panic!("Expected function type"),
         }
     }
+
+    #[test]
+    fn test_unify_binds_type_params() {
+        let t = || Type::Param("T".to_string());
+        let mut bindings = std::collections::HashMap::new();
+        let pair = Type::function(vec![t(), Type::array(t(), 2)], t());
+        assert!(pair.unify(&Type::function(vec![Type::Bool, Type::array(Type::Bool, 2)], Type::Bool), &mut bindings));
+        assert_eq!(bindings.get("T"), Some(&Type::Bool));
+        assert_eq!(pair.substitute(&bindings), Type::function(vec![Type::Bool, Type::array(Type::Bool, 2)], Type::Bool));
+        // `T` is already bound to `bool`
+        assert!(!t().unify(&Type::I32, &mut bindings));
+        assert!(!Type::array(t(), 2).unify(&Type::array(Type::Bool, 3), &mut bindings));
+        assert!(pair.is_generic() && !Type::array(Type::I32, 1).is_generic());
+    }
 }
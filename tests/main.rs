@@ -1,22 +1,26 @@
-use nova_lang::{lexer::Token, parser::Parser, codegen::CodeGen, AstNode};
+use nova_lang::{lexer, parser::Parser, codegen::CodeGen, TypeChecker};
 use inkwell::context::Context;
 
 #[test]
 fn test_full_compilation() {
-    let source = r#"fn main() {
+    let source = r#"fn main(): i32 {
         let x: i32 = 42;
         return x;
     }"#;
 
     // Lexing
-    let mut lexer = Token::lexer(source);
-    let tokens: Vec<_> = lexer.collect();
-    assert!(tokens.len() > 0);
+    let tokens = lexer::tokenize(source).unwrap();
+    assert!(!tokens.is_empty());
 
     // Parsing
     let mut parser = Parser::new(tokens);
     let ast = parser.parse().unwrap();
 
+    // Type checking
+    let mut type_checker = TypeChecker::new();
+    type_checker.check(&ast).unwrap();
+    type_checker.check_entry_point(&ast).unwrap();
+
     // Code generation
     let context = Context::create();
     let mut codegen = CodeGen::new(&context);